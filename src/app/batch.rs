@@ -0,0 +1,143 @@
+use std::io::{self, Write};
+
+use super::session::{LineResult, Session, StateChange};
+
+// where lines came from, each source numbers its lines from 1
+#[derive(Clone, Copy)]
+pub enum Source<'a> {
+    Expressions,
+    File(&'a str),
+    Stdin,
+    Init,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Flow {
+    Continue,
+    Quit,
+    Failed,
+}
+
+// runs lines without the tui for -e, files and piped input. results go to out and
+// errors to err, stops at the first error or a quit
+pub fn run_lines(session: &mut Session, source: Source, lines: impl IntoIterator<Item = String>, out: &mut impl Write, err: &mut impl Write) -> io::Result<Flow> {
+    for (line_number, line) in lines.into_iter().enumerate() {
+        let mut report = Report {source, line_number: line_number + 1, out, err};
+        let result = session.execute(&line);
+        match report.write(&line, result)? {
+            Flow::Continue => {},
+            flow => return Ok(flow),
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+// the init script already ran, only its errors are shown so a broken one doesn't go unnoticed
+pub fn report_init(lines: Vec<(String, LineResult)>, err: &mut impl Write) -> io::Result<()> {
+    for (line_number, (line, result)) in lines.into_iter().enumerate() {
+        if let LineResult::Error {..} = result {
+            Report {source: Source::Init, line_number: line_number + 1, out: &mut io::sink(), err}.write(&line, result)?;
+        }
+    }
+    Ok(())
+}
+
+struct Report<'a, O, E> {
+    source: Source<'a>,
+    line_number: usize,
+    out: &'a mut O,
+    err: &'a mut E,
+}
+
+impl<O: Write, E: Write> Report<'_, O, E> {
+    fn write(&mut self, line: &str, result: LineResult) -> io::Result<Flow> {
+        match result {
+            LineResult::Value(value) => writeln!(self.out, "{}", value.as_string())?,
+            LineResult::Error {message, span} => {
+                let n = self.line_number;
+                match self.source {
+                    Source::Expressions => writeln!(self.err, "error in -e {n}: {message}")?,
+                    Source::File(path) => writeln!(self.err, "error on line {n} of {path}: {message}")?,
+                    Source::Stdin => writeln!(self.err, "error on line {n}: {message}")?,
+                    Source::Init => writeln!(self.err, "error on line {n} of the init script: {message}")?,
+                }
+                writeln!(self.err, "    {line}")?;
+                if let Some(underline) = span.and_then(|span| caret_line(line, span.start, span.end)) {
+                    writeln!(self.err, "    {underline}")?;
+                }
+                return Ok(Flow::Failed);
+            },
+            // whatever a command said goes to err so it doesn't mix with results
+            LineResult::Message(msg) => writeln!(self.err, "{msg}")?,
+            LineResult::Script(lines) | LineResult::StateChange(StateChange::Reload(lines)) => {
+                for (line, result) in lines {
+                    if let flow @ (Flow::Quit | Flow::Failed) = self.write(&line, result)? {
+                        return Ok(flow);
+                    }
+                }
+            },
+            LineResult::StateChange(StateChange::Quit) => return Ok(Flow::Quit),
+            LineResult::StateChange(_) | LineResult::Empty => {},
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+// tabs are kept so the carets line up however wide the terminal shows them
+fn caret_line(line: &str, start: usize, end: usize) -> Option<String> {
+    let padding: String = line.get(..start)?
+        .chars()
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = line.get(start..end)?.chars().count().max(1);
+    Some(padding + &"^".repeat(width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: Source, lines: &[&str]) -> (Flow, String, String) {
+        let mut session = Session::new();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let flow = run_lines(&mut session, source, lines.iter().map(|line| line.to_string()), &mut out, &mut err).unwrap();
+        (flow, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    #[test]
+    fn results_and_commands() {
+        let (flow, out, err) = run(Source::Stdin, &["def sq(x) = x^2", "a = sq(3)", "-- comment", "config trig deg", "std.sin(90) + a", "quit", "1"]);
+        assert_eq!(flow, Flow::Quit);
+        assert_eq!(out, "9\n10\n");
+        assert_eq!(err, "trig mode set to deg\n");
+    }
+    #[test]
+    fn stops_at_error() {
+        let (flow, out, err) = run(Source::Stdin, &["1", "2 * (3 + y)", "3"]);
+        assert_eq!(flow, Flow::Failed);
+        assert_eq!(out, "1\n");
+        assert_eq!(err, "error on line 2: unknown identifier: y\n    2 * (3 + y)\n             ^\n");
+    }
+    #[test]
+    fn failed_commands() {
+        for line in ["load nope", "config trig blah", "config nope", "show bogus", "panel nope", "restore", "use"] {
+            let (flow, _, err) = run(Source::Stdin, &[line, "1"]);
+            assert_eq!(flow, Flow::Failed, "{line}");
+            assert!(err.starts_with("error on line 1: "), "{line} gave {err}");
+        }
+        assert_eq!(run(Source::Stdin, &["config trig deg", "1"]).0, Flow::Continue);
+    }
+    #[test]
+    fn sources_number_their_own_lines() {
+        let (_, _, err) = run(Source::Expressions, &["1", "y"]);
+        assert!(err.starts_with("error in -e 2: unknown identifier: y\n"), "{err}");
+        let (_, _, err) = run(Source::File("calc.txt"), &["y"]);
+        assert!(err.starts_with("error on line 1 of calc.txt: unknown identifier: y\n"), "{err}");
+
+        let mut session = Session::new();
+        let lines = vec![("x = 1".to_string(), session.execute("x = 1")), ("2 * y".to_string(), session.execute("2 * y"))];
+        let mut err = Vec::new();
+        report_init(lines, &mut err).unwrap();
+        assert!(String::from_utf8(err).unwrap().starts_with("error on line 2 of the init script: "));
+    }
+}
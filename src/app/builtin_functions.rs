@@ -1,14 +1,16 @@
 use std::iter;
 
-use super::executor::{RuntimeError, Value};
+use super::{calculus, complex::Complex, environment::Environment, executor::{self, RuntimeError, Value}, linalg::{self, LuDecomposition}, state::FunctionBody};
+
+pub type BuiltinFn = fn(&Environment, Vec<Value>) -> Result<Value, RuntimeError>;
 
 // allow for row or column vectors but that seems kind of dumb so maybe clean up for just columns
-fn dot(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match (values.get(0), values.get(1)) {
+fn dot(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match (values.first(), values.get(1)) {
         (Some(val1), Some(val2)) => {
             match (val1, val2) {
                 (Value::Matrix(mat1), Value::Matrix(mat2)) => {
-                    if mat1.len() == 0 || mat2.len() == 0 {
+                    if mat1.is_empty() || mat2.is_empty() {
                         return Ok(Value::Number(0.0));
                     }
 
@@ -39,8 +41,8 @@ fn dot(values: Vec<Value>) -> Result<Value, RuntimeError> {
     }
 }
 
-fn cross(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    let (val1, val2) = match (values.get(0), values.get(1)) {
+fn cross(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (val1, val2) = match (values.first(), values.get(1)) {
         (Some(val1), Some(val2)) => (val1, val2),
         _ => return Err(RuntimeError::WrongNumFunctionArgs{
             fname: "cross".to_string(),
@@ -68,8 +70,8 @@ fn cross(values: Vec<Value>) -> Result<Value, RuntimeError> {
     ]))
 }
 
-fn unit(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    let mut matrix = match values.get(0) {
+fn unit(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let mut matrix = match values.first() {
         Some(Value::Matrix(input)) => input.clone(),
         _ => return Err(RuntimeError::BuiltinFuncErr("unit expects a vector".to_string())),
     };
@@ -91,8 +93,8 @@ fn unit(values: Vec<Value>) -> Result<Value, RuntimeError> {
     Ok(Value::Matrix(matrix))
 }
 
-fn magnitude(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    let matrix = match values.get(0) {
+fn magnitude(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = match values.first() {
         Some(Value::Matrix(input)) => input,
         _ => return Err(RuntimeError::BuiltinFuncErr("magnitude expects a vector".to_string())),
     };
@@ -110,239 +112,681 @@ fn magnitude(values: Vec<Value>) -> Result<Value, RuntimeError> {
     Ok(Value::Number(magnitude))
 }
 
-fn inv(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    let matrix = match values.get(0) {
-        Some(Value::Matrix(mat)) => mat,
-        _ => return Err(RuntimeError::BuiltinFuncErr("inv expects matrix".to_string())),
-    };
-
-    let rows = matrix.len();
-    let cols = matrix[0].len();
+fn inv(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("inv", &values)?;
+    match LuDecomposition::new(matrix).inverse() {
+        Some(inverse) => Ok(Value::Matrix(inverse)),
+        None => Err(RuntimeError::BuiltinFuncErr("matrix is not invertible".to_string())),
+    }
+}
 
-    if rows != cols {
-        return Err(RuntimeError::BuiltinFuncErr("inv requires square matrix".to_string()));
+fn transpose(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Matrix(mat)) => Ok(Value::Matrix(trans(mat))),
+        Some(Value::ComplexMatrix(mat)) => Ok(Value::ComplexMatrix(trans(mat))),
+        _ => Err(RuntimeError::BuiltinFuncErr("transpose expects a matrix".to_string())),
     }
+}
 
-    if rows == 1 {
-        return Ok(Value::Matrix(vec![vec![1.0/matrix[0][0]]]));
+fn det(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    if let Some(Value::Matrix(input)) = values.first() {
+        if input.is_empty() {
+            return Ok(Value::Number(0.0))
+        }
     }
+    let matrix = square_matrix_arg("det", &values)?;
+    Ok(Value::Number(LuDecomposition::new(matrix).det()))
+}
 
-    let det = det_recurse(matrix);
+// packed LU factors of PA with the unit diagonal of L left out
+fn lu(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("lu", &values)?;
+    Ok(Value::Matrix(LuDecomposition::new(matrix).packed))
+}
+
+fn lu_l(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("lu.l", &values)?;
+    Ok(Value::Matrix(LuDecomposition::new(matrix).l()))
+}
 
-    if det == 0.0 {
-        return Err(RuntimeError::BuiltinFuncErr("matrix is not invertible".to_string()));
+fn lu_u(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("lu.u", &values)?;
+    Ok(Value::Matrix(LuDecomposition::new(matrix).u()))
+}
+
+fn lu_p(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("lu.p", &values)?;
+    Ok(Value::Matrix(LuDecomposition::new(matrix).p()))
+}
+
+fn solve(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match (values.first(), values.get(1)) {
+        (Some(Value::Matrix(a)), Some(Value::Matrix(b))) => Ok(Value::Matrix(linalg::solve(a, b)?)),
+        _ => Err(RuntimeError::BuiltinFuncErr("solve expects a matrix and a vector or matrix".to_string())),
     }
+}
 
-    let mut adjoint = adjoint(matrix);
+fn expm(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("expm", &values)?;
+    match linalg::expm(matrix) {
+        Some(output) => Ok(Value::Matrix(output)),
+        None => Err(RuntimeError::BuiltinFuncErr("expm requires finite matrix entries".to_string())),
+    }
+}
 
-    for row in adjoint.iter_mut() {
-        for col in row.iter_mut() {
-            *col /= det;
-        }
+fn sqrtm(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("sqrtm", &values)?;
+    match linalg::sqrtm(matrix) {
+        Some(output) => Ok(Value::Matrix(output)),
+        None => Err(RuntimeError::BuiltinFuncErr(
+            "sqrtm did not converge, the matrix may be singular or have negative eigenvalues".to_string()
+        )),
     }
+}
 
-    Ok(Value::Matrix(adjoint))
+fn logm(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("logm", &values)?;
+    match linalg::logm(matrix) {
+        Some(output) => Ok(Value::Matrix(output)),
+        None => Err(RuntimeError::BuiltinFuncErr(
+            "logm did not converge, the matrix may be singular or have negative eigenvalues".to_string()
+        )),
+    }
 }
 
-fn transpose(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
-        Some(Value::Matrix(mat)) => Ok(Value::Matrix(trans(mat))),
-        _ => Err(RuntimeError::BuiltinFuncErr("transpose expects a matrix".to_string())),
+// eigenvectors are the columns of the output
+fn eig(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("eig", &values)?;
+    if linalg::is_symmetric(matrix) {
+        let (_, vectors) = linalg::symmetric_eigen(matrix);
+        return Ok(Value::Matrix(vectors));
     }
+    let eigenvalues = general_eigenvalues("eig", matrix)?;
+    Ok(Value::ComplexMatrix(linalg::eigenvectors(matrix, &eigenvalues)).normalized())
 }
 
-fn det(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    let matrix = match values.get(0) {
-        Some(Value::Matrix(input)) => input,
-        _ => return Err(RuntimeError::BuiltinFuncErr("det expects a matrix".to_string())),
-    };
+fn eigvals(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("eigvals", &values)?;
+    if linalg::is_symmetric(matrix) {
+        let (eigenvalues, _) = linalg::symmetric_eigen(matrix);
+        return Ok(Value::Matrix(eigenvalues.into_iter().map(|num| vec![num]).collect()));
+    }
+    let eigenvalues = general_eigenvalues("eigvals", matrix)?;
+    Ok(Value::ComplexMatrix(eigenvalues.into_iter().map(|num| vec![num]).collect()).normalized())
+}
 
-    if matrix.is_empty() {
-        return Ok(Value::Number(0.0))
+fn general_eigenvalues(fname: &str, matrix: &[Vec<f64>]) -> Result<Vec<Complex>, RuntimeError> {
+    linalg::eigenvalues(matrix).ok_or(RuntimeError::BuiltinFuncErr(
+        format!("{fname} did not converge, the matrix may have non finite entries")
+    ))
+}
+
+// singular values as a vector, the factors are available through std.svd.u/s/v
+fn svd(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = matrix_arg("svd", &values)?;
+    Ok(Value::Matrix(linalg::svd(matrix).s.into_iter().map(|num| vec![num]).collect()))
+}
+
+fn svd_u(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = matrix_arg("svd.u", &values)?;
+    Ok(Value::Matrix(linalg::svd(matrix).u))
+}
+
+fn svd_s(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = matrix_arg("svd.s", &values)?;
+    let singular_values = linalg::svd(matrix).s;
+    Ok(Value::Matrix(singular_values.iter()
+        .enumerate()
+        .map(|(i, num)| (0..singular_values.len()).map(|j| if i == j { *num } else { 0.0 }).collect())
+        .collect()))
+}
+
+fn svd_v(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = matrix_arg("svd.v", &values)?;
+    Ok(Value::Matrix(linalg::svd(matrix).v))
+}
+
+fn qr(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = matrix_arg("qr", &values)?;
+    Ok(Value::Matrix(linalg::qr(matrix).1))
+}
+
+fn qr_q(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = matrix_arg("qr.q", &values)?;
+    Ok(Value::Matrix(linalg::qr(matrix).0))
+}
+
+// upper triangular R with R'R = A
+fn chol(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let matrix = square_matrix_arg("chol", &values)?;
+    if !linalg::is_symmetric(matrix) {
+        return Err(RuntimeError::BuiltinFuncErr("chol requires a symmetric matrix".to_string()));
+    }
+    match linalg::cholesky(matrix) {
+        Some(r) => Ok(Value::Matrix(r)),
+        None => Err(RuntimeError::BuiltinFuncErr("chol requires a positive definite matrix".to_string())),
     }
+}
 
-    let rows = matrix.len();
-    let cols = matrix[0].len();
+fn matrix_arg<'a>(fname: &str, values: &'a [Value]) -> Result<&'a Vec<Vec<f64>>, RuntimeError> {
+    match values.first() {
+        Some(Value::Matrix(mat)) if !mat.is_empty() && !mat[0].is_empty() => Ok(mat),
+        Some(Value::ComplexMatrix(_)) => Err(RuntimeError::BuiltinFuncErr(format!("{fname} only supports real matrices"))),
+        _ => Err(RuntimeError::BuiltinFuncErr(format!("{fname} expects a matrix"))),
+    }
+}
 
+fn square_matrix_arg<'a>(fname: &str, values: &'a [Value]) -> Result<&'a Vec<Vec<f64>>, RuntimeError> {
+    let matrix = matrix_arg(fname, values)?;
+    let (rows, cols) = linalg::dims(matrix);
     if rows != cols {
-        return Err(RuntimeError::BuiltinFuncErr("det requires equal row and column length".to_string()));
+        return Err(RuntimeError::BuiltinFuncErr(format!("{fname} requires a square matrix, got {rows}x{cols}")));
     }
+    Ok(matrix)
+}
 
-    if rows == 1 {
-        return Ok(Value::Number(matrix[0][0]));
-    }
+fn rref(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (mut matrix, tolerance) = echelon_args("rref", &values)?;
+    row_echelon(&mut matrix, tolerance, true);
+    Ok(Value::Matrix(matrix))
+}
 
-    Ok(Value::Number(det_recurse(matrix)))
+fn row_echelon_form(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (mut matrix, tolerance) = echelon_args("ref", &values)?;
+    row_echelon(&mut matrix, tolerance, false);
+    Ok(Value::Matrix(matrix))
 }
 
-fn rref(_values: Vec<Value>) -> Result<Value, RuntimeError> {
-    todo!();
+// 1 indexed pivot columns as a vector
+fn pivots(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (mut matrix, tolerance) = echelon_args("pivots", &values)?;
+    let pivot_cols = row_echelon(&mut matrix, tolerance, false);
+    Ok(Value::Matrix(pivot_cols.into_iter().map(|col| vec![(col + 1) as f64]).collect()))
 }
 
-fn log(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    let num = match values.get(0) {
-        Some(Value::Number(num)) => num,
+fn rank(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let (mut matrix, tolerance) = echelon_args("rank", &values)?;
+    let pivot_cols = row_echelon(&mut matrix, tolerance, false);
+    Ok(Value::Number(pivot_cols.len() as f64))
+}
+
+// shared by the row echelon functions which take a matrix and an optional tolerance
+fn echelon_args(fname: &str, values: &[Value]) -> Result<(Vec<Vec<f64>>, Option<f64>), RuntimeError> {
+    let matrix = match values.first() {
+        Some(Value::Matrix(mat)) => mat.clone(),
+        _ => return Err(RuntimeError::BuiltinFuncErr(format!("{fname} expects a matrix"))),
+    };
+    let tolerance = match values.get(1) {
+        Some(Value::Number(tol)) if *tol >= 0.0 => Some(*tol),
+        Some(_) => return Err(RuntimeError::BuiltinFuncErr(format!("{fname} tolerance must be a positive number"))),
+        None => None,
+    };
+    Ok((matrix, tolerance))
+}
+
+fn log(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let num = match values.first() {
+        Some(Value::Number(num)) => Complex::from(*num),
+        Some(Value::Complex(num)) => *num,
         _ => return Err(RuntimeError::BuiltinFuncErr("log expects a number".to_string())),
     };
-    match values.get(1) {
-        Some(Value::Number(base)) => Ok(Value::Number(num.log(*base))),
-        _ => Ok(Value::Number(num.log10())),
-    }
+    let output = match values.get(1) {
+        Some(Value::Number(base)) if num.re >= 0.0 && num.is_real() => Value::Number(num.re.log(*base)),
+        Some(Value::Number(base)) => Value::Complex(num.ln() / Complex::from(*base).ln()),
+        Some(Value::Complex(base)) => Value::Complex(num.ln() / base.ln()),
+        _ if num.re >= 0.0 && num.is_real() => Value::Number(num.re.log10()),
+        _ => Value::Complex(num.ln() / Complex::from(10.0).ln()),
+    };
+    Ok(output.normalized())
 }
 
-fn ln(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
-        Some(Value::Number(input)) => Ok(Value::Number(input.ln())),
+fn ln(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) if *input >= 0.0 => Ok(Value::Number(input.ln())),
+        Some(Value::Number(input)) => Ok(Value::Complex(Complex::from(*input).ln())),
+        Some(Value::Complex(input)) => Ok(Value::Complex(input.ln()).normalized()),
         _ => Err(RuntimeError::BuiltinFuncErr("ln expects a number".to_string())),
     }
 }
 
-fn sin(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
-        Some(Value::Number(input)) => Ok(Value::Number(input.sin())),
+fn sqrt(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) if *input >= 0.0 => Ok(Value::Number(input.sqrt())),
+        Some(Value::Number(input)) => Ok(Value::Complex(Complex::from(*input).sqrt())),
+        Some(Value::Complex(input)) => Ok(Value::Complex(input.sqrt()).normalized()),
+        _ => Err(RuntimeError::BuiltinFuncErr("sqrt expects a number".to_string())),
+    }
+}
+
+fn sin(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) => Ok(Value::Number(env.angle_to_radians(*input).sin())),
+        Some(Value::Complex(input)) => Ok(Value::Complex(env.complex_to_radians(*input).sin()).normalized()),
         _ => Err(RuntimeError::BuiltinFuncErr("sin expects 1 number".to_string())),
     }
 }
 
-fn cos(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
-        Some(Value::Number(input)) => Ok(Value::Number(input.cos())),
+fn cos(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) => Ok(Value::Number(env.angle_to_radians(*input).cos())),
+        Some(Value::Complex(input)) => Ok(Value::Complex(env.complex_to_radians(*input).cos()).normalized()),
         _ => Err(RuntimeError::BuiltinFuncErr("cos expects 1 number".to_string())),
     }
 }
 
-fn tan(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
-        Some(Value::Number(input)) => Ok(Value::Number(input.tan())),
+fn tan(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) => Ok(Value::Number(env.angle_to_radians(*input).tan())),
+        Some(Value::Complex(input)) => Ok(Value::Complex(env.complex_to_radians(*input).tan()).normalized()),
         _ => Err(RuntimeError::BuiltinFuncErr("tan expects 1 number".to_string())),
     }
 }
 
-fn asin(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
-        Some(Value::Number(input)) => Ok(Value::Number(input.asin())),
+// inputs outside of [-1, 1] have complex results rather than NaN
+fn asin(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) if input.abs() <= 1.0 => Ok(Value::Number(env.angle_from_radians(input.asin()))),
+        Some(Value::Number(input)) => Ok(Value::Complex(env.complex_from_radians(Complex::from(*input).asin())).normalized()),
+        Some(Value::Complex(input)) => Ok(Value::Complex(env.complex_from_radians(input.asin())).normalized()),
         _ => Err(RuntimeError::BuiltinFuncErr("asin expects 1 number".to_string())),
     }
 }
 
-fn acos(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
-        Some(Value::Number(input)) => Ok(Value::Number(input.acos())),
+fn acos(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) if input.abs() <= 1.0 => Ok(Value::Number(env.angle_from_radians(input.acos()))),
+        Some(Value::Number(input)) => Ok(Value::Complex(env.complex_from_radians(Complex::from(*input).acos())).normalized()),
+        Some(Value::Complex(input)) => Ok(Value::Complex(env.complex_from_radians(input.acos())).normalized()),
         _ => Err(RuntimeError::BuiltinFuncErr("acos expects 1 number".to_string())),
     }
 }
 
-fn atan(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
-        Some(Value::Number(input)) => Ok(Value::Number(input.atan())),
+fn atan(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) => Ok(Value::Number(env.angle_from_radians(input.atan()))),
+        Some(Value::Complex(input)) => Ok(Value::Complex(env.complex_from_radians(input.atan())).normalized()),
         _ => Err(RuntimeError::BuiltinFuncErr("atan expects 1 number".to_string())),
     }
 }
 
-fn rad(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
+fn rad(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
         Some(Value::Number(input)) => Ok(Value::Number(input.to_radians())),
         _ => Err(RuntimeError::BuiltinFuncErr("rad expects 1 number".to_string())),
     }
 }
 
-fn deg(values: Vec<Value>) -> Result<Value, RuntimeError> {
-    match values.get(0) {
+fn deg(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
         Some(Value::Number(input)) => Ok(Value::Number(input.to_degrees())),
         _ => Err(RuntimeError::BuiltinFuncErr("deg expects 1 number".to_string())),
     }
 }
 
-// -- matrix helper methods ---------------------------------------------------
+fn re(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) => Ok(Value::Number(*input)),
+        Some(Value::Complex(input)) => Ok(Value::Number(input.re)),
+        Some(Value::Matrix(mat)) => Ok(Value::Matrix(mat.clone())),
+        Some(Value::ComplexMatrix(mat)) => Ok(Value::Matrix(executor::matrix_transform_elements(mat, |num| num.re))),
+        _ => Err(RuntimeError::BuiltinFuncErr("re expects a number or matrix".to_string())),
+    }
+}
 
-fn det_recurse(mat: &Vec<Vec<f64>>) -> f64 {
-    let size = mat.len();
+fn im(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(_)) => Ok(Value::Number(0.0)),
+        Some(Value::Complex(input)) => Ok(Value::Number(input.im)),
+        Some(Value::Matrix(mat)) => Ok(Value::Matrix(executor::matrix_transform_elements(mat, |_| 0.0))),
+        Some(Value::ComplexMatrix(mat)) => Ok(Value::Matrix(executor::matrix_transform_elements(mat, |num| num.im))),
+        _ => Err(RuntimeError::BuiltinFuncErr("im expects a number or matrix".to_string())),
+    }
+}
 
-    if size == 2 {
-        return mat[0][0] * mat[1][1] - mat[1][0] * mat[0][1];
+fn conj(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Complex(input)) => Ok(Value::Complex(input.conj())),
+        Some(Value::ComplexMatrix(mat)) => Ok(Value::ComplexMatrix(executor::matrix_transform_elements(mat, Complex::conj))),
+        Some(value) => Ok(value.clone()),
+        None => Err(RuntimeError::BuiltinFuncErr("conj expects 1 value".to_string())),
     }
+}
 
-    let mut sum = 0.0;
+fn abs(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) => Ok(Value::Number(input.abs())),
+        Some(Value::Complex(input)) => Ok(Value::Number(input.abs())),
+        _ => Err(RuntimeError::BuiltinFuncErr("abs expects 1 number".to_string())),
+    }
+}
 
-    let mut submat = mat.clone();
-    let coefficients = submat.pop().unwrap();
+fn arg(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        Some(Value::Number(input)) => Ok(Value::Number(env.angle_from_radians(Complex::from(*input).arg()))),
+        Some(Value::Complex(input)) => Ok(Value::Number(env.angle_from_radians(input.arg()))),
+        _ => Err(RuntimeError::BuiltinFuncErr("arg expects 1 number".to_string())),
+    }
+}
 
-    for i in 0..size {
-        let mut minor = submat.clone();
-        for row in minor.iter_mut() {
-            row.remove(i);
+fn polar(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match (values.first(), values.get(1)) {
+        (Some(Value::Number(r)), Some(Value::Number(theta))) => {
+            Ok(Value::Complex(Complex::from_polar(*r, env.angle_to_radians(*theta))).normalized())
+        },
+        _ => Err(RuntimeError::BuiltinFuncErr("polar expects a magnitude and an angle".to_string())),
+    }
+}
+
+// rand() for a number in [0, 1), rand(n) for an n sized vector
+fn rand(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    match values.first() {
+        None => Ok(Value::Number(env.random())),
+        Some(Value::Number(size)) if *size > executor::MAX_VECTOR_LEN as f64 => {
+            Err(RuntimeError::BuiltinFuncErr(format!("rand can make at most {} numbers", executor::MAX_VECTOR_LEN)))
+        },
+        Some(Value::Number(size)) if *size >= 0.0 && size.fract() == 0.0 => {
+            Ok(Value::Matrix((0..*size as usize).map(|_| vec![env.random()]).collect()))
+        },
+        _ => Err(RuntimeError::BuiltinFuncErr("rand expects nothing or a vector size".to_string())),
+    }
+}
+
+// deriv(f, x) with x as a number or a matrix of points
+fn deriv(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let function = function_arg("deriv", 1, &values, 2)?;
+    match &values[1] {
+        Value::Number(x) => Ok(Value::Number(calculus::derivative(|x| real_call(env, "deriv", function, x), *x)?)),
+        Value::Matrix(mat) => {
+            let rows = mat.iter()
+                .map(|row| row.iter().map(|x| calculus::derivative(|x| real_call(env, "deriv", function, x), *x)).collect())
+                .collect::<Result<_, _>>()?;
+            Ok(Value::Matrix(rows))
+        },
+        _ => Err(RuntimeError::BuiltinFuncErr("deriv expects a real number or matrix for x".to_string())),
+    }
+}
+
+fn integrate(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let function = function_arg("integrate", 1, &values, 3)?;
+    match (&values[1], &values[2]) {
+        (Value::Number(start), Value::Number(end)) => Ok(Value::Number(calculus::integrate(|x| real_call(env, "integrate", function, x), *start, *end)?.value)),
+        _ => Err(RuntimeError::BuiltinFuncErr("integrate expects real numbers for the bounds".to_string())),
+    }
+}
+
+// trapz(y) with a spacing of 1, trapz(y, dx) with a fixed spacing or trapz(y, x) at the points x
+fn trapz(_env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let y = vector_arg("trapz", values.first())?;
+    let output = match values.get(1) {
+        None => calculus::trapz(&y, None, 1.0),
+        Some(Value::Number(step)) => calculus::trapz(&y, None, *step),
+        x => {
+            let x = vector_arg("trapz", x)?;
+            if x.len() != y.len() {
+                return Err(RuntimeError::BuiltinFuncErr(format!("trapz needs as many x values as y values, got {} and {}", x.len(), y.len())));
+            }
+            calculus::trapz(&y, Some(&x), 1.0)
+        },
+    };
+    Ok(Value::Number(output))
+}
+
+// ode(f, t0, t1, y0, tolerance, max_step) solves y' = f(t, y) with y(t0) = y0, giving a [t, y...] row for every step.
+// y is passed to f in the same shape as y0, so a number for a scalar problem or a vector
+fn ode(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let function = function_arg("ode", 2, &values, 4)?;
+    let (t0, t1) = match (&values[1], &values[2]) {
+        (Value::Number(t0), Value::Number(t1)) if t0.is_finite() && t1.is_finite() => (*t0, *t1),
+        _ => return Err(RuntimeError::BuiltinFuncErr("ode expects finite real numbers for t0 and t1".to_string())),
+    };
+    let (y0, is_row) = match &values[3] {
+        Value::Number(num) => (vec![*num], None),
+        value @ Value::Matrix(mat) if !mat.is_empty() => (vector_arg("ode", Some(value))?, Some(mat.len() == 1)),
+        _ => return Err(RuntimeError::BuiltinFuncErr("ode expects a real number or vector for y0".to_string())),
+    };
+    let tolerance = match values.get(4) {
+        None => calculus::DEFAULT_ODE_TOLERANCE,
+        Some(Value::Number(tolerance)) if *tolerance > 0.0 => *tolerance,
+        Some(_) => return Err(RuntimeError::BuiltinFuncErr("ode tolerance must be a positive number".to_string())),
+    };
+    let max_step = match values.get(5) {
+        None => f64::INFINITY,
+        Some(Value::Number(max_step)) if *max_step > 0.0 => *max_step,
+        Some(_) => return Err(RuntimeError::BuiltinFuncErr("ode max step must be a positive number".to_string())),
+    };
+
+    let dims = y0.len();
+    let call = |t: f64, y: &[f64]| {
+        let y = match is_row {
+            None => Value::Number(y[0]),
+            Some(true) => Value::Matrix(vec![y.to_vec()]),
+            Some(false) => Value::Matrix(y.iter().map(|num| vec![*num]).collect()),
+        };
+        let slope = match env.call(function, vec![Value::Number(t), y])? {
+            Value::Number(num) => vec![num],
+            value @ Value::Matrix(_) => vector_arg("ode", Some(&value))?,
+            value => return Err(RuntimeError::BuiltinFuncErr(format!("ode needs real numbers from the function, got {} at t = {t}", value.short_string()))),
+        };
+        match slope.len() == dims {
+            true => Ok(slope),
+            false => Err(RuntimeError::BuiltinFuncErr(format!("ode needs the function to give {dims} values to match y0, got {}", slope.len()))),
         }
-        let mut subdet = det_recurse(&minor);
-        if (i + size-1) % 2 != 0 {
-            subdet = -subdet;
+    };
+    let solution = calculus::ode(call, t0, t1, y0, tolerance, max_step)?;
+    Ok(Value::Matrix(solution.into_iter().map(|(t, y)| iter::once(t).chain(y).collect()).collect()))
+}
+
+// map(f, M) calls f on every element and keeps the shape
+fn map(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let function = function_arg("map", 1, &values, 2)?;
+    let call = |num: Complex| match env.call(function, vec![Value::Complex(num).normalized()])? {
+        Value::Number(num) => Ok(Complex::from(num)),
+        Value::Complex(num) => Ok(num),
+        value => Err(RuntimeError::BuiltinFuncErr(format!("map needs a number from the function, got {}", value.short_string()))),
+    };
+    match values[1].to_complex() {
+        Value::Complex(num) => Ok(Value::Complex(call(num)?).normalized()),
+        Value::ComplexMatrix(mat) => {
+            let rows = mat.into_iter()
+                .map(|row| row.into_iter().map(call).collect())
+                .collect::<Result<_, _>>()?;
+            Ok(Value::ComplexMatrix(rows).normalized())
+        },
+        value => Err(RuntimeError::BuiltinFuncErr(format!("map expects a number or matrix, got {}", value.short_string()))),
+    }
+}
+
+// filter(f, v) keeps the elements where f is true, rows stay rows and columns stay columns
+fn filter(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let function = function_arg("filter", 1, &values, 2)?;
+    let (elements, is_row) = elements_arg("filter", &values[1])?;
+    let mut kept = Vec::new();
+    for num in elements {
+        if env.call(function, vec![Value::Complex(num).normalized()])?.is_true() {
+            kept.push(num);
         }
-        sum += subdet * coefficients[i];
     }
+    let rows = match is_row && !kept.is_empty() {
+        true => vec![kept],
+        false => kept.into_iter().map(|num| vec![num]).collect(),
+    };
+    Ok(Value::ComplexMatrix(rows).normalized())
+}
 
-    sum
+// reduce(f, v, start) folds f(total, element) over v, leaving out start begins with the first element
+fn reduce(env: &Environment, values: Vec<Value>) -> Result<Value, RuntimeError> {
+    let function = function_arg("reduce", 2, &values, 2)?;
+    let (elements, _) = elements_arg("reduce", &values[1])?;
+    let mut elements = elements.into_iter().map(|num| Value::Complex(num).normalized());
+    let mut total = match values.get(2) {
+        Some(start) => start.clone(),
+        None => elements.next().ok_or(RuntimeError::BuiltinFuncErr("reduce needs a starting value for an empty vector".to_string()))?,
+    };
+    for element in elements {
+        total = env.call(function, vec![total, element])?;
+    }
+    Ok(total)
 }
 
-// gets submatrix excluding row and col 0 indexed
-fn submatrix(matrix: &Vec<Vec<f64>>, row: usize, col: usize) -> Vec<Vec<f64>> {
-    let mut output = matrix.clone();
-    output.remove(row);
-    for row in output.iter_mut() {
-        row.remove(col);
+// the function a builtin works with, which comes first. Builtins can have optional
+// parameters so they're left to complain about their own arguments
+fn function_arg<'a>(fname: &str, params: usize, values: &'a [Value], num_args: usize) -> Result<&'a Value, RuntimeError> {
+    if values.len() < num_args {
+        return Err(RuntimeError::WrongNumFunctionArgs {fname: fname.to_string(), expected: num_args, got: values.len()});
+    }
+    match &values[0] {
+        function @ Value::Function(function_def) if function_def.params.len() == params || matches!(function_def.body, FunctionBody::Builtin(_)) => Ok(function),
+        Value::Function(function_def) => Err(RuntimeError::BuiltinFuncErr(format!(
+            "{fname} needs a function that takes {params} argument{}, {} takes {}",
+            if params == 1 { "" } else { "s" }, values[0].short_string(), function_def.params.len(),
+        ))),
+        value => Err(RuntimeError::BuiltinFuncErr(format!("{fname} expects a function first, got {}", value.short_string()))),
     }
-    output
 }
 
-fn adjoint(matrix: &Vec<Vec<f64>>) -> Vec<Vec<f64>> {
-    let size = matrix.len();
+// the elements of a row or column vector, and whether it was a row
+fn elements_arg(fname: &str, value: &Value) -> Result<(Vec<Complex>, bool), RuntimeError> {
+    let mat = match value.to_complex() {
+        Value::Complex(num) => vec![vec![num]],
+        Value::ComplexMatrix(mat) => mat,
+        value => return Err(RuntimeError::BuiltinFuncErr(format!("{fname} expects a vector, got {}", value.short_string()))),
+    };
+    let is_row = mat.len() == 1;
+    if !is_row && mat.iter().any(|row| row.len() != 1) {
+        let (rows, cols) = (mat.len(), mat[0].len());
+        return Err(RuntimeError::BuiltinFuncErr(format!("{fname} expects a vector, got a {rows}x{cols} matrix")));
+    }
+    Ok((mat.into_iter().flatten().collect(), is_row))
+}
 
-    if size == 2 {
-        return vec![
-            vec![matrix[1][1], -matrix[0][1]],
-            vec![-matrix[1][0], matrix[0][0]],
-        ];
+fn real_call(env: &Environment, fname: &str, function: &Value, x: f64) -> Result<f64, RuntimeError> {
+    match env.call(function, vec![Value::Number(x)])? {
+        Value::Number(num) => Ok(num),
+        value => Err(RuntimeError::BuiltinFuncErr(format!("{fname} needs a real number from the function, got {} at {x}", value.short_string()))),
+    }
+}
+
+// accepts row or column vectors
+fn vector_arg(fname: &str, value: Option<&Value>) -> Result<Vec<f64>, RuntimeError> {
+    match value {
+        Some(Value::Matrix(mat)) if mat.len() == 1 => Ok(mat[0].clone()),
+        Some(Value::Matrix(mat)) if mat.iter().all(|row| row.len() == 1) => Ok(mat.iter().map(|row| row[0]).collect()),
+        Some(Value::Matrix(mat)) => {
+            let (rows, cols) = linalg::dims(mat);
+            Err(RuntimeError::BuiltinFuncErr(format!("{fname} expects a vector, got a {rows}x{cols} matrix")))
+        },
+        Some(Value::ComplexMatrix(_)) => Err(RuntimeError::BuiltinFuncErr(format!("{fname} only supports real vectors"))),
+        _ => Err(RuntimeError::BuiltinFuncErr(format!("{fname} expects a vector"))),
     }
+}
+
+// -- matrix helper methods ---------------------------------------------------
+
+// gauss-jordan elimination with partial pivoting, works in place and returns the
+// 0 indexed pivot columns. Leading entries are scaled to 1, and entries above
+// them are only eliminated when reduced is set. Anything smaller than the
+// tolerance is treated as 0, which defaults to max(m, n) * eps * max|a_ij|
+fn row_echelon(mat: &mut [Vec<f64>], tolerance: Option<f64>, reduced: bool) -> Vec<usize> {
+    let rows = mat.len();
+    let cols = mat.first().map(|row| row.len()).unwrap_or(0);
+    let tolerance = tolerance.unwrap_or_else(|| {
+        let largest = mat.iter().flatten().fold(0.0_f64, |acc, num| acc.max(num.abs()));
+        rows.max(cols) as f64 * f64::EPSILON * largest
+    });
+
+    let mut pivot_cols = Vec::new();
+    let mut pivot_row = 0;
+
+    for col in 0..cols {
+        if pivot_row >= rows {
+            break;
+        }
 
-    let mut output = vec![Vec::with_capacity(size); size];
+        let (best_row, best_value) = (pivot_row..rows)
+            .map(|row| (row, mat[row][col].abs()))
+            .fold((pivot_row, -1.0), |best, current| if current.1 > best.1 { current } else { best });
 
-    for row in 0..size {
-        for col in 0..size {
-            let submat = submatrix(matrix, row, col);
-            let mut cofactor = det_recurse(&submat);
-            if (row + col) % 2 != 0 {
-                cofactor = -cofactor;
+        if best_value <= tolerance {
+            for row in mat.iter_mut().skip(pivot_row) {
+                row[col] = 0.0;
             }
-            output[row].push(cofactor);
+            continue;
         }
-    }
 
-    trans(&output)
-}
+        mat.swap(pivot_row, best_row);
 
-fn trans(mat: &Vec<Vec<f64>>) -> Vec<Vec<f64>> {
-    let rows = mat.len();
-    let cols = mat[0].len();
+        let pivot = mat[pivot_row][col];
+        for num in mat[pivot_row].iter_mut() {
+            *num /= pivot;
+        }
+        mat[pivot_row][col] = 1.0;
 
-    let mut output: Vec<Vec<f64>> = vec![Vec::with_capacity(rows); cols];
+        let pivot_values = mat[pivot_row].clone();
+        let start_row = match reduced {
+            true => 0,
+            false => pivot_row + 1,
+        };
 
-    for i in 0..rows {
-        for j in 0..cols {
-            output[j].push(mat[i][j]);
+        for (row_i, row) in mat.iter_mut().enumerate().skip(start_row) {
+            if row_i == pivot_row {
+                continue;
+            }
+            let factor = row[col];
+            if factor == 0.0 {
+                continue;
+            }
+            for (num, pivot_num) in iter::zip(row.iter_mut(), &pivot_values) {
+                *num -= factor * pivot_num;
+            }
+            row[col] = 0.0;
         }
+
+        pivot_cols.push(col);
+        pivot_row += 1;
     }
-    output
+
+    pivot_cols
 }
 
-pub static FUNCTIONS: [(&str, &[&str], fn(Vec<Value>) -> Result<Value, RuntimeError>); 18] = [
+fn trans<T: Copy>(mat: &[Vec<T>]) -> Vec<Vec<T>> {
+    let cols = mat.first().map(|row| row.len()).unwrap_or(0);
+    (0..cols)
+        .map(|j| mat.iter().map(|row| row[j]).collect())
+        .collect()
+}
+
+pub static FUNCTIONS: &[(&str, &[&str], BuiltinFn)] = &[
     ("std.dot", &["vec1", "vec2"], dot),
     ("std.cross", &["vec1", "vec2"], cross),
     ("std.unit", &["vector"], unit),
     ("std.magnitude", &["vector"], magnitude),
     ("std.inv", &["matrix"], inv),
     ("std.det", &["matrix"], det),
+    ("std.lu", &["matrix"], lu),
+    ("std.lu.l", &["matrix"], lu_l),
+    ("std.lu.u", &["matrix"], lu_u),
+    ("std.lu.p", &["matrix"], lu_p),
+    ("std.solve", &["matrix", "rhs"], solve),
+    ("std.expm", &["matrix"], expm),
+    ("std.sqrtm", &["matrix"], sqrtm),
+    ("std.logm", &["matrix"], logm),
+    ("std.eig", &["matrix"], eig),
+    ("std.eigvals", &["matrix"], eigvals),
+    ("std.svd", &["matrix"], svd),
+    ("std.svd.u", &["matrix"], svd_u),
+    ("std.svd.s", &["matrix"], svd_s),
+    ("std.svd.v", &["matrix"], svd_v),
+    ("std.qr", &["matrix"], qr),
+    ("std.qr.q", &["matrix"], qr_q),
+    ("std.chol", &["matrix"], chol),
     ("std.transpose", &["matrix"], transpose),
-    ("std.rref", &["matrix"], rref),
+    ("std.rref", &["matrix", "tolerance"], rref),
+    ("std.ref", &["matrix", "tolerance"], row_echelon_form),
+    ("std.pivots", &["matrix", "tolerance"], pivots),
+    ("std.rank", &["matrix", "tolerance"], rank),
     ("std.log", &["number", "base"], log),
     ("std.ln", &["number"], ln),
+    ("std.sqrt", &["number"], sqrt),
     ("std.sin", &["number"], sin),
     ("std.cos", &["number"], cos),
     ("std.tan", &["number"], tan),
@@ -351,4 +795,163 @@ pub static FUNCTIONS: [(&str, &[&str], fn(Vec<Value>) -> Result<Value, RuntimeEr
     ("std.atan", &["number"], atan),
     ("std.rad", &["number"], rad),
     ("std.deg", &["number"], deg),
+    ("std.re", &["number"], re),
+    ("std.im", &["number"], im),
+    ("std.conj", &["number"], conj),
+    ("std.abs", &["number"], abs),
+    ("std.arg", &["number"], arg),
+    ("std.polar", &["magnitude", "angle"], polar),
+    ("std.rand", &["size"], rand),
+    ("std.deriv", &["function", "x"], deriv),
+    ("std.integrate", &["function", "start", "end"], integrate),
+    ("std.trapz", &["y", "x"], trapz),
+    ("std.ode", &["function", "t0", "t1", "y0", "tolerance", "max_step"], ode),
+    ("std.map", &["function", "matrix"], map),
+    ("std.filter", &["function", "vector"], filter),
+    ("std.reduce", &["function", "vector", "start"], reduce),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app::{environment::Settings, session::Session, state::Context}, parser::{syntax_tree::generate_syntax_tree, tokens::tokenize_spanned}};
+
+    fn call(func: BuiltinFn, values: Vec<Value>) -> Result<Value, RuntimeError> {
+        func(&Environment::new(&Settings::default()), values)
+    }
+
+    fn matrix(value: Result<Value, RuntimeError>) -> Vec<Vec<f64>> {
+        match value {
+            Ok(Value::Matrix(mat)) => mat,
+            other => panic!("expected matrix, got {:?}", other),
+        }
+    }
+
+    fn number(value: Result<Value, RuntimeError>) -> f64 {
+        match value {
+            Ok(Value::Number(num)) => num,
+            other => panic!("expected number, got {:?}", other),
+        }
+    }
+
+    fn complex_matrix(value: Result<Value, RuntimeError>) -> Vec<Vec<Complex>> {
+        match value.map(|value| value.to_complex()) {
+            Ok(Value::ComplexMatrix(mat)) => mat,
+            other => panic!("expected matrix, got {:?}", other),
+        }
+    }
+
+    // for builtins that take functions, which need a context to call them in. The span
+    // is dropped so errors can be matched on directly
+    fn run(context: &mut Context, line: &str) -> Result<Value, RuntimeError> {
+        let expression = generate_syntax_tree(tokenize_spanned(line)).unwrap();
+        context.execute(expression).map_err(|err| match err {
+            RuntimeError::At(err, _) => *err,
+            err => err,
+        })
+    }
+
+    #[test]
+    fn rref_non_square() {
+        let input = vec![
+            vec![1.0, 2.0, -1.0, -4.0],
+            vec![2.0, 3.0, -1.0, -11.0],
+            vec![-2.0, 0.0, -3.0, 22.0],
+        ];
+        let output = matrix(call(rref, vec![Value::Matrix(input)]));
+        let expected = [[1.0, 0.0, 0.0, -8.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, -2.0]];
+        for (row, expected_row) in iter::zip(&output, &expected) {
+            for (num, expected_num) in iter::zip(row, expected_row) {
+                assert!((num - expected_num).abs() < 1e-12);
+            }
+        }
+    }
+    #[test]
+    fn rank_and_pivots() {
+        let input = vec![
+            vec![1.0, 2.0, 3.0],
+            vec![2.0, 4.0, 6.0],
+            vec![1.0, 0.0, 1.0],
+        ];
+        assert_eq!(number(call(rank, vec![Value::Matrix(input.clone())])), 2.0);
+        assert_eq!(matrix(call(pivots, vec![Value::Matrix(input)])), vec![vec![1.0], vec![2.0]]);
+    }
+    #[test]
+    fn rank_tolerance() {
+        let input = vec![vec![1.0, 0.0], vec![0.0, 1e-9]];
+        assert_eq!(number(call(rank, vec![Value::Matrix(input.clone())])), 2.0);
+        assert_eq!(number(call(rank, vec![Value::Matrix(input), Value::Number(1e-6)])), 1.0);
+    }
+    #[test]
+    fn eigenvectors_line_up_with_eigenvalues() {
+        // symmetric, general with real eigenvalues and a rotation with complex ones
+        let inputs = [
+            vec![vec![2.0, 1.0], vec![1.0, 3.0]],
+            vec![vec![4.0, 1.0, 0.0], vec![2.0, 3.0, 0.0], vec![0.0, 1.0, 6.0]],
+            vec![vec![0.0, -1.0], vec![1.0, 0.0]],
+        ];
+        for input in inputs {
+            let vectors = complex_matrix(call(eig, vec![Value::Matrix(input.clone())]));
+            let eigenvalues: Vec<_> = complex_matrix(call(eigvals, vec![Value::Matrix(input.clone())])).iter().map(|row| row[0]).collect();
+            assert!(eigenvalues.windows(2).all(|pair| pair[0].re <= pair[1].re));
+            for (k, eigenvalue) in eigenvalues.into_iter().enumerate() {
+                assert!(vectors.iter().map(|row| row[k].abs().powi(2)).sum::<f64>() > 0.5);
+                for (row, input_row) in input.iter().enumerate() {
+                    let product = iter::zip(input_row, &vectors).fold(Complex::default(), |sum, (a, v_row)| sum + Complex::from(*a) * v_row[k]);
+                    assert!((product - eigenvalue * vectors[row][k]).abs() < 1e-9);
+                }
+            }
+        }
+    }
+    #[test]
+    fn calculus() {
+        let mut session = Session::new();
+        session.execute("def f(x) = x^3");
+        session.execute("def g(x) = x + y");
+        let context = &mut session.context;
+        assert!((number(run(context, "std.deriv(f, 2)")) - 12.0).abs() < 1e-9);
+        assert!((number(run(context, "std.integrate(f, 0, 2)")) - 4.0).abs() < 1e-12);
+        assert!((number(run(context, "std.integrate(std.cos, 0, 1)")) - 1f64.sin()).abs() < 1e-12);
+        assert_eq!(number(run(context, "std.trapz([1, 2, 3], 0.5)")), 2.0);
+        assert!(number(run(context, "std.trapz([])")).is_sign_positive());
+
+        assert!(matches!(run(context, "std.deriv(2, 1)"), Err(RuntimeError::BuiltinFuncErr(msg)) if msg == "deriv expects a function first, got 2"));
+        assert!(matches!(run(context, "std.integrate(f, [1], 1)"), Err(RuntimeError::BuiltinFuncErr(msg)) if msg == "integrate expects real numbers for the bounds"));
+        assert!(matches!(
+            run(context, "std.integrate(std.sqrt, -1, 1)"),
+            Err(RuntimeError::BuiltinFuncErr(msg)) if msg.starts_with("integrate needs a real number from the function"),
+        ));
+        // errors from the function itself come through as they are
+        assert!(matches!(run(context, "std.deriv(g, 1)"), Err(RuntimeError::UnknownIdentifier(name)) if name == "y"));
+    }
+    #[test]
+    fn map_filter_reduce() {
+        let mut session = Session::new();
+        session.execute("sq = x -> x^2");
+        let context = &mut session.context;
+        assert_eq!(matrix(run(context, "std.map(sq, [1, 2; 3, 4])")), vec![vec![1.0, 4.0], vec![9.0, 16.0]]);
+        assert_eq!(matrix(run(context, "std.filter(x -> x > 2, [1, 2, 3, 4])")), vec![vec![3.0, 4.0]]);
+        assert_eq!(number(run(context, "std.reduce((a, b) -> a * b, 1:5)")), 120.0);
+
+        assert!(matches!(run(context, "std.map(x -> [x, x], [1, 2])"), Err(RuntimeError::BuiltinFuncErr(msg)) if msg == "map needs a number from the function, got 1x2"));
+        assert!(matches!(run(context, "std.filter(1, [1])"), Err(RuntimeError::BuiltinFuncErr(msg)) if msg == "filter expects a function first, got 1"));
+        assert!(matches!(run(context, "std.reduce((a, b) -> a + b, [])"), Err(RuntimeError::BuiltinFuncErr(msg)) if msg == "reduce needs a starting value for an empty vector"));
+        assert!(matches!(run(context, "std.map(x -> x + y, [1])"), Err(RuntimeError::UnknownIdentifier(name)) if name == "y"));
+    }
+    #[test]
+    fn ode_rows() {
+        let mut session = Session::new();
+        let context = &mut session.context;
+        // y goes to the function in the same shape as y0, and each row is [t, y...]
+        let rows = matrix(run(context, "std.ode((t, y) -> [y[2]; -y[1]], 0, 1, [1; 0])"));
+        assert!(rows.iter().all(|row| row.len() == 3));
+        assert_eq!((rows[0][0], rows[0][1], rows[0][2]), (0.0, 1.0, 0.0));
+        assert_eq!(rows.last().unwrap()[0], 1.0);
+        assert!(matrix(run(context, "std.ode((t, y) -> -y, 0, 1, 1)")).iter().all(|row| row.len() == 2));
+
+        assert!(matches!(
+            run(context, "std.ode((t, y) -> [1, 2], 0, 1, 0)"),
+            Err(RuntimeError::BuiltinFuncErr(msg)) if msg == "ode needs the function to give 1 values to match y0, got 2",
+        ));
+    }
+}
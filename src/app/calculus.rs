@@ -0,0 +1,339 @@
+// numerical calculus for the builtins that take functions. The functions being
+// worked on are user code that can fail, so their errors are passed straight through
+
+use std::iter;
+
+use super::executor::RuntimeError;
+
+// the positive half of the gauss-kronrod 15 point nodes on [-1, 1], every other one is also a 7 point gauss node
+const KRONROD_NODES: [f64; 8] = [
+    0.9914553711208126,
+    0.9491079123427585,
+    0.8648644233597691,
+    0.7415311855993945,
+    0.5860872354676911,
+    0.4058451513773972,
+    0.20778495500789848,
+    0.0,
+];
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224,
+    0.06309209262997856,
+    0.10479001032225019,
+    0.14065325971552592,
+    0.1690047266392679,
+    0.19035057806478542,
+    0.20443294007529889,
+    0.20948214108472782,
+];
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.1294849661688697,
+    0.27970539148927664,
+    0.3818300505051189,
+    0.4179591836734694,
+];
+
+const INTEGRAL_TOLERANCE: f64 = 1e-10;
+// each segment costs 15 calls, this keeps slow user functions from hanging the calculator
+const MAX_SEGMENTS: usize = 2000;
+
+// richardson tableau size and how much the step shrinks between rows
+const DERIVATIVE_STEPS: usize = 10;
+const STEP_SHRINK: f64 = 1.4;
+
+// dormand-prince 5(4) tableau. The last row of a is also the 5th order solution, so the
+// last stage of a step is the first stage of the next one
+const DORMAND_PRINCE_C: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+const DORMAND_PRINCE_A: [[f64; 6]; 7] = [
+    [0.0; 6],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0, 0.0, 0.0],
+    [9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0, 0.0],
+    [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+];
+// difference between the 5th and 4th order weights, which estimates the error of a step
+const DORMAND_PRINCE_E: [f64; 7] = [
+    71.0 / 57600.0, 0.0, -71.0 / 16695.0, 71.0 / 1920.0, -17253.0 / 339200.0, 22.0 / 525.0, -1.0 / 40.0,
+];
+
+pub const DEFAULT_ODE_TOLERANCE: f64 = 1e-6;
+// a solution this long is more than can be looked at, and usually means a stiff problem
+const MAX_ODE_STEPS: usize = 100_000;
+
+// the error is an estimate, and is always within the tolerance when integrate succeeds
+pub struct Integral {
+    pub value: f64,
+    pub error: f64,
+}
+
+// ridders' method, central differences with shrinking steps extrapolated to a step of 0.
+// stops once the extrapolation starts getting worse, which is roundoff taking over
+pub fn derivative(mut func: impl FnMut(f64) -> Result<f64, RuntimeError>, x: f64) -> Result<f64, RuntimeError> {
+    let mut step = 0.1 * x.abs().max(1.0);
+    let mut central = |step: f64| -> Result<f64, RuntimeError> {
+        Ok((func(x + step)? - func(x - step)?) / (2.0 * step))
+    };
+
+    let mut tableau = vec![vec![central(step)?]];
+    let mut best = tableau[0][0];
+    let mut best_error = f64::INFINITY;
+    for i in 1..DERIVATIVE_STEPS {
+        step /= STEP_SHRINK;
+        let mut row = vec![central(step)?];
+        let mut factor = STEP_SHRINK * STEP_SHRINK;
+        for j in 1..=i {
+            let estimate = (row[j - 1] * factor - tableau[i - 1][j - 1]) / (factor - 1.0);
+            let error = (estimate - row[j - 1]).abs().max((estimate - tableau[i - 1][j - 1]).abs());
+            if error <= best_error {
+                best_error = error;
+                best = estimate;
+            }
+            row.push(estimate);
+            factor *= STEP_SHRINK * STEP_SHRINK;
+        }
+        let diverging = (row[i] - tableau[i - 1][i - 1]).abs() >= 2.0 * best_error;
+        tableau.push(row);
+        if diverging {
+            break;
+        }
+    }
+    match best.is_finite() {
+        true => Ok(best),
+        false => Err(RuntimeError::BuiltinFuncErr(format!("deriv has no finite answer at {x}"))),
+    }
+}
+
+// adaptive gauss-kronrod, keeps splitting whichever segment has the largest error until the
+// total error is small enough. Infinite bounds are mapped onto a finite range first
+pub fn integrate(mut func: impl FnMut(f64) -> Result<f64, RuntimeError>, start: f64, end: f64) -> Result<Integral, RuntimeError> {
+    if start.is_nan() || end.is_nan() {
+        return Err(RuntimeError::BuiltinFuncErr("integrate bounds must be numbers".to_string()));
+    }
+    if start > end {
+        let integral = integrate(func, end, start)?;
+        return Ok(Integral {value: -integral.value, error: integral.error});
+    }
+    if start == end {
+        return Ok(Integral {value: 0.0, error: 0.0});
+    }
+
+    // the mapping itself blows up at the infinite end, so a sum that isn't finite there
+    // says nothing about whether the integral diverges
+    match (start.is_finite(), end.is_finite()) {
+        (true, true) => adaptive(func, start, end, true),
+        // x = start + t / (1 - t) for t in [0, 1)
+        (true, false) => adaptive(|t| Ok(func(start + t / (1.0 - t))? / ((1.0 - t) * (1.0 - t))), 0.0, 1.0, false),
+        // x = end - (1 - t) / t for t in (0, 1]
+        (false, true) => adaptive(|t| Ok(func(end - (1.0 - t) / t)? / (t * t)), 0.0, 1.0, false),
+        // x = t / (1 - t^2) for t in (-1, 1)
+        (false, false) => adaptive(|t| {
+            let denominator = 1.0 - t * t;
+            Ok(func(t / denominator)? * (1.0 + t * t) / (denominator * denominator))
+        }, -1.0, 1.0, false),
+    }
+}
+
+fn adaptive(mut func: impl FnMut(f64) -> Result<f64, RuntimeError>, start: f64, end: f64, bounded: bool) -> Result<Integral, RuntimeError> {
+    let mut segments = vec![(start, end, kronrod(&mut func, start, end)?)];
+    loop {
+        let value: f64 = segments.iter().map(|(_, _, integral)| integral.value).sum();
+        let error: f64 = segments.iter().map(|(_, _, integral)| integral.error).sum();
+        if !value.is_finite() {
+            return Err(RuntimeError::BuiltinFuncErr(match bounded {
+                true => "integrate diverges".to_string(),
+                false => "integrate did not converge, the function doesn't shrink fast enough toward infinity".to_string(),
+            }));
+        }
+        if error <= INTEGRAL_TOLERANCE * value.abs().max(1.0) {
+            return Ok(Integral {value, error});
+        }
+
+        let worst = (0..segments.len())
+            .max_by(|&i, &j| segments[i].2.error.total_cmp(&segments[j].2.error))
+            .unwrap_or(0);
+        let (left, right, _) = segments[worst];
+        let middle = (left + right) / 2.0;
+        // a segment that can't be split any further is as good as it is going to get
+        if segments.len() >= MAX_SEGMENTS || middle <= left || middle >= right {
+            return Err(RuntimeError::BuiltinFuncErr(format!("integrate did not converge, got {value} with an estimated error of {error:.1e}")));
+        }
+        segments[worst] = (left, middle, kronrod(&mut func, left, middle)?);
+        segments.push((middle, right, kronrod(&mut func, middle, right)?));
+    }
+}
+
+// the error estimate is the difference between the 15 point and the 7 point rule
+fn kronrod(func: &mut impl FnMut(f64) -> Result<f64, RuntimeError>, start: f64, end: f64) -> Result<Integral, RuntimeError> {
+    let center = (start + end) / 2.0;
+    let half_width = (end - start) / 2.0;
+
+    let center_value = func(center)?;
+    let mut kronrod_sum = center_value * KRONROD_WEIGHTS[7];
+    let mut gauss_sum = center_value * GAUSS_WEIGHTS[3];
+    for (i, (node, weight)) in iter::zip(&KRONROD_NODES[..7], &KRONROD_WEIGHTS).enumerate() {
+        let pair = func(center - half_width * node)? + func(center + half_width * node)?;
+        kronrod_sum += weight * pair;
+        if i % 2 == 1 {
+            gauss_sum += GAUSS_WEIGHTS[i / 2] * pair;
+        }
+    }
+    Ok(Integral {
+        value: kronrod_sum * half_width,
+        error: ((kronrod_sum - gauss_sum) * half_width).abs(),
+    })
+}
+
+// adaptive runge-kutta from t0 to t1, giving the time and state after every accepted step.
+// The tolerance is used as both the relative and the absolute error allowed per step
+pub fn ode(
+    mut func: impl FnMut(f64, &[f64]) -> Result<Vec<f64>, RuntimeError>,
+    t0: f64,
+    t1: f64,
+    y0: Vec<f64>,
+    tolerance: f64,
+    max_step: f64,
+) -> Result<Vec<(f64, Vec<f64>)>, RuntimeError> {
+    let direction = if t1 >= t0 { 1.0 } else { -1.0 };
+    let mut t = t0;
+    let mut y = y0;
+    let mut solution = vec![(t, y.clone())];
+    let mut slope = func(t, &y)?;
+    let mut step = first_step(&y, &slope, (t1 - t0).abs(), tolerance).min(max_step);
+
+    while (t1 - t) * direction > 0.0 {
+        if solution.len() > MAX_ODE_STEPS {
+            return Err(RuntimeError::BuiltinFuncErr(format!("ode took more than {MAX_ODE_STEPS} steps to get to {t}, try a looser tolerance")));
+        }
+        // the last step is cut short so it lands exactly on t1
+        let h = direction * step.min((t1 - t).abs());
+        if t + h == t {
+            return Err(RuntimeError::BuiltinFuncErr(format!("ode steps got too small at t = {t}, the solution may blow up there")));
+        }
+
+        let mut stages = vec![slope.clone()];
+        let mut stage_y = y.clone();
+        for (c, a) in iter::zip(&DORMAND_PRINCE_C[1..], &DORMAND_PRINCE_A[1..]) {
+            stage_y = (0..y.len())
+                .map(|i| y[i] + h * iter::zip(a, &stages).map(|(a, k)| a * k[i]).sum::<f64>())
+                .collect();
+            stages.push(func(t + c * h, &stage_y)?);
+        }
+
+        // root mean square of each component's error over what it's allowed, so 1 is just acceptable
+        let error = (0..y.len())
+            .map(|i| {
+                let local = h * iter::zip(&DORMAND_PRINCE_E, &stages).map(|(e, k)| e * k[i]).sum::<f64>();
+                let scale = tolerance + tolerance * y[i].abs().max(stage_y[i].abs());
+                (local / scale).powi(2)
+            })
+            .sum::<f64>();
+        let error = (error / y.len().max(1) as f64).sqrt();
+        if !error.is_finite() || stage_y.iter().any(|num| !num.is_finite()) {
+            step /= 10.0;
+            continue;
+        }
+
+        if error <= 1.0 {
+            t = if step >= (t1 - t).abs() { t1 } else { t + h };
+            y = stage_y;
+            slope = stages.pop().unwrap_or_default();
+            solution.push((t, y.clone()));
+        }
+        let factor = match error {
+            0.0 => 5.0,
+            error => (0.9 * error.powf(-0.2)).clamp(0.2, 5.0),
+        };
+        step = (h.abs() * factor).min(max_step);
+    }
+    Ok(solution)
+}
+
+// a step small enough that the slope doesn't change much across it, from hairer's heuristic
+fn first_step(y: &[f64], slope: &[f64], span: f64, tolerance: f64) -> f64 {
+    let norm = |values: &[f64]| values.iter().map(|num| (num / (tolerance + tolerance * num.abs())).powi(2)).sum::<f64>().sqrt();
+    let (y_norm, slope_norm) = (norm(y), norm(slope));
+    let step = match y_norm < 1e-5 || slope_norm < 1e-5 {
+        true => 1e-6 * span.max(1.0),
+        false => 0.01 * y_norm / slope_norm,
+    };
+    step.min(span)
+}
+
+// trapezoid rule over samples, the spacing is either a list of x values or one fixed step
+pub fn trapz(y: &[f64], x: Option<&[f64]>, step: f64) -> f64 {
+    match x {
+        Some(x) => iter::zip(y.windows(2), x.windows(2))
+            .map(|(y, x)| (x[1] - x[0]) * (y[0] + y[1]) / 2.0)
+            .fold(0.0, |total, area| total + area),
+        // folded from 0 since an empty float sum gives -0
+        None => y.windows(2).map(|y| step * (y[0] + y[1]) / 2.0).fold(0.0, |total, area| total + area),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derivatives() {
+        let deriv = derivative(|x| Ok(x.sin()), 1.0).unwrap();
+        assert!((deriv - 1f64.cos()).abs() < 1e-12);
+        let deriv = derivative(|x| Ok(x.powi(3)), 1e4).unwrap();
+        assert!((deriv - 3e8).abs() / 3e8 < 1e-12);
+        let deriv = derivative(|x| Ok(x.exp()), -2.0).unwrap();
+        assert!((deriv - (-2f64).exp()).abs() < 1e-12);
+    }
+    #[test]
+    fn integrals() {
+        let integral = integrate(|x| Ok(x.sin()), 0.0, std::f64::consts::PI).unwrap();
+        assert!((integral.value - 2.0).abs() < 1e-12);
+        // smooth, so the estimate should be well inside the tolerance
+        assert!(integral.error <= INTEGRAL_TOLERANCE * 2.0);
+        let integral = integrate(|x| Ok(x * x), 3.0, 0.0).unwrap();
+        assert!((integral.value + 9.0).abs() < 1e-12);
+        // singular at an endpoint, which the nodes never touch
+        let integral = integrate(|x| Ok(1.0 / x.sqrt()), 0.0, 1.0).unwrap();
+        assert!((integral.value - 2.0).abs() < 1e-8);
+        assert!(integrate(|x| Ok(1.0 / x), 0.0, 1.0).is_err());
+    }
+    #[test]
+    fn infinite_bounds() {
+        let integral = integrate(|x| Ok((-x * x).exp()), f64::NEG_INFINITY, f64::INFINITY).unwrap();
+        assert!((integral.value - std::f64::consts::PI.sqrt()).abs() < 1e-10);
+        let integral = integrate(|x| Ok(1.0 / (1.0 + x * x)), 0.0, f64::INFINITY).unwrap();
+        assert!((integral.value - std::f64::consts::FRAC_PI_2).abs() < 1e-10);
+        let integral = integrate(|x| Ok(x.exp()), f64::NEG_INFINITY, 0.0).unwrap();
+        assert!((integral.value - 1.0).abs() < 1e-10);
+        let Err(err) = integrate(|x| Ok(x.sin()), 0.0, f64::INFINITY) else {
+            panic!("sin has no integral to infinity");
+        };
+        assert!(err.to_string().starts_with("integrate did not converge"));
+    }
+    #[test]
+    fn ode_solutions() {
+        let solution = ode(|_, y| Ok(vec![-y[0]]), 0.0, 2.0, vec![1.0], 1e-8, f64::INFINITY).unwrap();
+        let (t, y) = solution.last().unwrap();
+        assert_eq!(*t, 2.0);
+        assert!((y[0] - (-2f64).exp()).abs() < 1e-8);
+
+        // a full turn of y'' = -y, with the steps kept short
+        let turn = 2.0 * std::f64::consts::PI;
+        let solution = ode(|_, y| Ok(vec![y[1], -y[0]]), 0.0, turn, vec![1.0, 0.0], 1e-8, 0.1).unwrap();
+        assert!(solution.windows(2).all(|pair| pair[1].0 - pair[0].0 <= 0.1 + 1e-12));
+        let (_, y) = solution.last().unwrap();
+        assert!((y[0] - 1.0).abs() < 1e-6 && y[1].abs() < 1e-6);
+
+        // y' = y^2 from y(0) = 1 goes to infinity at t = 1
+        assert!(ode(|_, y| Ok(vec![y[0] * y[0]]), 0.0, 2.0, vec![1.0], 1e-6, f64::INFINITY).is_err());
+    }
+    #[test]
+    fn trapezoids() {
+        assert_eq!(trapz(&[1.0, 2.0, 3.0], None, 1.0), 4.0);
+        assert_eq!(trapz(&[1.0, 2.0, 3.0], None, 0.5), 2.0);
+        assert_eq!(trapz(&[0.0, 1.0, 4.0], Some(&[0.0, 1.0, 2.0]), 1.0), 3.0);
+        assert_eq!(trapz(&[5.0], None, 1.0), 0.0);
+        assert!(trapz(&[], None, 1.0).is_sign_positive());
+    }
+}
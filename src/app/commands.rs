@@ -1,61 +1,68 @@
-use crate::parser::tokens::Token;
-use crate::parser::general_parsing;
-use super::{config::Panel, state::{App, Context, FunctionDef, PopupName}, user_scripts::ScriptError};
-
-// returns is_handled, errors are handled without warning caller
-pub fn handle_commands(app: &mut App, tokens: &Vec<Token>) -> bool {
-    if tokens.is_empty() {
-        return false;
-    }
-    if let (Some(Token::Minus), Some(Token::Minus)) = (tokens.get(0), tokens.get(1)) {
-        return true;
+use crate::parser::{commands, general_parsing, syntax_tree, tokens::{SpannedToken, Token}};
+use super::{config::Panel, executor::Value, plot::{self, Plot}, session::{LineResult, PopupName, Session, StateChange}, state::{Context, FunctionDef}, user_scripts::ScriptError, workspace};
+
+type CommandFn = fn(&mut Session, &[Token]) -> LineResult;
+
+// what each command in parser::commands::COMMAND_WORDS runs
+static COMMANDS: &[(&str, CommandFn)] = &[
+    ("clear", |_, _| LineResult::StateChange(StateChange::ClearHistory)),
+    ("quit", quit),
+    ("exit", quit),
+    ("reload", reload_session),
+    ("use", use_scope),
+    ("load", load_script),
+    ("def", declare_function),
+    ("config", update_config),
+    ("show", show_page),
+    ("panel", toggle_panel),
+    ("save", save_workspace),
+    ("restore", restore_workspace),
+    ("plot", plot),
+];
+
+// returns None when the line isn't a command
+pub fn handle_commands(session: &mut Session, tokens: &[Token]) -> Option<LineResult> {
+    if let (Some(Token::Minus), Some(Token::Minus)) = (tokens.first(), tokens.get(1)) {
+        return Some(LineResult::Empty);
     }
 
-    let mut is_handled = true;
-
-    match tokens[0] {
-        Token::Identifier(ref word) => match word.as_str() {
-            "clear" => clear_history(app),
-            "quit" | "exit" => exit_app(app),
-            "reload" => reload_app(app, tokens),
-            "use" => use_scope(app, tokens),
-            "load" => load_script(app, tokens),
-            "def" => declare_function(app, tokens),
-            "config" => update_config(app, tokens),
-            "show" => show_page(app, tokens),
-            "panel" => toggle_panel(app, tokens),
-            _ => is_handled = false,
-        },
-        _ => is_handled = false,
+    let command = match tokens.first() {
+        Some(Token::Identifier(word)) => COMMANDS.iter().find(|(name, _)| name == word),
+        _ => None,
     };
-
-    is_handled
+    command.map(|(_, run)| run(session, tokens))
 }
 
-fn clear_history(app: &mut App) {
-    app.context.history.clear();
-    app.context.history_scroll = 0;
+pub fn is_command(tokens: &[Token]) -> bool {
+    matches!(tokens.first(), Some(Token::Identifier(word)) if commands::is_command(word))
 }
 
-fn exit_app(app: &mut App) {
-    app.exit = true;
+// nothing can be shown after quitting, so a failed autosave is dropped
+fn quit(session: &mut Session, _: &[Token]) -> LineResult {
+    if session.config.autosave {
+        let _ = workspace::save(&session.context, workspace::AUTOSAVE_NAME);
+    }
+    LineResult::StateChange(StateChange::Quit)
 }
 
-fn reload_app(app: &mut App, tokens: &Vec<Token>) {
+fn reload_session(session: &mut Session, tokens: &[Token]) -> LineResult {
     let is_raw = tokens.get(1).is_some_and(|token| token.is_from_str("raw"));
-    app.context = Context::default();
+    session.context = Default::default();
 
-    if !is_raw {
-        let _ = app.run_script("init");
-    }
+    let init_lines = match is_raw {
+        true => Vec::new(),
+        false => session.run_script("init").unwrap_or_default(),
+    };
+    LineResult::StateChange(StateChange::Reload(init_lines))
 }
 
-fn use_scope(app: &mut App, tokens: &Vec<Token>) {
+fn use_scope(session: &mut Session, tokens: &[Token]) -> LineResult {
+    let context = &mut session.context;
     match tokens.get(1) {
         Some(Token::Identifier(scope_name)) => {
             let scope_name = scope_name.to_string() + ".";
 
-            let new_vars: Vec<_> = app.context.vars.iter().filter_map(|(name, value)| {
+            let new_vars: Vec<_> = context.vars.iter().filter_map(|(name, value)| {
                 match name.starts_with(&scope_name) {
                     true => Some((name[scope_name.len()..].to_string(), value.clone())),
                     false => None,
@@ -63,10 +70,10 @@ fn use_scope(app: &mut App, tokens: &Vec<Token>) {
             }).collect();
 
             for (name, value) in new_vars {
-                app.context.set_var(name, value);
+                context.set_var(name, value);
             }
 
-            let new_functions: Vec<_> = app.context.functions.iter().filter_map(|function_def| {
+            let new_functions: Vec<_> = context.functions.iter().filter_map(|function_def| {
                 match function_def.name.starts_with(&scope_name) {
                     true => Some(FunctionDef {
                         name: function_def.name[scope_name.len()..].to_string(),
@@ -78,104 +85,244 @@ fn use_scope(app: &mut App, tokens: &Vec<Token>) {
             }).collect();
 
             for function_def in new_functions {
-                app.context.set_function(function_def);
+                context.set_function(function_def);
             }
+            LineResult::Empty
         },
-        _ => app.context.push_history_text("usage: use <scope>"),
+        _ => command_error("usage: use <scope>"),
     }
 }
 
-fn load_script(app: &mut App, tokens: &Vec<Token>) {
-    match tokens.get(1) {
-        Some(Token::Identifier(script_name)) => {
-            let file_path = script_name.replace(".", "/");
-            match app.run_script(&file_path) {
-                Ok(()) => (),
-                Err(ScriptError::NoConfigPath) => {
-                    app.context.push_history_text(
-                        "create a 'config' folder at (will put path here later) or next to the executable to use scripts"
-                    );
-                },
-                Err(ScriptError::ScriptNotFound(_)) => {
-                    match (tokens.get(2), tokens.get(3)) {
-                        (Some(Token::Div), Some(Token::Identifier(name2))) => {
-                            app.context.push_history_text(&format!("script paths use '.' instead of '/' ex: {script_name}.{name2}"));
-                        },
-                        _ => app.context.push_history_text("script not found"),
-                    }
-                },
-                Err(ScriptError::OsDoesNotSupportConfigDir) => {
-                    app.context.push_history_text(
-                        "can't locate config folder on your operating system, try removing config lines from your scripts"
-                    );
+fn load_script(session: &mut Session, tokens: &[Token]) -> LineResult {
+    let script_name = match tokens.get(1) {
+        Some(Token::Identifier(script_name)) => script_name,
+        _ => return command_error("usage: load <scriptname>"),
+    };
+    let file_path = script_name.replace(".", "/");
+    let msg = match session.run_script(&file_path) {
+        Ok(lines) => return LineResult::Script(lines),
+        Err(ScriptError::NoConfigPath) => {
+            "create a 'config' folder at (will put path here later) or next to the executable to use scripts".to_string()
+        },
+        Err(ScriptError::ScriptNotFound(_)) => {
+            match (tokens.get(2), tokens.get(3)) {
+                (Some(Token::Div), Some(Token::Identifier(name2))) => {
+                    format!("script paths use '.' instead of '/' ex: {script_name}.{name2}")
                 },
+                _ => "script not found".to_string(),
             }
         },
-        _ => app.context.push_history_msg("usage: load <scriptname>"),
-    }
+        Err(ScriptError::OsDoesNotSupportConfigDir) => {
+            "can't locate config folder on your operating system, try removing config lines from your scripts".to_string()
+        },
+        Err(e @ (ScriptError::WriteFailed(_) | ScriptError::NotADefinition(..))) => e.to_string(),
+    };
+    command_error(msg)
 }
 
-fn declare_function(app: &mut App, tokens: &Vec<Token>) {
+fn declare_function(session: &mut Session, tokens: &[Token]) -> LineResult {
     match general_parsing::parse_function_definition(tokens) {
-        Ok(function_definition) => app.context.set_function(function_definition),
-        Err(e) => app.context.push_history_msg(&e.to_string()),
+        Ok(function_definition) => {
+            session.context.set_function(function_definition);
+            LineResult::Empty
+        },
+        Err(e) => command_error(e.to_string()),
     }
 }
 
-fn update_config(app: &mut App, tokens: &Vec<Token>) {
-    let response = app.config.update_from_tokens(&tokens[1..]);
-    app.context.push_history_text(&response);
+fn save_workspace(session: &mut Session, tokens: &[Token]) -> LineResult {
+    match tokens.get(1) {
+        Some(Token::Identifier(name)) => match workspace::save(&session.context, name) {
+            Ok(msg) => LineResult::Message(msg),
+            Err(e) => command_error(e.to_string()),
+        },
+        _ => command_error("usage: save <name>"),
+    }
 }
 
-fn show_page(app: &mut App, tokens: &Vec<Token>) {
-    app.context.modal_scroll = 0;
+fn restore_workspace(session: &mut Session, tokens: &[Token]) -> LineResult {
     match tokens.get(1) {
+        Some(Token::Identifier(name)) => match workspace::restore(session, name) {
+            Ok(msg) => LineResult::Message(msg),
+            Err(ScriptError::ScriptNotFound(name)) => command_error(format!("no saved workspace called {name}")),
+            Err(e) => command_error(e.to_string()),
+        },
+        _ => command_error("usage: restore <name>"),
+    }
+}
+
+fn update_config(session: &mut Session, tokens: &[Token]) -> LineResult {
+    if tokens.get(1).is_some_and(|token| token.is_from_str("theme")) {
+        return update_theme(&tokens[2..]);
+    }
+    let response = match session.context.settings.update_from_tokens(&tokens[1..]) {
+        Some(response) => response,
+        None => session.config.update_from_tokens(&tokens[1..]),
+    };
+    match response {
+        Ok(msg) => LineResult::Message(msg),
+        Err(msg) => command_error(msg),
+    }
+}
+
+// colors belong to the frontend, so this only checks the color and passes it on
+fn update_theme(tokens: &[Token]) -> LineResult {
+    let option = match tokens.first() {
+        Some(Token::Identifier(option)) => option.clone(),
+        _ => return command_error("use 'show help' to show all theme options"),
+    };
+    let color = match tokens.get(1) {
+        Some(Token::Identifier(color_text) | Token::Number(color_text)) => u32::from_str_radix(color_text, 16).ok(),
+        _ => None,
+    };
+    match color {
+        Some(color) => LineResult::StateChange(StateChange::SetThemeColor(option, color)),
+        None => command_error("theme requires color in hex format: 'config theme text AABBCC'"),
+    }
+}
+
+fn show_page(_: &mut Session, tokens: &[Token]) -> LineResult {
+    let popup = match tokens.get(1) {
         Some(Token::Identifier(ident)) => match ident.as_str() {
-            "vars" => app.context.current_popup = Some(PopupName::Vars),
-            "functions" => app.context.current_popup = Some(PopupName::Functions),
-            "help" => app.context.current_popup = Some(PopupName::Help),
-            _ => app.context.push_history_text("usage: show <vars/functions/help>"),
+            "vars" => PopupName::Vars,
+            "functions" => PopupName::Functions,
+            "help" => PopupName::Help,
+            _ => return command_error("usage: show <vars/functions/help>"),
         },
-        _ => app.context.push_history_text("usage: show <vars/functions/help>"),
+        _ => return command_error("usage: show <vars/functions/help>"),
+    };
+    LineResult::StateChange(StateChange::ShowPopup(popup))
+}
+
+// plot f, g from -5 to 5 for functions, where the range is optional and can be any expression,
+// or plot data, hist or bar with values
+fn plot(session: &mut Session, tokens: &[Token]) -> LineResult {
+    // a function could be called data, hist or bar, so those only count when something
+    // follows that isn't part of a list of functions
+    let kind = match (tokens.get(1), tokens.get(2)) {
+        (Some(Token::Identifier(kind)), Some(next)) if *next != Token::Comma && !next.is_from_str("from") => kind.as_str(),
+        _ => "",
+    };
+    let plot = match kind {
+        "data" => plot_data(&session.context, &tokens[2..]),
+        "hist" => plot_histogram(&session.context, &tokens[2..]),
+        "bar" => command_value(&session.context, &tokens[2..]).and_then(|(label, value)| Plot::bars(&label, &value)),
+        _ => plot_functions(&session.context, tokens),
+    };
+    match plot {
+        Ok(plot) => LineResult::StateChange(StateChange::ShowPopup(PopupName::Plot(plot))),
+        Err(message) => command_error(message),
     }
 }
 
-fn toggle_panel(app: &mut App, tokens: &Vec<Token>) {
-    let err_msg = "usage: panel <vars/autocomplete/preview> <optional: on/off>";
+fn plot_functions(context: &Context, tokens: &[Token]) -> Result<Plot, String> {
+    let usage = || "usage: plot <functions> <optional: from <start> to <end>>, or plot <data/hist/bar> <values>".to_string();
+    let from = tokens.iter().position(|token| token.is_from_str("from")).unwrap_or(tokens.len());
+    let to = tokens.iter().position(|token| token.is_from_str("to")).unwrap_or(tokens.len());
 
-    let mut toggle_panel = |panel: Panel| {
-        match tokens.get(2) {
-            Some(Token::Identifier(ident)) => match ident.as_str() {
-                "on" => {
-                    if !app.config.panels.contains(&panel) {
-                        app.config.panels.push(panel);
-                    }
-                },
-                "off" => {
-                    if let Some(index) = app.config.panels.iter().position(|p| p == &panel) {
-                        app.config.panels.remove(index);
-                    }
-                },
-                _ => app.context.push_history_msg(err_msg),
-            },
-            None => {
-                let index = app.config.panels.iter().position(|p| p == &panel);
-                match index {
-                    Some(index) => {app.config.panels.remove(index);},
-                    None => {app.config.panels.push(panel);},
-                };
-            },
-            _ => app.context.push_history_msg(err_msg),
-        };
+    let mut functions = Vec::new();
+    for (i, token) in tokens[1..from].iter().enumerate() {
+        match (i % 2, token) {
+            (0, Token::Identifier(name)) => functions.push(name.clone()),
+            (1, Token::Comma) => {},
+            _ => return Err(usage()),
+        }
+    }
+    if functions.is_empty() || functions.len() * 2 != from {
+        return Err(usage());
+    }
+
+    let x_bounds = match (from < tokens.len(), to > from && to < tokens.len()) {
+        (false, _) => plot::DEFAULT_X_BOUNDS,
+        (true, false) => return Err(usage()),
+        (true, true) => [command_number(context, &tokens[from + 1..to])?, command_number(context, &tokens[to + 1..])?],
     };
+    Plot::functions(context, functions, x_bounds)
+}
 
-    match tokens.get(1) {
+// plot data M, or plot data M line to join the points up
+fn plot_data(context: &Context, tokens: &[Token]) -> Result<Plot, String> {
+    let (tokens, joined) = match tokens.split_last() {
+        Some((last, rest)) if !rest.is_empty() && last.is_from_str("line") => (rest, true),
+        Some((last, rest)) if !rest.is_empty() && last.is_from_str("scatter") => (rest, false),
+        _ => (tokens, false),
+    };
+    let (label, value) = command_value(context, tokens)?;
+    Plot::data(&label, &value, joined)
+}
+
+// plot hist v or plot hist v 20, a number can only be the bin count if it couldn't be part of the values
+fn plot_histogram(context: &Context, tokens: &[Token]) -> Result<Plot, String> {
+    let (tokens, bins) = match tokens.split_last() {
+        Some((Token::Number(bins), rest)) if matches!(rest.last(), Some(Token::Identifier(_) | Token::Number(_) | Token::CloseParen | Token::CloseBracket)) => {
+            let bins = bins.parse::<usize>().map_err(|_| "bins must be a whole number".to_string())?;
+            (rest, Some(bins))
+        },
+        _ => (tokens, None),
+    };
+    let (label, value) = command_value(context, tokens)?;
+    Plot::histogram(&label, &value, bins)
+}
+
+// an expression inside a command, with how it was written for labels. it runs in its own
+// context so nothing it assigns sticks around
+fn command_value(context: &Context, tokens: &[Token]) -> Result<(String, Value), String> {
+    let spanned_tokens = tokens.iter().cloned().map(SpannedToken::from).collect();
+    let expression = syntax_tree::generate_syntax_tree(spanned_tokens).map_err(|e| e.to_string())?;
+    let label = expression.to_string();
+    match Context::from_context(context).execute(expression) {
+        Ok(value) => Ok((label, value)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// like the 2 * pi in 'plot f from 0 to 2 * pi'
+fn command_number(context: &Context, tokens: &[Token]) -> Result<f64, String> {
+    match command_value(context, tokens)? {
+        (_, Value::Number(num)) if num.is_finite() => Ok(num),
+        (_, value) => Err(format!("expected a number, got {}", value.short_string())),
+    }
+}
+
+fn toggle_panel(_: &mut Session, tokens: &[Token]) -> LineResult {
+    let err_msg = command_error("usage: panel <vars/autocomplete/preview> <optional: on/off>");
+
+    let panel = match tokens.get(1) {
         Some(Token::Identifier(ident)) => match ident.as_str() {
-            "vars" => toggle_panel(Panel::Variables),
-            "autocomplete" => toggle_panel(Panel::Autocomplete),
-            "preview" => toggle_panel(Panel::ExpPreview),
-            _ => app.context.push_history_msg(err_msg),
+            "vars" => Panel::Variables,
+            "autocomplete" => Panel::Autocomplete,
+            "preview" => Panel::ExpPreview,
+            _ => return err_msg,
         },
-        _ => app.context.push_history_msg(err_msg),
+        _ => return err_msg,
     };
+
+    let turn_on = match tokens.get(2) {
+        Some(Token::Identifier(ident)) => match ident.as_str() {
+            "on" => Some(true),
+            "off" => Some(false),
+            _ => return err_msg,
+        },
+        None => None,
+        _ => return err_msg,
+    };
+    LineResult::StateChange(StateChange::TogglePanel(panel, turn_on))
+}
+
+// commands aren't expressions so there's nothing to underline
+fn command_error(message: impl Into<String>) -> LineResult {
+    LineResult::Error {message: message.into(), span: None}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::commands::COMMAND_WORDS;
+
+    #[test]
+    fn every_command_word_runs() {
+        let names: Vec<_> = COMMANDS.iter().map(|(name, _)| *name).collect();
+        let words: Vec<_> = COMMAND_WORDS.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, words);
+    }
 }
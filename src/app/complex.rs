@@ -0,0 +1,239 @@
+use std::{fmt, iter::Sum, ops::{Add, Div, Mul, Neg, Sub}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const I: Complex = Complex {re: 0.0, im: 1.0};
+
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex {re, im}
+    }
+
+    pub fn from_polar(r: f64, theta: f64) -> Complex {
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    pub fn is_real(self) -> bool {
+        self.im == 0.0
+    }
+
+    pub fn exp(self) -> Complex {
+        Complex::from_polar(self.re.exp(), self.im)
+    }
+
+    // principal branch, cut along the negative real axis
+    pub fn ln(self) -> Complex {
+        Complex::new(self.abs().ln(), self.arg())
+    }
+
+    pub fn sqrt(self) -> Complex {
+        if self.im == 0.0 {
+            return match self.re >= 0.0 {
+                true => Complex::new(self.re.sqrt(), 0.0),
+                false => Complex::new(0.0, (-self.re).sqrt()),
+            };
+        }
+        let r = self.abs();
+        let re = ((r + self.re) / 2.0).sqrt();
+        let im = ((r - self.re) / 2.0).sqrt();
+        Complex::new(re, im.copysign(self.im))
+    }
+
+    pub fn powi(self, n: i32) -> Complex {
+        let mut base = match n < 0 {
+            true => Complex::from(1.0) / self,
+            false => self,
+        };
+        let mut exponent = n.unsigned_abs();
+        let mut output = Complex::from(1.0);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                output = output * base;
+            }
+            base = base * base;
+            exponent >>= 1;
+        }
+        output
+    }
+
+    pub fn powc(self, exponent: Complex) -> Complex {
+        if exponent.is_real() && exponent.re.fract() == 0.0 && exponent.re.abs() <= i32::MAX as f64 {
+            return self.powi(exponent.re as i32);
+        }
+        if self == Complex::default() {
+            return Complex::default();
+        }
+        if self.is_real() && exponent.is_real() && self.re < 0.0 {
+            return negative_real_pow(self.re, exponent.re);
+        }
+        (exponent * self.ln()).exp()
+    }
+
+    pub fn sin(self) -> Complex {
+        Complex::new(self.re.sin() * self.im.cosh(), self.re.cos() * self.im.sinh())
+    }
+
+    pub fn cos(self) -> Complex {
+        Complex::new(self.re.cos() * self.im.cosh(), -self.re.sin() * self.im.sinh())
+    }
+
+    pub fn tan(self) -> Complex {
+        self.sin() / self.cos()
+    }
+
+    pub fn asin(self) -> Complex {
+        // -i * ln(iz + sqrt(1 - z^2))
+        let root = (Complex::from(1.0) - self * self).sqrt();
+        -Complex::I * (Complex::I * self + root).ln()
+    }
+
+    pub fn acos(self) -> Complex {
+        Complex::from(std::f64::consts::FRAC_PI_2) - self.asin()
+    }
+
+    pub fn atan(self) -> Complex {
+        // i/2 * ln((i + z) / (i - z))
+        Complex::new(0.0, 0.5) * ((Complex::I + self) / (Complex::I - self)).ln()
+    }
+}
+
+// (-x)^p for real p, kept exact when p is a multiple of 1/2 so (-1)^0.5 is i and not 6e-17 + i
+fn negative_real_pow(base: f64, exponent: f64) -> Complex {
+    let magnitude = (-base).powf(exponent);
+    let half_turns = (exponent * 2.0).rem_euclid(4.0);
+    match half_turns.fract() == 0.0 {
+        true => match half_turns as u8 {
+            0 => Complex::new(magnitude, 0.0),
+            1 => Complex::new(0.0, magnitude),
+            2 => Complex::new(-magnitude, 0.0),
+            _ => Complex::new(0.0, -magnitude),
+        },
+        false => Complex::from_polar(magnitude, exponent * std::f64::consts::PI),
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Complex::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, rhs: Complex) -> Complex {
+        if rhs.is_real() {
+            return Complex::new(self.re / rhs.re, self.im / rhs.re);
+        }
+        let denom = rhs.re * rhs.re + rhs.im * rhs.im;
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / denom,
+            (self.im * rhs.re - self.re * rhs.im) / denom,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl Sum for Complex {
+    fn sum<I: Iterator<Item = Complex>>(iter: I) -> Complex {
+        iter.fold(Complex::default(), |acc, z| acc + z)
+    }
+}
+
+// rectangular form that the tokenizer can read back in, ex: 3+4i, -2.5i
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_real() {
+            return write!(f, "{}", self.re);
+        }
+        match (self.re == 0.0, self.im.is_sign_negative()) {
+            (true, _) => write!(f, "{}i", self.im),
+            (false, true) => write!(f, "{}-{}i", self.re, -self.im),
+            (false, false) => write!(f, "{}+{}i", self.re, self.im),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Complex, b: Complex) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Complex::new(3.0, 4.0);
+        let b = Complex::new(1.0, -2.0);
+        assert_eq!(a * b, Complex::new(11.0, -2.0));
+        assert!(close(a / b * b, a));
+        assert_eq!(a.abs(), 5.0);
+    }
+    #[test]
+    fn powers() {
+        assert_eq!(Complex::I.powi(2), Complex::new(-1.0, 0.0));
+        assert_eq!(negative_real_pow(-1.0, 0.5), Complex::I);
+        assert_eq!(negative_real_pow(-8.0, 1.0 / 3.0).abs(), 2.0);
+        assert!(close(Complex::new(-4.0, 0.0).sqrt(), Complex::new(0.0, 2.0)));
+    }
+    #[test]
+    fn inverse_trig() {
+        let z = Complex::new(0.3, -1.2);
+        assert!(close(z.sin().asin(), z));
+        assert!(close(z.cos().acos(), z));
+        assert!(close(z.tan().atan(), z));
+        assert!(close(Complex::from(2.0).asin().sin(), Complex::from(2.0)));
+    }
+    #[test]
+    fn display() {
+        assert_eq!(Complex::new(3.0, 4.0).to_string(), "3+4i");
+        assert_eq!(Complex::new(3.0, -4.0).to_string(), "3-4i");
+        assert_eq!(Complex::new(0.0, -2.5).to_string(), "-2.5i");
+    }
+}
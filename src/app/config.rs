@@ -1,163 +1,96 @@
-use ratatui::style::Color;
-
 use crate::parser::tokens::Token;
 
 use super::user_scripts::{self, DEFAULT_INIT_SCRIPT_CONTENT};
 
+const MAX_HISTORY_SIZE: usize = 100_000;
+
 impl Config {
-    pub fn update_from_tokens(&mut self, input: &[Token]) -> String {
-        let config_opt = match input.get(0) {
+    pub fn update_from_tokens(&mut self, input: &[Token]) -> Result<String, String> {
+        let config_opt = match input.first() {
             Some(Token::Identifier(config_opt)) => config_opt.as_str(),
-            _ => return "use 'show help' to get a complete list of config options".to_string(),
+            _ => return Err("use 'show help' to get a complete list of config options".to_string()),
         };
         match config_opt {
             "cursor" => self.update_cursor(&input[1..]),
             "script" => self.update_script(&input[1..]),
-            "trig" => self.update_trig(&input[1..]),
-            "theme" => self.update_theme(&input[1..]),
-            _ => format!("unknown option {}, use 'show help' to get a complete list of config options", config_opt),
+            "history" => self.update_history(&input[1..]),
+            "autosave" => self.update_autosave(&input[1..]),
+            _ => Err(format!("unknown option {}, use 'show help' to get a complete list of config options", config_opt)),
         }
     }
 
-    fn update_cursor(&mut self, input: &[Token]) -> String {
-        match input.get(0) {
+    fn update_cursor(&mut self, input: &[Token]) -> Result<String, String> {
+        match input.first() {
             Some(token) => {
                 self.cursor = token.to_string();
-                format!("set cursor to {}", token.to_string())
+                Ok(format!("set cursor to {}", token))
             },
             None => {
                 self.cursor = String::new();
-                "cleared cursor".to_string()
+                Ok("cleared cursor".to_string())
             }
         }
     }
 
-    fn update_script(&mut self, input: &[Token]) -> String {
-        let script_opt = match input.get(0) {
+    fn update_script(&mut self, input: &[Token]) -> Result<String, String> {
+        let script_opt = match input.first() {
             Some(Token::Identifier(script_opt)) => script_opt.as_str(),
             _ => "",
         };
-        let config_path = match user_scripts::guessed_config_path() {
-            Ok(config_path) => config_path,
-            Err(e) => return e.to_string(),
-        };
+        let config_path = user_scripts::guessed_config_path().map_err(|e| e.to_string())?;
         match script_opt {
             "create" => {
                 if config_path.exists() {
-                    return format!("config already exists at {}", config_path.to_string_lossy());
+                    return Err(format!("config already exists at {}", config_path.to_string_lossy()));
                 }
 
                 let err = std::fs::create_dir(&config_path)
                     .and_then(|_| std::fs::write(config_path.join("init.txt"), DEFAULT_INIT_SCRIPT_CONTENT));
 
                 match err {
-                    Ok(()) => format!("created config at {}", config_path.to_string_lossy()),
-                    Err(e) => e.to_string(),
+                    Ok(()) => Ok(format!("created config at {}", config_path.to_string_lossy())),
+                    Err(e) => Err(e.to_string()),
                 }
             },
             "show" => match config_path.exists() {
-                true => format!("config exists at {}", config_path.to_string_lossy()),
-                false => format!(
+                true => Ok(format!("config exists at {}", config_path.to_string_lossy())),
+                false => Ok(format!(
                     "no config exists, use 'config script create' or create a folder at {}",
                     config_path.to_string_lossy(),
-                ),
+                )),
             },
             "open" => {
                 // TODO
-                return "not implemented".to_string();
+                Err("not implemented".to_string())
             },
-            _ => "script options: config script <create/show/open>".to_string()
+            _ => Err("script options: config script <create/show/open>".to_string())
         }
     }
 
-    fn update_trig(&mut self, input: &[Token]) -> String {
-        let trig_opt = match input.get(0) {
-            Some(Token::Identifier(trig_opt)) => trig_opt.as_str(),
-            _ => "",
-        };
-        match trig_opt {
-            "deg" => {
-                self.is_radians = false;
-                "trig mode set to deg".to_string()
+    fn update_history(&mut self, input: &[Token]) -> Result<String, String> {
+        match input.first() {
+            Some(Token::Number(size)) => match size.parse::<usize>() {
+                Ok(size) if size <= MAX_HISTORY_SIZE => {
+                    self.history_size = size;
+                    Ok(format!("keeping the last {size} lines of history"))
+                },
+                _ => Err(format!("history size must be a whole number up to {MAX_HISTORY_SIZE}")),
             },
-            "rad" => {
-                self.is_radians = true;
-                "trig mode set to rad".to_string()
-            },
-            "" => {
-                self.is_radians = !self.is_radians;
-                format!(
-                    "trig mode set to {}",
-                    match self.is_radians {
-                        true => "rad",
-                        false => "deg",
-                    }
-                )
-            }
-            _ => "trig mode options are 'deg', 'rad'".to_string(),
+            None => Ok(format!("keeping the last {} lines of history", self.history_size)),
+            _ => Err("usage: config history <lines>".to_string()),
         }
     }
 
-    fn update_theme(&mut self, input: &[Token]) -> String {
-        let theme_opt = match input.get(0) {
-            Some(Token::Identifier(theme_opt)) => theme_opt,
-            _ => return "use 'show help' to show all theme options".to_string(),
-        };
-        let color_text = match input.get(1) {
-            Some(Token::Identifier(color_text)) => color_text,
-            Some(Token::Number(color_text)) => color_text,
-            _ => return "theme requires color in hex format: 'config theme text AABBCC'".to_string(),
-        };
-        let color = match u32::from_str_radix(color_text, 16) {
-            Ok(color) => Color::from_u32(color),
-            Err(_) => return "theme requires color in hex format: 'config theme text AABBCC'".to_string(),
+    fn update_autosave(&mut self, input: &[Token]) -> Result<String, String> {
+        match input.first() {
+            Some(token) if token.is_from_str("on") => self.autosave = true,
+            Some(token) if token.is_from_str("off") => self.autosave = false,
+            None => self.autosave = !self.autosave,
+            _ => return Err("usage: config autosave <on/off>".to_string()),
         };
-        match theme_opt.as_str() {
-            "number" => self.theme.number = color,
-            "identifier" => self.theme.identifier = color,
-            "unknownIdentifier" => self.theme.unknown_identifier = color,
-            "command" => self.theme.command = color,
-            "operator" => self.theme.operator = color,
-            "inputBg" => self.theme.input_line_bg = color,
-            "resultBg" => self.theme.result_line_bg = color,
-            "currentBg" => self.theme.current_line_bg = color,
-            "text" => self.theme.text = color,
-            "cursor" => self.theme.cursor = color,
-            _ => return "unknown theme option".to_string(),
-        };
-        return "theme option updated".to_string();
-    }
-}
-
-#[derive(Debug)]
-pub struct Theme {
-    pub number: Color,
-    pub identifier: Color,
-    pub unknown_identifier: Color,
-    pub command: Color,
-    pub operator: Color,
-    pub input_line_bg: Color,
-    pub result_line_bg: Color,
-    pub current_line_bg: Color,
-    pub divider: Color,
-    pub text: Color,
-    pub cursor: Color,
-}
-
-impl Default for Theme {
-    fn default() -> Self {
-        Theme {
-            number: Color::Rgb(230, 134, 57),
-            identifier: Color::Rgb(240, 240, 240),
-            unknown_identifier: Color::Rgb(180, 180, 180),
-            command: Color::Rgb(255, 87, 87),
-            operator: Color::Rgb(232, 208, 151),
-            input_line_bg: Color::Rgb(60, 60, 60),
-            result_line_bg: Color::Rgb(40, 40, 40),
-            current_line_bg: Color::Rgb(60, 60, 60),
-            divider: Color::White,
-            text: Color::Rgb(240, 240, 240),
-            cursor: Color::White,
+        match self.autosave {
+            true => Ok("vars and functions will be saved on quit, use 'restore autosave' to bring them back".to_string()),
+            false => Ok("autosave turned off".to_string()),
         }
     }
 }
@@ -171,21 +104,19 @@ pub enum Panel {
 
 #[derive(Debug)]
 pub struct Config {
-    pub is_radians: bool,
     pub cursor: String,
-    pub theme: Theme,
-    pub panels: Vec<Panel>,
     pub tab_width: usize,
+    pub history_size: usize,
+    pub autosave: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
-            is_radians: false,
             cursor: "█".to_string(),
-            theme: Theme::default(),
-            panels: vec![Panel::Autocomplete, Panel::Variables],
             tab_width: 4,
+            history_size: 1000,
+            autosave: false,
         }
     }
 }
@@ -0,0 +1,325 @@
+use std::{cell::Cell, rc::Rc, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::parser::tokens::Token;
+
+use super::{complex::Complex, executor::{RuntimeError, Value}, state::Context};
+
+// every nested call uses real stack space, the default stays safe on a normal 8MB main thread
+// and lcalc runs on a bigger stack so the limit can be raised
+const DEFAULT_CALL_DEPTH: usize = 250;
+const MAX_CALL_DEPTH_LIMIT: usize = 10000;
+// previews run on every keypress, so they give up early instead of stalling the ui
+const PREVIEW_CALL_DEPTH: usize = 100;
+const PREVIEW_CALLS: usize = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleMode {
+    Radians,
+    Degrees,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComplexFormat {
+    Rectangular,
+    Polar,
+}
+
+// settings that change how expressions are evaluated and displayed, these
+// live on the context rather than the config so builtins can read them
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub angle_mode: AngleMode,
+    pub precision: Option<usize>,
+    pub complex_format: ComplexFormat,
+    pub max_call_depth: usize,
+    // shared between a context and its function contexts so calls keep advancing the same sequence
+    rng_state: Rc<Cell<u64>>,
+    // calls a preview has left, shared the same way. None outside previews
+    preview_calls: Option<Rc<Cell<usize>>>,
+}
+
+// what builtin functions get to see of the context that called them
+pub struct Environment<'a> {
+    pub settings: &'a Settings,
+    // lets builtins like std.integrate call the functions they're given
+    context: Option<&'a Context<'a>>,
+}
+
+impl<'a> Environment<'a> {
+    pub fn new(settings: &Settings) -> Environment<'_> {
+        Environment {settings, context: None}
+    }
+
+    pub fn with_context(context: &'a Context<'a>) -> Environment<'a> {
+        Environment {settings: &context.settings, context: Some(context)}
+    }
+
+    // a function passed in as an argument, called the same way as one written in the line
+    pub fn call(&self, function: &Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        match (function, self.context) {
+            (Value::Function(function_def), Some(context)) => context.call_definition(function_def, args),
+            (Value::Function(function_def), None) => Err(RuntimeError::BuiltinFuncErr(format!("{} can't be called here", function_def.name))),
+            (value, _) => Err(RuntimeError::BuiltinFuncErr(format!("expected a function, got {}", value.short_string()))),
+        }
+    }
+
+    pub fn angle_to_radians(&self, angle: f64) -> f64 {
+        match self.settings.angle_mode {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_radians(),
+        }
+    }
+
+    pub fn angle_from_radians(&self, angle: f64) -> f64 {
+        match self.settings.angle_mode {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_degrees(),
+        }
+    }
+
+    pub fn complex_to_radians(&self, angle: Complex) -> Complex {
+        match self.settings.angle_mode {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle * Complex::from(1f64.to_radians()),
+        }
+    }
+
+    pub fn complex_from_radians(&self, angle: Complex) -> Complex {
+        match self.settings.angle_mode {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle * Complex::from(1f64.to_degrees()),
+        }
+    }
+
+    // uniform in [0, 1)
+    pub fn random(&self) -> f64 {
+        // splitmix64
+        let state = self.settings.rng_state.get().wrapping_add(0x9E3779B97F4A7C15);
+        self.settings.rng_state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Settings {
+    // returns None when the option belongs to the ui config instead
+    pub fn update_from_tokens(&mut self, input: &[Token]) -> Option<Result<String, String>> {
+        let config_opt = match input.first() {
+            Some(Token::Identifier(config_opt)) => config_opt.as_str(),
+            _ => return None,
+        };
+        let response = match config_opt {
+            "trig" => self.update_trig(&input[1..]),
+            "complex" => self.update_complex(&input[1..]),
+            "precision" => self.update_precision(&input[1..]),
+            "seed" => self.update_seed(&input[1..]),
+            "depth" => self.update_depth(&input[1..]),
+            _ => return None,
+        };
+        Some(response)
+    }
+
+    fn update_trig(&mut self, input: &[Token]) -> Result<String, String> {
+        let trig_opt = match input.first() {
+            Some(Token::Identifier(trig_opt)) => trig_opt.as_str(),
+            _ => "",
+        };
+        match trig_opt {
+            "deg" => self.angle_mode = AngleMode::Degrees,
+            "rad" => self.angle_mode = AngleMode::Radians,
+            "" => {
+                self.angle_mode = match self.angle_mode {
+                    AngleMode::Radians => AngleMode::Degrees,
+                    AngleMode::Degrees => AngleMode::Radians,
+                };
+            },
+            _ => return Err("trig mode options are 'deg', 'rad'".to_string()),
+        };
+        Ok(format!("trig mode set to {}", self.angle_mode_name()))
+    }
+
+    fn update_complex(&mut self, input: &[Token]) -> Result<String, String> {
+        let complex_opt = match input.first() {
+            Some(Token::Identifier(complex_opt)) => complex_opt.as_str(),
+            _ => "",
+        };
+        match complex_opt {
+            "rect" => {
+                self.complex_format = ComplexFormat::Rectangular;
+                Ok("complex numbers shown as a+bi".to_string())
+            },
+            "polar" => {
+                self.complex_format = ComplexFormat::Polar;
+                Ok("complex numbers shown as r∠θ".to_string())
+            },
+            _ => Err("complex display options are 'rect', 'polar'".to_string()),
+        }
+    }
+
+    // a copy with its own random sequence starting where this one is, so drawing from it uses nothing up here
+    pub fn detached(&self) -> Settings {
+        Settings {
+            rng_state: Rc::new(Cell::new(self.rng_state.get())),
+            ..self.clone()
+        }
+    }
+
+    // detached, and limited in how deep and how many calls it can make
+    pub fn for_preview(&self) -> Settings {
+        Settings {
+            preview_calls: Some(Rc::new(Cell::new(PREVIEW_CALLS))),
+            ..self.detached()
+        }
+    }
+
+    // counts a call, true once a preview has gone too deep or made too many of them
+    pub fn preview_exhausted(&self, call_depth: usize) -> bool {
+        match &self.preview_calls {
+            Some(calls) if call_depth > PREVIEW_CALL_DEPTH || calls.get() == 0 => true,
+            Some(calls) => {
+                calls.set(calls.get() - 1);
+                false
+            },
+            None => false,
+        }
+    }
+
+    fn update_precision(&mut self, input: &[Token]) -> Result<String, String> {
+        match input.first() {
+            Some(Token::Number(digits)) => match digits.parse::<usize>() {
+                Ok(digits) if (1..=17).contains(&digits) => {
+                    self.precision = Some(digits);
+                    Ok(format!("showing {digits} significant digits"))
+                },
+                _ => Err("precision must be a whole number from 1 to 17".to_string()),
+            },
+            None => {
+                self.precision = None;
+                Ok("showing full precision".to_string())
+            },
+            _ => Err("usage: config precision <digits>".to_string()),
+        }
+    }
+
+    fn update_seed(&mut self, input: &[Token]) -> Result<String, String> {
+        match input.first() {
+            Some(Token::Number(seed)) => match seed.parse::<u64>() {
+                Ok(seed) => {
+                    self.rng_state.set(seed);
+                    Ok(format!("random seed set to {seed}"))
+                },
+                Err(_) => Err("seed must be a whole number".to_string()),
+            },
+            _ => Err("usage: config seed <number>".to_string()),
+        }
+    }
+
+    fn update_depth(&mut self, input: &[Token]) -> Result<String, String> {
+        match input.first() {
+            Some(Token::Number(depth)) => match depth.parse::<usize>() {
+                Ok(depth) if (1..=MAX_CALL_DEPTH_LIMIT).contains(&depth) => {
+                    self.max_call_depth = depth;
+                    Ok(format!("function calls can nest {depth} deep"))
+                },
+                _ => Err(format!("depth must be a whole number from 1 to {MAX_CALL_DEPTH_LIMIT}")),
+            },
+            None => Ok(format!("function calls can nest {} deep", self.max_call_depth)),
+            _ => Err("usage: config depth <calls>".to_string()),
+        }
+    }
+
+    pub fn angle_mode_name(&self) -> &'static str {
+        match self.angle_mode {
+            AngleMode::Radians => "rad",
+            AngleMode::Degrees => "deg",
+        }
+    }
+
+    pub fn format_number(&self, num: f64) -> String {
+        match self.precision {
+            Some(digits) => format_significant(num, digits),
+            None => num.to_string(),
+        }
+    }
+
+    pub fn format_complex(&self, num: Complex) -> String {
+        match self.complex_format {
+            ComplexFormat::Rectangular => match (num.is_real(), num.re == 0.0, num.im.is_sign_negative()) {
+                (true, _, _) => self.format_number(num.re),
+                (false, true, _) => format!("{}i", self.format_number(num.im)),
+                (false, false, true) => format!("{}-{}i", self.format_number(num.re), self.format_number(-num.im)),
+                (false, false, false) => format!("{}+{}i", self.format_number(num.re), self.format_number(num.im)),
+            },
+            ComplexFormat::Polar => {
+                let angle = Environment::new(self).angle_from_radians(num.arg());
+                let unit = match self.angle_mode {
+                    AngleMode::Radians => "",
+                    AngleMode::Degrees => "°",
+                };
+                format!("{}∠{}{unit}", self.format_number(num.abs()), self.format_number(angle))
+            },
+        }
+    }
+}
+
+fn format_significant(num: f64, digits: usize) -> String {
+    if num == 0.0 || !num.is_finite() {
+        return num.to_string();
+    }
+    let magnitude = num.abs().log10().floor() as i32;
+    if !(-5..15).contains(&magnitude) {
+        return format!("{:.*e}", digits.saturating_sub(1), num);
+    }
+    let decimals = (digits as i32 - 1 - magnitude).max(0) as usize;
+    let scale = 10f64.powi(digits as i32 - 1 - magnitude);
+    let rounded = (num * scale).round() / scale;
+    let text = format!("{:.*}", decimals, rounded);
+    match text.contains('.') {
+        true => text.trim_end_matches('0').trim_end_matches('.').to_string(),
+        false => text,
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0);
+        Settings {
+            angle_mode: AngleMode::Radians,
+            precision: None,
+            complex_format: ComplexFormat::Rectangular,
+            max_call_depth: DEFAULT_CALL_DEPTH,
+            rng_state: Rc::new(Cell::new(seed)),
+            preview_calls: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn significant_digits() {
+        assert_eq!(format_significant(1.23456789, 4), "1.235");
+        assert_eq!(format_significant(1234567.0, 4), "1235000");
+        assert_eq!(format_significant(0.000123456, 2), "0.00012");
+        assert_eq!(format_significant(2.0, 6), "2");
+        assert_eq!(format_significant(6.02e23, 3), "6.02e23");
+    }
+
+    #[test]
+    fn seeded_random() {
+        let mut settings = Settings::default();
+        settings.update_from_tokens(&[Token::Identifier("seed".to_string()), Token::Number("7".to_string())]);
+        let first = Environment::new(&settings).random();
+        settings.update_from_tokens(&[Token::Identifier("seed".to_string()), Token::Number("7".to_string())]);
+        assert_eq!(Environment::new(&settings).random(), first);
+        assert!((0.0..1.0).contains(&first));
+    }
+}
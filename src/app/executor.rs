@@ -1,15 +1,28 @@
 
-use std::{error::Error, fmt, iter};
+use std::{error::Error, fmt, iter::{self, Sum}, ops::Mul, rc::Rc};
 
-use crate::{app::state::{Context, FunctionBody}, parser::{highlighting::{HighlightToken, HighlightTokenType}, syntax_tree::Expression, tokens::Token}};
+use crate::{app::state::{Context, FunctionBody, FunctionDef, LAMBDA_NAME}, parser::{highlighting::{get_highlight_tokens, HighlightToken, HighlightTokenType}, syntax_tree::{Expression, ExpressionKind}, tokens::{Span, Token}}};
+use super::{complex::Complex, environment::{Environment, Settings}, linalg};
 
 type Num = f64;
 type MatrixBody = Vec<Vec<Num>>;
+type ComplexMatrixBody = Vec<Vec<Complex>>;
+
+// calls kept from each end of the trace when it gets too long to show
+const SHOWN_CALLS: usize = 3;
+
+// stops something like 1:1e12 or std.rand(1e12) from eating all of the memory
+pub const MAX_VECTOR_LEN: usize = 10_000_000;
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Number(Num),
+    Complex(Complex),
     Matrix(MatrixBody),
+    ComplexMatrix(ComplexMatrixBody),
+    // a lambda or a function used by name without calling it, these can be kept in
+    // variables and handed to builtins like std.map or std.integrate
+    Function(Rc<FunctionDef>),
 }
 
 #[derive(Debug)]
@@ -22,8 +35,14 @@ pub enum RuntimeError {
     MatrixUnevenColumns(usize, usize),
     NestedMatrix,
     IncompatibleMatrices(usize, usize, usize, usize),
+    ShapeMismatch(String, usize, usize, usize, usize),
     WrongNumFunctionArgs{fname: String, expected: usize, got: usize},
     BuiltinFuncErr(String),
+    InvalidIndex(String),
+    IndexOutOfBounds{index: usize, len: usize, dimension: &'static str},
+    CallDepthExceeded{limit: usize, calls: Vec<String>}, // calls go from innermost to outermost
+    PreviewLimit,
+    At(Box<RuntimeError>, Span), // where in the line the error came from
 }
 
 impl fmt::Display for RuntimeError {
@@ -37,57 +56,91 @@ impl fmt::Display for RuntimeError {
             RuntimeError::MatrixUnevenColumns(col1, col2) => write!(f, "matrix columns must be equal length, found {} and {}", col1, col2),
             RuntimeError::NestedMatrix => write!(f, "nested matrices not supported"),
             RuntimeError::IncompatibleMatrices(m1, n1, m2, n2) => write!(f, "cannot multiply {m1}x{n1} with {m2}x{n2}"),
+            RuntimeError::ShapeMismatch(op, m1, n1, m2, n2) => write!(f, "shapes {m1}x{n1} and {m2}x{n2} don't match for '{op}'"),
             RuntimeError::WrongNumFunctionArgs { fname, expected, got } => write!(f, "{fname} expected {expected} arguments but got {got}"),
             RuntimeError::BuiltinFuncErr(st) => write!(f, "{st}"),
+            RuntimeError::InvalidIndex(st) => write!(f, "invalid index: {st}"),
+            RuntimeError::IndexOutOfBounds { index, len, dimension } => {
+                write!(f, "index {index} is out of bounds, there are only {len} {dimension}")
+            },
+            RuntimeError::CallDepthExceeded { limit, calls } => {
+                write!(f, "function calls nested more than {limit} deep (see config depth)")?;
+                if calls.len() <= 2 * SHOWN_CALLS {
+                    return write!(f, ": {}", calls.iter().rev().cloned().collect::<Vec<_>>().join(" -> "));
+                }
+                let outer: Vec<_> = calls.iter().rev().take(SHOWN_CALLS).cloned().collect();
+                let inner: Vec<_> = calls.iter().take(SHOWN_CALLS).rev().cloned().collect();
+                let skipped = calls.len() - 2 * SHOWN_CALLS;
+                write!(f, ": {} -> ({skipped} more calls) -> {}", outer.join(" -> "), inner.join(" -> "))
+            },
+            RuntimeError::PreviewLimit => write!(f, "too much work to preview, press enter to run it"),
+            RuntimeError::At(err, _) => write!(f, "{err}"),
         }
     }
 }
 
 impl Error for RuntimeError {}
 
+impl RuntimeError {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            RuntimeError::At(_, span) => Some(*span),
+            _ => None,
+        }
+    }
+
+    // the innermost expression that failed keeps its span
+    fn at(self, span: Span) -> RuntimeError {
+        match self {
+            RuntimeError::At(..) => self,
+            err => RuntimeError::At(Box::new(err), span),
+        }
+    }
+
+    fn without_span(self) -> RuntimeError {
+        match self {
+            RuntimeError::At(err, _) => *err,
+            err => err,
+        }
+    }
+}
+
 // might pull out operator implementation and implement on Value enum if convenient in the future
 impl Context<'_> {
     pub fn execute(&mut self, expression: Expression) -> Result<Value, RuntimeError> {
-        match expression {
-            Expression::Number(st) => match st.parse::<Num>() {
+        let span = expression.span;
+        self.execute_kind(expression.kind).map_err(|err| err.at(span))
+    }
+
+    fn execute_kind(&mut self, kind: ExpressionKind) -> Result<Value, RuntimeError> {
+        match kind {
+            ExpressionKind::Number(st) => match st.parse::<Num>() {
                 Ok(num) => Ok(Value::Number(num)),
-                Err(_) => Err(RuntimeError::BadNumber(st)),
+                Err(_) => parse_imaginary(&st)
+                    .map(|num| Value::Complex(Complex::new(0.0, num)).normalized())
+                    .ok_or(RuntimeError::BadNumber(st)),
             }
-            Expression::Identifier(identifier) => match self.get_var(&identifier) {
+            ExpressionKind::Identifier(identifier) => match self.get_var(&identifier) {
                 Some(value) => Ok(value.clone()),
-                None => Err(RuntimeError::UnknownIdentifier(identifier.clone()))
-            },
-            Expression::Unary(op, input) => match op {
-                Token::Minus => match self.execute(*input)? {
-                    Value::Number(num) => Ok(Value::Number(-num)),
-                    Value::Matrix(mut mat) => {
-                        for vec in mat.iter_mut() {
-                            for num in vec {
-                                *num = - *num;
-                            }
-                        }
-                        Ok(Value::Matrix(mat))
-                    }
+                None => match (identifier.as_str(), self.get_function(&identifier)) {
+                    ("i" | "j", _) => Ok(Value::Complex(Complex::I)),
+                    (_, Some(function_def)) => Ok(Value::Function(Rc::new(function_def.clone()))),
+                    _ => Err(RuntimeError::UnknownIdentifier(identifier.clone())),
                 },
-                _ => Err(RuntimeError::ParserFailure(format!("{:?} of {:?}", op, input)))
             },
-            Expression::Binary(lhs, op, rhs) => {
+            ExpressionKind::Unary(op, input) => self.execute_unary(op, *input),
+            ExpressionKind::Binary(lhs, op, rhs) => {
                 match op {
-                    Token::Assign => match *lhs {
-                        Expression::Identifier(identifier) => {
-                            let value = self.execute(*rhs)?;
-                            self.set_var(identifier, value.clone());
-                            Ok(value)
-                        },
-                        _ => Err(RuntimeError::AssigningToValue(self.execute(*lhs)?.as_string())),
-                    },
-                    Token::AltAssign => match *rhs {
-                        Expression::Identifier(identifier) => {
-                            let value = self.execute(*lhs)?;
-                            self.set_var(identifier, value.clone());
-                            Ok(value)
-                        },
-                        _ => Err(RuntimeError::AssigningToValue(self.execute(*rhs)?.as_string())),
+                    Token::Assign => self.assign(*lhs, *rhs),
+                    Token::AltAssign => self.assign(*rhs, *lhs),
+                    // a number on the left can decide the result without running the right side
+                    Token::And | Token::Or => {
+                        let lval = self.execute(*lhs)?;
+                        match (&op, &lval) {
+                            (Token::And, Value::Number(_) | Value::Complex(_)) if !lval.is_true() => Ok(Value::Number(0.0)),
+                            (Token::Or, Value::Number(_) | Value::Complex(_)) if lval.is_true() => Ok(Value::Number(1.0)),
+                            _ => lval.binary_op(op, &self.execute(*rhs)?),
+                        }
                     },
                     _ => {
                         let lval = self.execute(*lhs)?;
@@ -96,60 +149,281 @@ impl Context<'_> {
                     }
                 }
             },
-            Expression::FuncCall(fname, args) => {
-                let arg_values = args.into_iter()
-                    .map(|arg| self.execute(arg))
-                    .collect::<Result<Vec<Value>, RuntimeError>>()?;
-
-                let mut function_context = Context::from_context(&self);
-                let function_def = self.get_function(&fname).ok_or(RuntimeError::UnknownIdentifier(fname.clone()))?;
-
-                match &function_def.body {
-                    FunctionBody::Builtin(closure) => closure(arg_values),
-                    FunctionBody::User(body) => {
-                        if function_def.params.len() != arg_values.len() {
-                            return Err(RuntimeError::WrongNumFunctionArgs {
-                                fname,
-                                expected: function_def.params.len(),
-                                got: arg_values.len(),
-                            });
-                        }
-                        for (param, arg) in iter::zip(&function_def.params, arg_values) {
-                            function_context.set_var(param.clone(), arg);
-                        }
-                        function_context.execute(body.clone())
-                    },
-                }
+            ExpressionKind::FuncCall(fname, args) if fname == "if" => self.execute_if(args),
+            ExpressionKind::FuncCall(fname, args) => self.execute_call(fname, args),
+            ExpressionKind::Matrix(rows) => self.execute_matrix(rows),
+            ExpressionKind::Range(start, step, end) => self.execute_range(*start, step.map(|step| *step), *end),
+            ExpressionKind::Index(inner, args) => {
+                let value = self.execute(*inner)?;
+                let selection = self.execute_indices(&value, args, false)?;
+                value.index(&selection)
             },
-            Expression::Matrix(rows) => {
-                let map_row = |row: Vec<Expression>| {
-                    row.into_iter()
-                        .map(|exp| match self.execute(exp) {
-                            Ok(val) => match val {
-                                Value::Number(num) => Ok(num),
-                                Value::Matrix(_) => Err(RuntimeError::NestedMatrix),
-                            }
-                            Err(e) => Err(e),
-                        })
-                        .collect::<Result<Vec<Num>, RuntimeError>>()
+            ExpressionKind::Colon => Err(RuntimeError::InvalidOperation("':' on its own can only be used inside an index".to_string())),
+            ExpressionKind::Group(inner) => self.execute(*inner),
+            ExpressionKind::Lambda(params, body) => Ok(self.execute_lambda(params, *body)),
+            ExpressionKind::Empty => Ok(Value::Number(0.0)), // this might need to be handled different in some cases
+        }
+    }
+}
+
+impl Context<'_> {
+    // the big arms of execute live in their own functions to keep its stack frame small,
+    // every nested user function call goes through execute a few times
+    fn execute_call(&mut self, fname: String, args: Vec<Expression>) -> Result<Value, RuntimeError> {
+        let arg_values = args.into_iter()
+            .map(|arg| self.execute(arg))
+            .collect::<Result<Vec<Value>, RuntimeError>>()?;
+        self.call(&fname, arg_values)
+    }
+
+    // calls a function by name with values that are already worked out, for anything that
+    // needs to run a function many times like plotting. Variables holding functions come
+    // first, but any other variable doesn't get in the way of a function with the same name
+    pub fn call(&self, fname: &str, arg_values: Vec<Value>) -> Result<Value, RuntimeError> {
+        if let Some(Value::Function(function_def)) = self.get_var(fname) {
+            return self.call_definition(function_def, arg_values);
+        }
+        let function_def = self.get_function(fname).ok_or(RuntimeError::UnknownIdentifier(fname.to_string()))?;
+        self.call_definition(function_def, arg_values)
+    }
+
+    // user functions see the caller's variables, the same as a call written out in the line
+    pub fn call_definition(&self, function_def: &FunctionDef, arg_values: Vec<Value>) -> Result<Value, RuntimeError> {
+        if self.settings.preview_exhausted(self.call_depth + 1) {
+            return Err(RuntimeError::PreviewLimit);
+        }
+        let mut function_context = Context::from_context(self);
+        let fname = &function_def.name;
+
+        match &function_def.body {
+            FunctionBody::Builtin(closure) => closure(&Environment::with_context(self), arg_values),
+            FunctionBody::User(body) | FunctionBody::Lambda(body, _) => {
+                if function_def.params.len() != arg_values.len() {
+                    return Err(RuntimeError::WrongNumFunctionArgs {
+                        fname: fname.to_string(),
+                        expected: function_def.params.len(),
+                        got: arg_values.len(),
+                    });
+                }
+                // captured variables sit under the arguments and in front of the caller's
+                if let FunctionBody::Lambda(_, captured) = &function_def.body {
+                    function_context.vars.extend(captured.iter().cloned());
+                }
+                for (param, arg) in iter::zip(&function_def.params, arg_values) {
+                    function_context.set_var(param.clone(), arg);
+                }
+                function_context.call_depth += 1;
+                let result = match function_context.call_depth > self.settings.max_call_depth {
+                    true => Err(RuntimeError::CallDepthExceeded {limit: self.settings.max_call_depth, calls: Vec::new()}),
+                    false => function_context.execute(body.clone()),
                 };
+                // spans inside the body point into the def line, so errors are shown at the call instead.
+                // the trace is only built while an error unwinds so normal calls don't pay for it
+                result.map_err(|err| match err.without_span() {
+                    RuntimeError::CallDepthExceeded {limit, mut calls} => {
+                        calls.push(function_context.describe_call(fname, &function_def.params));
+                        RuntimeError::CallDepthExceeded {limit, calls}
+                    },
+                    err => err,
+                })
+            },
+        }
+    }
 
-                let evaluated_rows = rows.into_iter()
-                    .map(map_row)
-                    .collect::<Result<MatrixBody, RuntimeError>>()?;
+    // the variables the body uses are kept as they are now, so a lambda returned from a
+    // function still has the arguments it was made with after that call is gone
+    fn execute_lambda(&self, params: Vec<String>, body: Expression) -> Value {
+        let mut names = Vec::new();
+        body.free_names(&params, &mut names);
+        let captured = names.into_iter()
+            .filter_map(|name| self.get_var(&name).map(|value| (name, value.clone())))
+            .collect();
+        Value::Function(Rc::new(FunctionDef {
+            name: LAMBDA_NAME.to_string(),
+            params,
+            body: FunctionBody::Lambda(body, captured),
+        }))
+    }
 
-                let num_cols = evaluated_rows.get(0).map(|row| row.len()).unwrap_or(0);
+    fn execute_unary(&mut self, op: Token, input: Expression) -> Result<Value, RuntimeError> {
+        match op {
+            Token::Minus => match self.execute(input)? {
+                Value::Number(num) => Ok(Value::Number(-num)),
+                Value::Complex(num) => Ok(Value::Complex(-num)),
+                Value::Matrix(mat) => Ok(Value::Matrix(matrix_transform_elements(&mat, |num| -num))),
+                Value::ComplexMatrix(mat) => Ok(Value::ComplexMatrix(matrix_transform_elements(&mat, |num| -num))),
+                value @ Value::Function(_) => Err(RuntimeError::InvalidOperation(format!("-{}", value.short_string()))),
+            },
+            Token::Not => match self.execute(input)? {
+                Value::Number(num) => Ok(Value::Number(truth(num == 0.0))),
+                Value::Complex(num) => Ok(Value::Number(truth(num == Complex::default()))),
+                Value::Matrix(mat) => Ok(Value::Matrix(matrix_transform_elements(&mat, |num| truth(num == 0.0)))),
+                Value::ComplexMatrix(mat) => Ok(Value::Matrix(matrix_transform_elements(&mat, |num| truth(num == Complex::default())))),
+                value @ Value::Function(_) => Err(RuntimeError::InvalidOperation(format!("not {}", value.short_string()))),
+            },
+            _ => Err(RuntimeError::ParserFailure(format!("{:?} of {:?}", op, input)))
+        }
+    }
 
-                for row in &evaluated_rows {
-                    if row.len() != num_cols {
-                        return Err(RuntimeError::MatrixUnevenColumns(num_cols, row.len()));
+    fn execute_matrix(&mut self, rows: Vec<Vec<Expression>>) -> Result<Value, RuntimeError> {
+        let map_row = |row: Vec<Expression>| {
+            row.into_iter()
+                .map(|exp| match self.execute(exp) {
+                    Ok(val) => match val {
+                        Value::Number(num) => Ok(Complex::from(num)),
+                        Value::Complex(num) => Ok(num),
+                        Value::Matrix(_) | Value::ComplexMatrix(_) => Err(RuntimeError::NestedMatrix),
+                        Value::Function(_) => Err(RuntimeError::InvalidOperation("functions can't be put in a matrix".to_string())),
                     }
-                }
+                    Err(e) => Err(e),
+                })
+                .collect::<Result<Vec<Complex>, RuntimeError>>()
+        };
+
+        let evaluated_rows = rows.into_iter()
+            .map(map_row)
+            .collect::<Result<ComplexMatrixBody, RuntimeError>>()?;
+
+        let num_cols = evaluated_rows.first().map(|row| row.len()).unwrap_or(0);
 
-                Ok(Value::Matrix(evaluated_rows))
+        for row in &evaluated_rows {
+            if row.len() != num_cols {
+                return Err(RuntimeError::MatrixUnevenColumns(num_cols, row.len()));
             }
-            Expression::Group(inner) => self.execute(*inner),
-            Expression::Empty => Ok(Value::Number(0.0)), // this might need to be handled different in some cases
+        }
+
+        Ok(Value::ComplexMatrix(evaluated_rows).normalized())
+    }
+
+    fn execute_range(&mut self, start: Expression, step: Option<Expression>, end: Expression) -> Result<Value, RuntimeError> {
+        let start = self.execute_range_bound(start)?;
+        let step = match step {
+            Some(step) => self.execute_range_bound(step)?,
+            None => 1.0,
+        };
+        let end = self.execute_range_bound(end)?;
+        if step == 0.0 {
+            return Err(RuntimeError::InvalidOperation("range step can't be 0".to_string()));
+        }
+        // the small nudge keeps 0:0.1:1 from losing its last element to rounding
+        let count = ((end - start) / step + 1e-10).floor() + 1.0;
+        if count > MAX_VECTOR_LEN as f64 {
+            return Err(RuntimeError::InvalidOperation(format!("range would have {count} elements")));
+        }
+        let count = count.max(0.0) as usize;
+        Ok(Value::Matrix((0..count).map(|i| vec![start + i as f64 * step]).collect()))
+    }
+
+    fn assign(&mut self, target: Expression, value: Expression) -> Result<Value, RuntimeError> {
+        let Expression {kind, span} = target;
+        match kind {
+            ExpressionKind::Identifier(identifier) => {
+                let value = self.execute(value)?.named(&identifier);
+                self.set_var(identifier, value.clone());
+                Ok(value)
+            },
+            ExpressionKind::Index(inner, args) => match inner.kind {
+                ExpressionKind::Identifier(identifier) => {
+                    let value = self.execute(value)?;
+                    let current = self.get_var(&identifier)
+                        .ok_or(RuntimeError::UnknownIdentifier(identifier.clone()))?
+                        .clone();
+                    let selection = self.execute_indices(&current, args, true)?;
+                    let updated = current.assign_indices(&selection, &value)?;
+                    self.set_var(identifier, updated.clone());
+                    Ok(updated)
+                },
+                kind => {
+                    let inner = Box::new(Expression {kind, span: inner.span});
+                    Err(RuntimeError::AssigningToValue(self.execute(Expression {kind: ExpressionKind::Index(inner, args), span})?.as_string()))
+                },
+            },
+            kind => Err(RuntimeError::AssigningToValue(self.execute(Expression {kind, span})?.as_string())),
+        }
+    }
+
+    // ex: f(1, [1, 2]), read back from the parameters of a function context
+    fn describe_call(&self, fname: &str, params: &[String]) -> String {
+        let args: Vec<_> = params.iter()
+            .filter_map(|param| self.get_var(param))
+            .map(|arg| arg.short_string())
+            .collect();
+        format!("{fname}({})", args.join(", "))
+    }
+
+    // only the branch that gets picked is evaluated, which is what lets user functions recurse
+    fn execute_if(&mut self, args: Vec<Expression>) -> Result<Value, RuntimeError> {
+        let num_args = args.len();
+        let mut args = args.into_iter();
+        let (Some(condition), Some(then), Some(otherwise), None) = (args.next(), args.next(), args.next(), args.next()) else {
+            return Err(RuntimeError::WrongNumFunctionArgs {fname: "if".to_string(), expected: 3, got: num_args});
+        };
+        match self.execute(condition)?.is_true() {
+            true => self.execute(then),
+            false => self.execute(otherwise),
+        }
+    }
+
+    fn execute_range_bound(&mut self, expression: Expression) -> Result<Num, RuntimeError> {
+        match self.execute(expression)? {
+            Value::Number(num) if num.is_finite() => Ok(num),
+            value => Err(RuntimeError::InvalidOperation(format!("range bounds must be finite real numbers, got {}", value.short_string()))),
+        }
+    }
+
+    // assignments can reach past the end, which grows the matrix
+    fn execute_indices(&mut self, value: &Value, args: Vec<Expression>, growable: bool) -> Result<Selection, RuntimeError> {
+        if let Value::Function(_) = value {
+            return Err(RuntimeError::InvalidIndex(format!("{} is a function, call it with ()", value.short_string())));
+        }
+        let (rows, cols) = value.dims();
+        let mut args = args.into_iter();
+        match (args.next(), args.next(), args.next()) {
+            (Some(index), None, None) => Ok(Selection::Linear(self.execute_index(index, rows * cols, "elements", growable)?)),
+            (Some(row_index), Some(col_index), None) => Ok(Selection::Grid(
+                self.execute_index(row_index, rows, "rows", growable)?,
+                self.execute_index(col_index, cols, "columns", growable)?,
+            )),
+            _ => Err(RuntimeError::InvalidIndex("use [index] or [row, column]".to_string())),
+        }
+    }
+
+    // converts a 1 based index argument to 0 based positions, 'end' is the length of the dimension
+    fn execute_index(&mut self, index: Expression, len: usize, dimension: &'static str, growable: bool) -> Result<Vec<usize>, RuntimeError> {
+        if let ExpressionKind::Colon = index.kind {
+            return Ok((0..len).collect());
+        }
+        let mut index_context = Context::from_context(self);
+        index_context.set_var("end".to_string(), Value::Number(len as Num));
+        let positions = match index_context.execute(index)? {
+            Value::Number(num) => vec![num],
+            Value::Matrix(mat) => column_major(&mat),
+            value => return Err(RuntimeError::InvalidIndex(value.as_string())),
+        };
+        positions.into_iter()
+            .map(|num| match num.fract() == 0.0 && num >= 1.0 {
+                true if num as usize > len && !growable => Err(RuntimeError::IndexOutOfBounds {index: num as usize, len, dimension}),
+                true => Ok(num as usize - 1),
+                false => Err(RuntimeError::InvalidIndex(format!("{num}, indices are whole numbers starting at 1"))),
+            })
+            .collect()
+    }
+}
+
+// 0 based positions picked out by an index
+pub enum Selection {
+    Linear(Vec<usize>),
+    Grid(Vec<usize>, Vec<usize>),
+}
+
+impl Selection {
+    // (row, column) pairs in column major order
+    fn positions(&self, rows: usize) -> Vec<(usize, usize)> {
+        match self {
+            Selection::Linear(indices) => indices.iter().map(|k| (k % rows, k / rows)).collect(),
+            Selection::Grid(row_indices, col_indices) => col_indices.iter()
+                .flat_map(|col| row_indices.iter().map(move |row| (*row, *col)))
+                .collect(),
         }
     }
 }
@@ -157,111 +431,340 @@ impl Context<'_> {
 // transformations
 impl Value {
     pub fn binary_op(&self, op: Token, rhs: &Value) -> Result<Value, RuntimeError> {
+        if self.is_complex() || rhs.is_complex() || needs_complex_pow(&op, self, rhs) {
+            return self.to_complex().complex_binary_op(op, &rhs.to_complex()).map(Value::normalized);
+        }
         match op {
-            Token::Plus => match self {
-                Value::Number(num1) => match rhs {
-                    Value::Number(num2) => Ok(Value::Number(num1 + num2)),
-                    Value::Matrix(_) => Err(RuntimeError::InvalidOperation(format!("{:?} + {:?}", self, rhs))),
-                },
-                Value::Matrix(mat1) => match rhs {
-                    Value::Number(_) => Err(RuntimeError::InvalidOperation(format!("matrix + {:?}", rhs))),
-                    Value::Matrix(mat2) => matrix_matrix_transform_elements(mat1, mat2, |(num1, num2)| num1 + num2),
-                },
+            Token::Plus => self.elementwise(&op, rhs, |num1, num2| num1 + num2),
+            Token::Minus => self.elementwise(&op, rhs, |num1, num2| num1 - num2),
+            Token::ElemMult => self.elementwise(&op, rhs, |num1, num2| num1 * num2),
+            Token::ElemDiv => self.elementwise(&op, rhs, |num1, num2| num1 / num2),
+            Token::ElemPow => self.elementwise(&op, rhs, |num1, num2| num1.powf(num2)),
+            Token::Mult => match (self, rhs) {
+                (Value::Matrix(mat1), Value::Matrix(mat2)) => matrix_multiplication(mat1, mat2).map(Value::Matrix),
+                _ => self.elementwise(&op, rhs, |num1, num2| num1 * num2),
             },
-            Token::Minus => match self {
-                Value::Number(num1) => match rhs {
-                    Value::Number(num2) => Ok(Value::Number(num1 - num2)),
-                    Value::Matrix(_) => Err(RuntimeError::InvalidOperation(format!("{:?} - {:?}", self, rhs))),
-                },
-                Value::Matrix(mat1) => match rhs {
-                    Value::Number(_) => Err(RuntimeError::InvalidOperation(format!("matrix - {:?}", rhs))),
-                    Value::Matrix(mat2) => matrix_matrix_transform_elements(mat1, mat2, |(num1, num2)| num1 - num2),
+            // A / B solves xB = A, which is (B' \ A')'
+            Token::Div => match (self, rhs) {
+                (Value::Matrix(mat1), Value::Matrix(mat2)) => {
+                    let solved = linalg::solve(&linalg::transpose(mat2), &linalg::transpose(mat1))?;
+                    Ok(Value::Matrix(linalg::transpose(&solved)))
                 },
+                _ => self.elementwise(&op, rhs, |num1, num2| num1 / num2),
             },
-            Token::Mult => match self {
-                Value::Number(num1) => match rhs {
-                    Value::Number(num2) => Ok(Value::Number(num1 * num2)),
-                    Value::Matrix(mat2) => matrix_transform_elements(mat2, |num| num * num1),
-                },
-                Value::Matrix(mat1) => match rhs {
-                    Value::Number(num2) => matrix_transform_elements(mat1, |num| num * num2),
-                    Value::Matrix(mat2) => matrix_multiplication(mat1, mat2),
-                },
+            // A \ b solves Ax = b
+            Token::LeftDiv => match (self, rhs) {
+                (Value::Matrix(mat1), Value::Matrix(mat2)) => Ok(Value::Matrix(linalg::solve(mat1, mat2)?)),
+                _ => self.elementwise(&op, rhs, |num1, num2| num2 / num1),
             },
-            Token::Div => match self {
-                Value::Number(num1) => match rhs {
-                    Value::Number(num2) => Ok(Value::Number(num1 / num2)),
-                    Value::Matrix(_) => Err(RuntimeError::InvalidOperation(format!("{num1} / matrix"))),
-                },
-                Value::Matrix(_) => match rhs {
-                    Value::Number(_) => Err(RuntimeError::InvalidOperation(format!("matrix / number"))),
-                    Value::Matrix(_) => Err(RuntimeError::InvalidOperation(format!("matrix / matrix"))),
-                }
+            Token::Pow => match (self, rhs) {
+                (Value::Number(num1), Value::Number(num2)) => Ok(Value::Number(num1.powf(*num2))),
+                (Value::Matrix(mat1), Value::Number(num2)) => real_matrix_power(mat1, *num2).map(Value::Matrix),
+                _ => Err(RuntimeError::InvalidOperation(
+                    format!("{} ^ {}, use .^ to raise each element", self.short_string(), rhs.short_string())
+                )),
+            },
+            Token::Equal => self.elementwise(&op, rhs, |num1, num2| truth(num1 == num2)),
+            Token::NotEqual => self.elementwise(&op, rhs, |num1, num2| truth(num1 != num2)),
+            Token::Less => self.elementwise(&op, rhs, |num1, num2| truth(num1 < num2)),
+            Token::LessEqual => self.elementwise(&op, rhs, |num1, num2| truth(num1 <= num2)),
+            Token::Greater => self.elementwise(&op, rhs, |num1, num2| truth(num1 > num2)),
+            Token::GreaterEqual => self.elementwise(&op, rhs, |num1, num2| truth(num1 >= num2)),
+            Token::And => self.elementwise(&op, rhs, |num1, num2| truth(num1 != 0.0 && num2 != 0.0)),
+            Token::Or => self.elementwise(&op, rhs, |num1, num2| truth(num1 != 0.0 || num2 != 0.0)),
+            _ => Err(RuntimeError::ParserFailure("ops got set up weird".into())),
+        }
+    }
+
+    // both sides are expected to already be promoted with to_complex
+    fn complex_binary_op(&self, op: Token, rhs: &Value) -> Result<Value, RuntimeError> {
+        match op {
+            Token::Plus => self.complex_elementwise(&op, rhs, |num1, num2| num1 + num2),
+            Token::Minus => self.complex_elementwise(&op, rhs, |num1, num2| num1 - num2),
+            Token::ElemMult => self.complex_elementwise(&op, rhs, |num1, num2| num1 * num2),
+            Token::ElemDiv => self.complex_elementwise(&op, rhs, |num1, num2| num1 / num2),
+            Token::ElemPow => self.complex_elementwise(&op, rhs, |num1, num2| num1.powc(num2)),
+            Token::Mult => match (self, rhs) {
+                (Value::ComplexMatrix(mat1), Value::ComplexMatrix(mat2)) => matrix_multiplication(mat1, mat2).map(Value::ComplexMatrix),
+                _ => self.complex_elementwise(&op, rhs, |num1, num2| num1 * num2),
             },
-            Token::Pow => match self {
-                Value::Number(num1) => match rhs {
-                    Value::Number(num2) => Ok(Value::Number(num1.powf(*num2))),
-                    Value::Matrix(_) => Err(RuntimeError::InvalidOperation(format!("number ^ matrix"))),
+            Token::Div | Token::LeftDiv => match (self, rhs) {
+                (Value::ComplexMatrix(_), Value::ComplexMatrix(_)) => Err(RuntimeError::InvalidOperation(
+                    format!("{} {op} {}, solving complex systems is not supported", self.short_string(), rhs.short_string())
+                )),
+                _ if op == Token::Div => self.complex_elementwise(&op, rhs, |num1, num2| num1 / num2),
+                _ => self.complex_elementwise(&op, rhs, |num1, num2| num2 / num1),
+            },
+            Token::Pow => match (self, rhs) {
+                (Value::Complex(num1), Value::Complex(num2)) => Ok(Value::Complex(num1.powc(*num2))),
+                (Value::ComplexMatrix(mat1), Value::Complex(num2)) if num2.is_real() => {
+                    match integer_exponent(mat1, num2.re)? {
+                        exponent if exponent < 0 => Err(RuntimeError::InvalidOperation(
+                            "negative powers of complex matrices are not supported".to_string()
+                        )),
+                        exponent => matrix_power(mat1, exponent.unsigned_abs()).map(Value::ComplexMatrix),
+                    }
                 },
-                Value::Matrix(_mat1) => match rhs {
-                    Value::Number(_num2) => todo!(),
-                    Value::Matrix(_) => Err(RuntimeError::InvalidOperation(format!("matrix ^ matrix"))),
-                }
+                _ => Err(RuntimeError::InvalidOperation(format!("{} ^ {}", self.short_string(), rhs.short_string()))),
+            },
+            Token::Equal => self.complex_elementwise(&op, rhs, |num1, num2| Complex::from(truth(num1 == num2))),
+            Token::NotEqual => self.complex_elementwise(&op, rhs, |num1, num2| Complex::from(truth(num1 != num2))),
+            Token::And | Token::Or => {
+                let zero = Complex::default();
+                self.complex_elementwise(&op, rhs, |num1, num2| Complex::from(match op {
+                    Token::And => truth(num1 != zero && num2 != zero),
+                    _ => truth(num1 != zero || num2 != zero),
+                }))
             },
+            Token::Less | Token::LessEqual | Token::Greater | Token::GreaterEqual => Err(RuntimeError::InvalidOperation(
+                format!("{} {op} {}, complex numbers can't be ordered", self.short_string(), rhs.short_string())
+            )),
             _ => Err(RuntimeError::ParserFailure("ops got set up weird".into())),
         }
     }
+
+    // numbers combine with every element, matrices broadcast against each other
+    fn elementwise(&self, op: &Token, rhs: &Value, f: impl Fn(Num, Num) -> Num) -> Result<Value, RuntimeError> {
+        match (self, rhs) {
+            (Value::Number(num1), Value::Number(num2)) => Ok(Value::Number(f(*num1, *num2))),
+            (Value::Number(num1), Value::Matrix(mat2)) => Ok(Value::Matrix(matrix_transform_elements(mat2, |num| f(*num1, num)))),
+            (Value::Matrix(mat1), Value::Number(num2)) => Ok(Value::Matrix(matrix_transform_elements(mat1, |num| f(num, *num2)))),
+            (Value::Matrix(mat1), Value::Matrix(mat2)) => broadcast(op, mat1, mat2, f).map(Value::Matrix),
+            _ => Err(RuntimeError::InvalidOperation(format!("{} {op} {}", self.short_string(), rhs.short_string()))),
+        }
+    }
+
+    fn complex_elementwise(&self, op: &Token, rhs: &Value, f: impl Fn(Complex, Complex) -> Complex) -> Result<Value, RuntimeError> {
+        match (self, rhs) {
+            (Value::Complex(num1), Value::Complex(num2)) => Ok(Value::Complex(f(*num1, *num2))),
+            (Value::Complex(num1), Value::ComplexMatrix(mat2)) => {
+                Ok(Value::ComplexMatrix(matrix_transform_elements(mat2, |num| f(*num1, num))))
+            },
+            (Value::ComplexMatrix(mat1), Value::Complex(num2)) => {
+                Ok(Value::ComplexMatrix(matrix_transform_elements(mat1, |num| f(num, *num2))))
+            },
+            (Value::ComplexMatrix(mat1), Value::ComplexMatrix(mat2)) => broadcast(op, mat1, mat2, f).map(Value::ComplexMatrix),
+            _ => Err(RuntimeError::InvalidOperation(format!("{} {op} {}", self.short_string(), rhs.short_string()))),
+        }
+    }
+
+    pub fn dims(&self) -> (usize, usize) {
+        match self {
+            Value::Number(_) | Value::Complex(_) | Value::Function(_) => (1, 1),
+            Value::Matrix(mat) => (mat.len(), mat.first().map(|r| r.len()).unwrap_or(0)),
+            Value::ComplexMatrix(mat) => (mat.len(), mat.first().map(|r| r.len()).unwrap_or(0)),
+        }
+    }
+
+    // indexing works on the complex form so real and complex values share one path,
+    // normalized brings real results back
+    fn complex_body(&self) -> ComplexMatrixBody {
+        match self.to_complex() {
+            Value::Complex(num) => vec![vec![num]],
+            Value::ComplexMatrix(mat) => mat,
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn index(&self, selection: &Selection) -> Result<Value, RuntimeError> {
+        let mat = self.complex_body();
+        let (rows, _) = self.dims();
+        let elements: Vec<Complex> = selection.positions(rows).into_iter().map(|(row, col)| mat[row][col]).collect();
+        let output = match selection {
+            _ if elements.len() == 1 => return Ok(Value::Complex(elements[0]).normalized()),
+            _ if elements.is_empty() => return Ok(Value::Matrix(Vec::new())),
+            // linear indexing a row vector gives back a row vector
+            Selection::Linear(_) if rows == 1 => vec![elements],
+            Selection::Linear(_) => elements.into_iter().map(|num| vec![num]).collect(),
+            Selection::Grid(row_indices, _) => (0..row_indices.len())
+                .map(|i| elements.iter().skip(i).step_by(row_indices.len()).copied().collect())
+                .collect(),
+        };
+        Ok(Value::ComplexMatrix(output).normalized())
+    }
+
+    // a number fills every selected element, otherwise the element counts have to match
+    // like MATLAB, assigning past the end fills the new space with zeros. A single
+    // index can only grow a vector, along the way it already goes
+    pub fn assign_indices(&self, selection: &Selection, value: &Value) -> Result<Value, RuntimeError> {
+        let mut mat = self.complex_body();
+        let (rows, cols) = self.dims();
+        let (rows, cols) = match selection {
+            Selection::Linear(indices) => match indices.iter().max().map_or(0, |k| k + 1) {
+                needed if needed <= rows * cols => (rows, cols),
+                needed if rows <= 1 => (1, needed),
+                needed if cols == 1 => (needed, 1),
+                needed => return Err(RuntimeError::IndexOutOfBounds {index: needed, len: rows * cols, dimension: "elements"}),
+            },
+            Selection::Grid(row_indices, col_indices) => (
+                row_indices.iter().map(|row| row + 1).fold(rows, usize::max),
+                col_indices.iter().map(|col| col + 1).fold(cols, usize::max),
+            ),
+        };
+        mat.resize(rows, Vec::new());
+        for row in &mut mat {
+            row.resize(cols, Complex::default());
+        }
+
+        let positions = selection.positions(rows);
+        let values = match value.to_complex() {
+            Value::Complex(num) => vec![num; positions.len()],
+            Value::ComplexMatrix(values) => column_major(&values),
+            _ => return Err(RuntimeError::InvalidOperation("functions can't be put in a matrix".to_string())),
+        };
+        if values.len() != positions.len() {
+            let (selected_rows, selected_cols) = match selection {
+                Selection::Linear(indices) => (indices.len(), 1),
+                Selection::Grid(row_indices, col_indices) => (row_indices.len(), col_indices.len()),
+            };
+            let (value_rows, value_cols) = value.dims();
+            return Err(RuntimeError::ShapeMismatch("=".to_string(), selected_rows, selected_cols, value_rows, value_cols));
+        }
+        for ((row, col), num) in iter::zip(positions, values) {
+            mat[row][col] = num;
+        }
+        match (self, rows, cols) {
+            (Value::Number(_) | Value::Complex(_), 1, 1) => Ok(Value::Complex(mat[0][0]).normalized()),
+            _ => Ok(Value::ComplexMatrix(mat).normalized()),
+        }
+    }
+
+    // like MATLAB, a matrix is only true when it has elements and none of them are 0
+    pub fn is_true(&self) -> bool {
+        match self {
+            Value::Number(num) => *num != 0.0,
+            Value::Complex(num) => *num != Complex::default(),
+            Value::Matrix(mat) => !mat.is_empty() && mat.iter().flatten().all(|num| *num != 0.0),
+            Value::ComplexMatrix(mat) => !mat.is_empty() && mat.iter().flatten().all(|num| *num != Complex::default()),
+            Value::Function(_) => true,
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Value::Complex(_) | Value::ComplexMatrix(_))
+    }
+
+    // a lambda takes the name of the first variable it's assigned to, so errors and call traces can say which one it was
+    fn named(self, name: &str) -> Value {
+        match self {
+            Value::Function(function_def) if function_def.name == LAMBDA_NAME => Value::Function(Rc::new(FunctionDef {
+                name: name.to_string(),
+                ..(*function_def).clone()
+            })),
+            value => value,
+        }
+    }
+
+    // promotes real values to their complex counterpart
+    pub fn to_complex(&self) -> Value {
+        match self {
+            Value::Number(num) => Value::Complex(Complex::from(*num)),
+            Value::Matrix(mat) => Value::ComplexMatrix(matrix_transform_elements(mat, Complex::from)),
+            _ => self.clone(),
+        }
+    }
+
+    // demotes complex values with no imaginary part so that real only functions keep working
+    pub fn normalized(self) -> Value {
+        match self {
+            Value::Complex(num) if num.is_real() => Value::Number(num.re),
+            Value::ComplexMatrix(ref mat) if mat.iter().flatten().all(|num| num.is_real()) => {
+                Value::Matrix(matrix_transform_elements(mat, |num| num.re))
+            },
+            _ => self,
+        }
+    }
 }
 
-fn match_matrices(mat1: &MatrixBody, mat2: &MatrixBody, matcher: impl Fn((usize, usize, usize, usize)) -> bool) -> Result<(), RuntimeError> {
-    let m1 = mat1.len();
-    let m2 = mat2.len();
-    let n1 = mat1.get(0).map(|v| v.len()).unwrap_or(0);
-    let n2 = mat2.get(0).map(|v| v.len()).unwrap_or(0);
-    match matcher((m1, n1, m2, n2)) {
-        true => Ok(()),
-        false => Err(RuntimeError::IncompatibleMatrices(m1, n1, m2, n2)),
+fn parse_imaginary(st: &str) -> Option<Num> {
+    st.strip_suffix(['i', 'j'])
+        .and_then(|num| num.parse::<Num>().ok())
+}
+
+// real powers only go complex for a negative base with a fractional exponent, ex: (-1)^0.5
+fn needs_complex_pow(op: &Token, lhs: &Value, rhs: &Value) -> bool {
+    let elements = |value: &Value| match value {
+        Value::Number(num) => vec![*num],
+        Value::Matrix(mat) => mat.iter().flatten().copied().collect(),
+        _ => Vec::new(),
+    };
+    match op {
+        Token::Pow if matches!(lhs, Value::Number(_)) => {},
+        Token::ElemPow => {},
+        _ => return false,
     }
+    elements(lhs).iter().any(|num| *num < 0.0) && elements(rhs).iter().any(|num| num.fract() != 0.0)
 }
 
-fn matrix_transform_elements(matrix: &MatrixBody, transform: impl Fn(&Num) -> Num) -> Result<Value, RuntimeError> {
-    let res = matrix.iter()
+fn truth(condition: bool) -> Num {
+    match condition {
+        true => 1.0,
+        false => 0.0,
+    }
+}
+
+fn column_major<T: Copy>(mat: &[Vec<T>]) -> Vec<T> {
+    let cols = mat.first().map(|r| r.len()).unwrap_or(0);
+    (0..cols).flat_map(|col| mat.iter().map(move |row| row[col])).collect()
+}
+
+pub fn matrix_transform_elements<T: Copy, U>(matrix: &[Vec<T>], transform: impl Fn(T) -> U) -> Vec<Vec<U>> {
+    matrix.iter()
         .map(|vec| {
             vec.iter()
-                .map(&transform)
+                .map(|num| transform(*num))
                 .collect::<Vec<_>>()
         })
-        .collect();
-    Ok(Value::Matrix(res))
+        .collect()
 }
 
-fn matrix_matrix_transform_elements(mat1: &MatrixBody, mat2: &MatrixBody, transform: impl Fn((&Num, &Num)) -> Num) -> Result<Value, RuntimeError> {
-    match_matrices(mat1, mat2, |(m1, n1, m2, n2)| m1 == m2 && n1 == n2)?;
-    let res = iter::zip(mat1, mat2)
-        .map(|(vec1, vec2)| {
-            iter::zip(vec1, vec2)
-                .map(&transform)
-                .collect::<Vec<_>>()
+// each dimension has to match or be 1, so a row or column vector is repeated across a matrix
+fn broadcast<T: Copy>(op: &Token, mat1: &[Vec<T>], mat2: &[Vec<T>], f: impl Fn(T, T) -> T) -> Result<Vec<Vec<T>>, RuntimeError> {
+    let m1 = mat1.len();
+    let m2 = mat2.len();
+    let n1 = mat1.first().map(|r| r.len()).unwrap_or(0);
+    let n2 = mat2.first().map(|r| r.len()).unwrap_or(0);
+
+    let broadcast_dim = |d1: usize, d2: usize| match (d1, d2) {
+        _ if d1 == d2 => Some(d1),
+        (1, _) => Some(d2),
+        (_, 1) => Some(d1),
+        _ => None,
+    };
+    let (rows, cols) = match (broadcast_dim(m1, m2), broadcast_dim(n1, n2)) {
+        (Some(rows), Some(cols)) => (rows, cols),
+        _ => return Err(RuntimeError::ShapeMismatch(op.to_string(), m1, n1, m2, n2)),
+    };
+
+    let index = |i: usize, size: usize| if size == 1 { 0 } else { i };
+    let output = (0..rows)
+        .map(|i| {
+            (0..cols)
+                .map(|j| f(mat1[index(i, m1)][index(j, n1)], mat2[index(i, m2)][index(j, n2)]))
+                .collect()
         })
         .collect();
-    Ok(Value::Matrix(res))
+    Ok(output)
 }
 
-fn matrix_multiplication(mat1: &MatrixBody, mat2: &MatrixBody) -> Result<Value, RuntimeError> {
+fn matrix_multiplication<T>(mat1: &[Vec<T>], mat2: &[Vec<T>]) -> Result<Vec<Vec<T>>, RuntimeError>
+where
+    T: Copy + Mul<Output = T> + Sum,
+{
     let m1 =  mat1.len();
     let m2 = mat2.len();
-    let n1 = mat1.get(0).map(|r| r.len()).unwrap_or(0);
-    let n2 = mat2.get(0).map(|r| r.len()).unwrap_or(0);
+    let n1 = mat1.first().map(|r| r.len()).unwrap_or(0);
+    let n2 = mat2.first().map(|r| r.len()).unwrap_or(0);
 
     if m1 == 0 || m2 == 0 || n1 != m2 {
         return Err(RuntimeError::IncompatibleMatrices(m1, n1, m2, n2))
     }
 
-    let mut output_rows: MatrixBody = iter::repeat(Vec::new()).take(m1).collect();
+    let mut output_rows: Vec<Vec<T>> = iter::repeat_n(Vec::new(), m1).collect();
 
     for (row_i, row1) in mat1.iter().enumerate() {
         for col_i in 0..n2 {
-            let output_num: Num = mat2.iter()
+            let output_num: T = mat2.iter()
                 .enumerate()
                 .map(|(num_i, row2)| row1[num_i] * row2[col_i])
                 .sum();
@@ -269,67 +772,110 @@ fn matrix_multiplication(mat1: &MatrixBody, mat2: &MatrixBody) -> Result<Value,
         }
     }
 
-    Ok(Value::Matrix(output_rows))
+    Ok(output_rows)
+}
+
+// negative powers go through the inverse
+fn real_matrix_power(mat: &MatrixBody, exponent: Num) -> Result<MatrixBody, RuntimeError> {
+    let exponent = integer_exponent(mat, exponent)?;
+    let base = match exponent < 0 {
+        true => linalg::LuDecomposition::new(mat).inverse().ok_or(RuntimeError::InvalidOperation(
+            "matrix is not invertible so it has no negative powers".to_string()
+        ))?,
+        false => mat.clone(),
+    };
+    matrix_power(&base, exponent.unsigned_abs())
+}
+
+fn integer_exponent<T>(mat: &[Vec<T>], exponent: Num) -> Result<i64, RuntimeError> {
+    let rows = mat.len();
+    let cols = mat.first().map(|r| r.len()).unwrap_or(0);
+    if rows == 0 || rows != cols {
+        return Err(RuntimeError::InvalidOperation(format!("only square matrices have powers, got {rows}x{cols}")));
+    }
+    if !exponent.is_finite() || exponent.fract() != 0.0 {
+        return Err(RuntimeError::InvalidOperation(
+            format!("matrix ^ {exponent}, use a whole number or std.sqrtm/std.expm/std.logm")
+        ));
+    }
+    Ok(exponent as i64)
+}
+
+// repeated squaring, A^0 is the identity
+fn matrix_power<T>(mat: &[Vec<T>], mut exponent: u64) -> Result<Vec<Vec<T>>, RuntimeError>
+where
+    T: Copy + Mul<Output = T> + Sum + From<f64>,
+{
+    let size = mat.len();
+    let mut output: Vec<Vec<T>> = (0..size)
+        .map(|i| (0..size).map(|j| T::from(if i == j { 1.0 } else { 0.0 })).collect())
+        .collect();
+    let mut base = mat.to_vec();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            output = matrix_multiplication(&output, &base)?;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = matrix_multiplication(&base, &base)?;
+        }
+    }
+    Ok(output)
 }
 
 // displaying values
 impl Value {
     pub fn as_string(&self) -> String {
-        let mut output = String::new();
         match self {
-            Value::Number(num) => output.push_str(&num.to_string()),
-            Value::Matrix(rows) => {
+            Value::Number(num) => num.to_string(),
+            Value::Complex(num) => num.to_string(),
+            Value::Matrix(_) | Value::ComplexMatrix(_) => {
+                let rows = self.element_strings(None).unwrap_or_default();
                 match rows.len() {
-                    0 => output.push_str("[Empty]"),
+                    0 => "[Empty]".to_string(),
                     _ => {
-                        output.push('[');
-                        for row in rows {
-                            for col in row {
-                                output.push_str(&col.to_string());
-                                output.push(',');
-                                output.push(' ');
-                            }
-                            output.pop();
-                            output.pop();
-                            output.push(';');
-                            output.push(' ');
-                        }
-                        output.pop();
-                        output.pop();
-                        output.push(']');
+                        let rows: Vec<_> = rows.iter().map(|row| row.join(", ")).collect();
+                        format!("[{}]", rows.join("; "))
                     },
-                };
+                }
             },
-        };
-        output
+            Value::Function(function_def) => function_def.to_string(),
+        }
     }
 
     pub fn short_string(&self) -> String {
         match self {
-            Value::Number(_) => self.as_string(),
-            Value::Matrix(rows) => format!("{}x{}", rows.len(), rows.get(0).map(|r| r.len()).unwrap_or(0)),
+            Value::Number(_) | Value::Complex(_) => self.as_string(),
+            Value::Function(function_def) => match function_def.body {
+                FunctionBody::Lambda(..) => function_def.to_string(),
+                _ => function_def.name.clone(),
+            },
+            Value::Matrix(rows) => format!("{}x{}", rows.len(), rows.first().map(|r| r.len()).unwrap_or(0)),
+            Value::ComplexMatrix(rows) => format!("{}x{} complex", rows.len(), rows.first().map(|r| r.len()).unwrap_or(0)),
         }
     }
 
-    pub fn output_tokens(&self) -> Vec<HighlightToken> {
+    pub fn output_tokens(&self, settings: &Settings) -> Vec<HighlightToken> {
         match self {
-            Value::Number(num) => vec![HighlightToken {text: num.to_string(), kind: HighlightTokenType::Number}],
-            Value::Matrix(rows) => {
+            Value::Number(num) => vec![HighlightToken {text: settings.format_number(*num), kind: HighlightTokenType::Number}],
+            Value::Complex(num) => vec![HighlightToken::number(settings.format_complex(*num))],
+            Value::Matrix(_) | Value::ComplexMatrix(_) => {
+                let rows = self.element_strings(Some(settings)).unwrap_or_default();
                 let mut tokens = Vec::new();
 
-                if rows.len() == 0 {
+                if rows.is_empty() {
                     tokens.push(HighlightToken::op("[Empty]"));
 
                 } else if rows.len() == 1 || rows[0].len() == 1 {
                     tokens.push(HighlightToken::op("["));
 
                     let (elements, delimiter) = match rows.len() {
-                        1 => (&rows[0], ','),
-                        _ => (&rows.iter().map(|row| row[0]).collect(), ';'),
+                        1 => (rows[0].clone(), ','),
+                        _ => (rows.iter().map(|row| row[0].clone()).collect(), ';'),
                     };
 
                     for number in elements {
-                        tokens.push(HighlightToken::number(number.to_string()));
+                        tokens.push(HighlightToken::number(number));
                         tokens.push(HighlightToken::op(&delimiter.to_string()));
                         tokens.push(HighlightToken::space());
                     }
@@ -346,7 +892,7 @@ impl Value {
                         tokens.push(HighlightToken::newline());
                         tokens.push(HighlightToken::tab());
                         for number in row {
-                            tokens.push(HighlightToken::number(number.to_string()));
+                            tokens.push(HighlightToken::number(number));
                             tokens.push(HighlightToken::op(", "));
                         }
                         tokens.pop();
@@ -358,6 +904,138 @@ impl Value {
 
                 tokens
             },
+            Value::Function(_) => get_highlight_tokens(&self.as_string()),
+        }
+    }
+
+    // settings are left out when the text needs to be read back in exactly
+    fn element_strings(&self, settings: Option<&Settings>) -> Option<Vec<Vec<String>>> {
+        match (self, settings) {
+            (Value::Matrix(rows), Some(settings)) => Some(matrix_transform_elements(rows, |num| settings.format_number(num))),
+            (Value::Matrix(rows), None) => Some(matrix_transform_elements(rows, |num| num.to_string())),
+            (Value::ComplexMatrix(rows), Some(settings)) => Some(matrix_transform_elements(rows, |num| settings.format_complex(num))),
+            (Value::ComplexMatrix(rows), None) => Some(matrix_transform_elements(rows, |num| num.to_string())),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{syntax_tree::generate_syntax_tree, tokens::tokenize_spanned};
+
+    fn run(context: &mut Context, line: &str) -> Result<Value, RuntimeError> {
+        let expression = generate_syntax_tree(tokenize_spanned(line)).unwrap();
+        context.execute(expression).map_err(RuntimeError::without_span)
+    }
+
+    fn shown(line: &str) -> String {
+        match run(&mut Context::default(), line) {
+            Ok(value) => value.as_string(),
+            Err(err) => panic!("{line} failed with {err}"),
         }
     }
+
+    #[test]
+    fn lazy_branches() {
+        assert_eq!(shown("if(1, 2, undefined_var)"), "2");
+        assert_eq!(shown("if(0, undefined_var, 3)"), "3");
+        assert_eq!(shown("0 and undefined_var"), "0");
+        assert_eq!(shown("1 or undefined_var"), "1");
+
+        let mut context = Context::default();
+        run(&mut context, "0 and (x = 1)").unwrap();
+        run(&mut context, "1 or (x = 1)").unwrap();
+        run(&mut context, "if(1, 0, x = 1)").unwrap();
+        assert!(context.get_var("x").is_none());
+        assert!(matches!(run(&mut context, "1 and undefined_var"), Err(RuntimeError::UnknownIdentifier(_))));
+        assert!(matches!(run(&mut context, "0 or undefined_var"), Err(RuntimeError::UnknownIdentifier(_))));
+    }
+
+    #[test]
+    fn ranges_and_indexing() {
+        assert_eq!(shown("5:-2:1"), "[5; 3; 1]");
+        assert_eq!(shown("1:-1:2"), "[Empty]");
+        assert!(matches!(run(&mut Context::default(), "1:0:3"), Err(RuntimeError::InvalidOperation(msg)) if msg == "range step can't be 0"));
+
+        let mut context = Context::default();
+        run(&mut context, "A = [1, 2, 3; 4, 5, 6]").unwrap();
+        assert_eq!(run(&mut context, "A[end, end]").unwrap().as_string(), "6");
+        assert_eq!(run(&mut context, "A[end]").unwrap().as_string(), "6");
+        assert_eq!(run(&mut context, "A[:, end - 1:end]").unwrap().as_string(), "[2, 3; 5, 6]");
+        assert!(matches!(
+            run(&mut context, "A[7]"),
+            Err(RuntimeError::IndexOutOfBounds {index: 7, len: 6, dimension: "elements"}),
+        ));
+        assert!(matches!(
+            run(&mut context, "A[1, 4]"),
+            Err(RuntimeError::IndexOutOfBounds {index: 4, len: 3, dimension: "columns"}),
+        ));
+    }
+    #[test]
+    fn indexed_assignment() {
+        let mut context = Context::default();
+        run(&mut context, "A = [1, 2; 3, 4]").unwrap();
+        assert_eq!(run(&mut context, "A[1, :] = 0").unwrap().as_string(), "[0, 0; 3, 4]");
+        // past the end grows with zeros
+        assert_eq!(run(&mut context, "A[3, 3] = 9").unwrap().as_string(), "[0, 0, 0; 3, 4, 0; 0, 0, 9]");
+        run(&mut context, "v = [1, 2]").unwrap();
+        assert_eq!(run(&mut context, "v[end + 2] = 5").unwrap().as_string(), "[1, 2, 0, 5]");
+        run(&mut context, "w = [1; 2]").unwrap();
+        assert_eq!(run(&mut context, "w[3] = 5").unwrap().as_string(), "[1; 2; 5]");
+        run(&mut context, "s = 1").unwrap();
+        assert_eq!(run(&mut context, "s[3] = 2").unwrap().as_string(), "[1, 0, 2]");
+
+        assert!(matches!(
+            run(&mut context, "A[10] = 1"),
+            Err(RuntimeError::IndexOutOfBounds {index: 10, len: 9, dimension: "elements"}),
+        ));
+        run(&mut context, "sq = x -> x^2").unwrap();
+        assert!(matches!(run(&mut context, "v[1] = sq"), Err(RuntimeError::InvalidOperation(msg)) if msg == "functions can't be put in a matrix"));
+        assert!(matches!(run(&mut context, "v[1:2] = [1, 2, 3]"), Err(RuntimeError::ShapeMismatch(..))));
+    }
+
+    #[test]
+    fn broadcasting() {
+        assert_eq!(shown("2 + [1, 2]"), "[3, 4]");
+        assert_eq!(shown("2 - [1, 2]"), "[1, 0]");
+        assert_eq!(shown("[1, 2] / 2"), "[0.5, 1]");
+        assert_eq!(shown("2 ./ [1, 4]"), "[2, 0.5]");
+        assert_eq!(shown("[1, 2] .^ 2"), "[1, 4]");
+        // a row against a column gives every pair
+        assert_eq!(shown("[1, 2] + [10; 20]"), "[11, 12; 21, 22]");
+        assert_eq!(shown("[1, 2; 3, 4] .* [1, 2]"), "[1, 4; 3, 8]");
+        assert_eq!(shown("[1, 2; 3, 4] - [1; 2]"), "[0, 1; 1, 2]");
+
+        for (line, message) in [
+            ("[1, 2, 3] + [1, 2]", "shapes 1x3 and 1x2 don't match for '+'"),
+            ("[1, 2; 3, 4] .* [1, 2, 3]", "shapes 2x2 and 1x3 don't match for '.*'"),
+            ("[1; 2; 3] - [1, 2; 3, 4]", "shapes 3x1 and 2x2 don't match for '-'"),
+        ] {
+            let err = run(&mut Context::default(), line).unwrap_err();
+            assert!(matches!(err, RuntimeError::ShapeMismatch(..)), "{line}");
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn complex_values() {
+        let value = |line: &str| run(&mut Context::default(), line).unwrap();
+        assert!(matches!(value("2 + 3i"), Value::Complex(num) if num == Complex::new(2.0, 3.0)));
+        assert!(matches!(value("(3 + 4i) * 2 - 1"), Value::Complex(num) if num == Complex::new(5.0, 8.0)));
+        assert!(matches!(value("std.sqrt(-1)"), Value::Complex(num) if num == Complex::new(0.0, 1.0)));
+        assert!(matches!(value("(-1)^0.5"), Value::Complex(num) if (num - Complex::new(0.0, 1.0)).abs() < 1e-15));
+        // results with no imaginary part go back to being real
+        assert!(matches!(value("(1 + 2i) * (1 - 2i)"), Value::Number(5.0)));
+        assert!(matches!(value("1i * 1i"), Value::Number(-1.0)));
+        assert!(matches!(value("(1 + 1i) - 1i"), Value::Number(1.0)));
+
+        assert!(matches!(value("[1, 1i; 0, 1] * [1; 1i]"), Value::ComplexMatrix(mat) if mat == vec![vec![Complex::new(0.0, 0.0)], vec![Complex::new(0.0, 1.0)]]));
+        assert!(matches!(value("[1i, 0; 0, 1i] * [1i, 0; 0, 1i]"), Value::Matrix(mat) if mat == vec![vec![-1.0, 0.0], vec![0.0, -1.0]]));
+        assert!(matches!(
+            run(&mut Context::default(), "[1i, 2] * [1, 2]"),
+            Err(RuntimeError::IncompatibleMatrices(1, 2, 1, 2)),
+        ));
+    }
 }
@@ -0,0 +1,164 @@
+// the line being typed and where the cursor is in it, the cursor is a byte offset
+// that always sits on a char boundary
+#[derive(Debug, Default)]
+pub struct InputLine {
+    text: String,
+    cursor: usize,
+}
+
+impl InputLine {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn before_cursor(&self) -> &str {
+        &self.text[..self.cursor]
+    }
+
+    pub fn after_cursor(&self) -> &str {
+        &self.text[self.cursor..]
+    }
+
+    // replaces the whole line and puts the cursor at the end
+    pub fn set(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    pub fn clear(&mut self) {
+        self.take();
+    }
+
+    // swaps the last len bytes before the cursor for text, used by tab completion
+    pub fn replace_before_cursor(&mut self, len: usize, text: &str) {
+        let start = self.cursor - len;
+        self.text.replace_range(start..self.cursor, text);
+        self.cursor = start + text.len();
+    }
+
+    pub fn insert(&mut self, ch: char) {
+        self.text.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+    }
+
+    pub fn backspace(&mut self) {
+        let start = self.prev_char();
+        self.text.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    pub fn delete(&mut self) {
+        let end = self.next_char();
+        self.text.drain(self.cursor..end);
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.prev_char();
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = self.next_char();
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    pub fn word_left(&mut self) {
+        self.cursor = self.prev_word();
+    }
+
+    pub fn word_right(&mut self) {
+        let after = self.after_cursor();
+        let word_start = after.find(is_word_char).unwrap_or(after.len());
+        let word_len = after[word_start..].find(|ch| !is_word_char(ch)).unwrap_or(after.len() - word_start);
+        self.cursor += word_start + word_len;
+    }
+
+    // ctrl+w
+    pub fn delete_word(&mut self) {
+        let start = self.prev_word();
+        self.text.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    // ctrl+k
+    pub fn delete_to_end(&mut self) {
+        self.text.truncate(self.cursor);
+    }
+
+    fn prev_char(&self) -> usize {
+        self.before_cursor().char_indices().next_back().map_or(0, |(i, _)| i)
+    }
+
+    fn next_char(&self) -> usize {
+        self.after_cursor().chars().next().map_or(self.cursor, |ch| self.cursor + ch.len_utf8())
+    }
+
+    // skips anything between words then the word itself, like readline
+    fn prev_word(&self) -> usize {
+        let before = self.before_cursor().trim_end_matches(|ch| !is_word_char(ch));
+        before.trim_end_matches(is_word_char).len()
+    }
+}
+
+// '.' splits words so ctrl+left stops between 'std' and 'sin'
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, cursor: usize) -> InputLine {
+        InputLine {text: text.to_string(), cursor}
+    }
+
+    #[test]
+    fn editing_mid_line() {
+        let mut input = line("[1, 2]", 0);
+        input.right();
+        input.delete();
+        input.insert('7');
+        input.insert('é');
+        assert_eq!((input.text(), input.cursor()), ("[7é, 2]", 4));
+        input.backspace();
+        input.left();
+        input.backspace();
+        assert_eq!((input.text(), input.cursor()), ("7, 2]", 0));
+        input.backspace();
+        input.end();
+        input.delete();
+        assert_eq!((input.text(), input.cursor()), ("7, 2]", 5));
+    }
+    #[test]
+    fn words() {
+        let mut input = line("x = std.sin(my_var) ", 20);
+        input.word_left();
+        assert_eq!(input.after_cursor(), "my_var) ");
+        input.word_left();
+        input.word_left();
+        assert_eq!(input.after_cursor(), "std.sin(my_var) ");
+        input.word_right();
+        assert_eq!(input.after_cursor(), ".sin(my_var) ");
+        input.word_right();
+        input.delete_word();
+        assert_eq!((input.text(), input.cursor()), ("x = std.(my_var) ", 8));
+        input.delete_to_end();
+        assert_eq!(input.text(), "x = std.");
+    }
+}
@@ -0,0 +1,942 @@
+// numeric routines shared by the builtin functions and the operators in executor
+
+use std::iter;
+
+use super::{complex::Complex, executor::RuntimeError};
+
+pub type Matrix = Vec<Vec<f64>>;
+
+pub fn dims(mat: &[Vec<f64>]) -> (usize, usize) {
+    (mat.len(), mat.first().map(|row| row.len()).unwrap_or(0))
+}
+
+pub fn identity(size: usize) -> Matrix {
+    (0..size)
+        .map(|i| (0..size).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect()
+}
+
+pub fn transpose(mat: &[Vec<f64>]) -> Matrix {
+    let (_, cols) = dims(mat);
+    (0..cols)
+        .map(|j| mat.iter().map(|row| row[j]).collect())
+        .collect()
+}
+
+pub fn multiply(mat1: &[Vec<f64>], mat2: &[Vec<f64>]) -> Matrix {
+    let (_, n2) = dims(mat2);
+    mat1.iter()
+        .map(|row1| {
+            (0..n2)
+                .map(|col| iter::zip(row1, mat2).map(|(num, row2)| num * row2[col]).sum())
+                .collect()
+        })
+        .collect()
+}
+
+pub fn scale(mat: &[Vec<f64>], factor: f64) -> Matrix {
+    mat.iter().map(|row| row.iter().map(|num| num * factor).collect()).collect()
+}
+
+pub fn combine(mat1: &[Vec<f64>], mat2: &[Vec<f64>], f: impl Fn(f64, f64) -> f64) -> Matrix {
+    iter::zip(mat1, mat2)
+        .map(|(row1, row2)| iter::zip(row1, row2).map(|(num1, num2)| f(*num1, *num2)).collect())
+        .collect()
+}
+
+// largest absolute column sum
+pub fn norm_1(mat: &[Vec<f64>]) -> f64 {
+    let (_, cols) = dims(mat);
+    (0..cols)
+        .map(|j| mat.iter().map(|row| row[j].abs()).sum::<f64>())
+        .fold(0.0, f64::max)
+}
+
+// entries smaller than this are treated as 0 when deciding if a matrix is singular
+pub fn default_tolerance(mat: &[Vec<f64>]) -> f64 {
+    let (rows, cols) = dims(mat);
+    let largest = mat.iter().flatten().fold(0.0_f64, |acc, num| acc.max(num.abs()));
+    rows.max(cols) as f64 * f64::EPSILON * largest
+}
+
+// PA = LU with partial pivoting, L and U are packed into a single matrix with
+// the unit diagonal of L left out
+pub struct LuDecomposition {
+    pub packed: Matrix,
+    pub perm: Vec<usize>,
+    pub is_singular: bool,
+    sign: f64,
+}
+
+impl LuDecomposition {
+    // expects a square matrix
+    pub fn new(mat: &[Vec<f64>]) -> LuDecomposition {
+        let size = mat.len();
+        let tolerance = default_tolerance(mat);
+        let mut packed = mat.to_vec();
+        let mut perm: Vec<usize> = (0..size).collect();
+        let mut sign = 1.0;
+        let mut is_singular = false;
+
+        for col in 0..size {
+            let (pivot_row, pivot_value) = (col..size)
+                .map(|row| (row, packed[row][col].abs()))
+                .fold((col, -1.0), |best, current| if current.1 > best.1 { current } else { best });
+
+            if pivot_value <= tolerance {
+                is_singular = true;
+                continue;
+            }
+
+            if pivot_row != col {
+                packed.swap(pivot_row, col);
+                perm.swap(pivot_row, col);
+                sign = -sign;
+            }
+
+            let pivot_values = packed[col].clone();
+            for row in packed.iter_mut().skip(col + 1) {
+                let factor = row[col] / pivot_values[col];
+                row[col] = factor;
+                for (num, pivot_num) in iter::zip(row.iter_mut(), &pivot_values).skip(col + 1) {
+                    *num -= factor * pivot_num;
+                }
+            }
+        }
+
+        LuDecomposition {packed, perm, is_singular, sign}
+    }
+
+    pub fn det(&self) -> f64 {
+        match self.is_singular {
+            true => 0.0,
+            false => self.packed.iter().enumerate().map(|(i, row)| row[i]).product::<f64>() * self.sign,
+        }
+    }
+
+    pub fn l(&self) -> Matrix {
+        let size = self.packed.len();
+        (0..size)
+            .map(|i| (0..size).map(|j| match j.cmp(&i) {
+                std::cmp::Ordering::Less => self.packed[i][j],
+                std::cmp::Ordering::Equal => 1.0,
+                std::cmp::Ordering::Greater => 0.0,
+            }).collect())
+            .collect()
+    }
+
+    pub fn u(&self) -> Matrix {
+        self.packed.iter()
+            .enumerate()
+            .map(|(i, row)| row.iter().enumerate().map(|(j, num)| if j >= i { *num } else { 0.0 }).collect())
+            .collect()
+    }
+
+    pub fn p(&self) -> Matrix {
+        let size = self.perm.len();
+        self.perm.iter()
+            .map(|&source| (0..size).map(|j| if j == source { 1.0 } else { 0.0 }).collect())
+            .collect()
+    }
+
+    // solves AX = B for every column of B
+    pub fn solve(&self, b: &[Vec<f64>]) -> Option<Matrix> {
+        if self.is_singular {
+            return None;
+        }
+        let size = self.packed.len();
+        let mut x: Matrix = self.perm.iter().map(|&row| b[row].clone()).collect();
+
+        for i in 0..size {
+            for k in 0..i {
+                let factor = self.packed[i][k];
+                let (done, rest) = x.split_at_mut(i);
+                for (num, solved) in iter::zip(rest[0].iter_mut(), &done[k]) {
+                    *num -= factor * solved;
+                }
+            }
+        }
+
+        for i in (0..size).rev() {
+            for k in i + 1..size {
+                let factor = self.packed[i][k];
+                let (current, solved) = x.split_at_mut(k);
+                for (num, solved_num) in iter::zip(current[i].iter_mut(), &solved[0]) {
+                    *num -= factor * solved_num;
+                }
+            }
+            let pivot = self.packed[i][i];
+            for num in x[i].iter_mut() {
+                *num /= pivot;
+            }
+        }
+
+        Some(x)
+    }
+
+    pub fn inverse(&self) -> Option<Matrix> {
+        self.solve(&identity(self.packed.len()))
+    }
+}
+
+// A = QR using householder reflections, Q is m x m and R is m x n
+pub fn qr(mat: &[Vec<f64>]) -> (Matrix, Matrix) {
+    let (rows, cols) = dims(mat);
+    let mut r = mat.to_vec();
+    let mut q = identity(rows);
+
+    for col in 0..cols.min(rows.saturating_sub(1)) {
+        let norm = (col..rows).map(|row| r[row][col].powi(2)).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            continue;
+        }
+        let alpha = match r[col][col] > 0.0 {
+            true => -norm,
+            false => norm,
+        };
+        let mut v: Vec<f64> = (0..rows).map(|row| if row < col { 0.0 } else { r[row][col] }).collect();
+        v[col] -= alpha;
+        let v_norm_sq: f64 = v.iter().map(|num| num * num).sum();
+        if v_norm_sq == 0.0 {
+            continue;
+        }
+
+        // R = (I - 2vv'/v'v) R
+        for j in 0..cols {
+            let dot: f64 = iter::zip(&r, &v).skip(col).map(|(row, v_num)| v_num * row[j]).sum();
+            let scale = 2.0 * dot / v_norm_sq;
+            for (row, v_num) in iter::zip(r.iter_mut(), &v).skip(col) {
+                row[j] -= scale * v_num;
+            }
+        }
+        // Q = Q (I - 2vv'/v'v)
+        for q_row in q.iter_mut() {
+            let dot: f64 = (col..rows).map(|row| q_row[row] * v[row]).sum();
+            let scale = 2.0 * dot / v_norm_sq;
+            for row in col..rows {
+                q_row[row] -= scale * v[row];
+            }
+        }
+        for row in r.iter_mut().skip(col + 1) {
+            row[col] = 0.0;
+        }
+    }
+
+    (q, r)
+}
+
+// solves Rx = b where R is upper triangular and at least as tall as it is wide
+fn back_substitute(r: &[Vec<f64>], b: &[Vec<f64>], size: usize) -> Option<Matrix> {
+    let tolerance = default_tolerance(r);
+    let (_, b_cols) = dims(b);
+    let mut x = vec![vec![0.0; b_cols]; size];
+    for i in (0..size).rev() {
+        if r[i][i].abs() <= tolerance {
+            return None;
+        }
+        for col in 0..b_cols {
+            let sum: f64 = (i + 1..size).map(|k| r[i][k] * x[k][col]).sum();
+            x[i][col] = (b[i][col] - sum) / r[i][i];
+        }
+    }
+    Some(x)
+}
+
+pub enum SolveError {
+    Singular,
+    RankDeficient,
+    Incompatible(usize, usize, usize, usize),
+}
+
+impl From<SolveError> for RuntimeError {
+    fn from(e: SolveError) -> Self {
+        match e {
+            SolveError::Singular => RuntimeError::BuiltinFuncErr("matrix is singular".to_string()),
+            SolveError::RankDeficient => RuntimeError::BuiltinFuncErr("matrix does not have full rank".to_string()),
+            SolveError::Incompatible(m1, n1, m2, n2) => RuntimeError::BuiltinFuncErr(
+                format!("cannot solve a {m1}x{n1} system with a {m2}x{n2} right hand side")
+            ),
+        }
+    }
+}
+
+// solves AX = B, exactly for square A, in the least squares sense for tall A
+// and with the minimum norm solution for wide A
+pub fn solve(a: &[Vec<f64>], b: &[Vec<f64>]) -> Result<Matrix, SolveError> {
+    let (rows, cols) = dims(a);
+    let (b_rows, b_cols) = dims(b);
+
+    if rows == 0 || rows != b_rows {
+        return Err(SolveError::Incompatible(rows, cols, b_rows, b_cols));
+    }
+
+    if rows == cols {
+        return LuDecomposition::new(a).solve(b).ok_or(SolveError::Singular);
+    }
+
+    if rows > cols {
+        let (q, r) = qr(a);
+        let qtb = multiply(&transpose(&q), b);
+        return back_substitute(&r, &qtb, cols).ok_or(SolveError::RankDeficient);
+    }
+
+    // A' = QR so A = R'Q', solve R'y = b going forwards then x = Qy
+    let (q, r) = qr(&transpose(a));
+    let tolerance = default_tolerance(&r);
+    let mut y = vec![vec![0.0; b_cols]; rows];
+    for i in 0..rows {
+        if r[i][i].abs() <= tolerance {
+            return Err(SolveError::RankDeficient);
+        }
+        for col in 0..b_cols {
+            let sum: f64 = (0..i).map(|k| r[k][i] * y[k][col]).sum();
+            y[i][col] = (b[i][col] - sum) / r[i][i];
+        }
+    }
+    let q_thin: Matrix = q.iter().map(|row| row[..rows].to_vec()).collect();
+    Ok(multiply(&q_thin, &y))
+}
+
+// scaling and squaring with a degree 8 pade approximant, returns None for non finite input
+pub fn expm(mat: &[Vec<f64>]) -> Option<Matrix> {
+    const DEGREE: i32 = 8;
+    let size = mat.len();
+    let norm = norm_1(mat);
+    if !norm.is_finite() {
+        return None;
+    }
+    let squarings = match norm > 0.5 {
+        true => (norm / 0.5).log2().ceil() as i32,
+        false => 0,
+    };
+    let scaled = scale(mat, 0.5f64.powi(squarings));
+
+    let mut coefficient = 1.0;
+    let mut power = identity(size);
+    let mut numerator = identity(size);
+    let mut denominator = identity(size);
+    for k in 1..=DEGREE {
+        coefficient *= (DEGREE - k + 1) as f64 / (k * (2 * DEGREE - k + 1)) as f64;
+        power = multiply(&power, &scaled);
+        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+        numerator = combine(&numerator, &power, |num, p| num + coefficient * p);
+        denominator = combine(&denominator, &power, |num, p| num + sign * coefficient * p);
+    }
+
+    let mut output = LuDecomposition::new(&denominator).solve(&numerator)?;
+    for _ in 0..squarings {
+        output = multiply(&output, &output);
+    }
+    Some(output)
+}
+
+// denman-beavers iteration, returns None when it doesn't converge which happens
+// for singular matrices and matrices with negative real eigenvalues
+pub fn sqrtm(mat: &[Vec<f64>]) -> Option<Matrix> {
+    let mut y = mat.to_vec();
+    let mut z = identity(mat.len());
+    for _ in 0..100 {
+        let y_inverse = LuDecomposition::new(&y).inverse()?;
+        let z_inverse = LuDecomposition::new(&z).inverse()?;
+        let next_y = combine(&y, &z_inverse, |num1, num2| (num1 + num2) / 2.0);
+        z = combine(&z, &y_inverse, |num1, num2| (num1 + num2) / 2.0);
+        // convergence is quadratic so the step after a small change is accurate to full precision
+        let change = norm_1(&combine(&next_y, &y, |num1, num2| num1 - num2));
+        y = next_y;
+        if change <= 1e-10 * norm_1(&y) {
+            return Some(y);
+        }
+    }
+    None
+}
+
+// inverse scaling and squaring, take square roots until the matrix is close to I
+// then sum the series log(I + E) = E - E^2/2 + E^3/3 - ...
+pub fn logm(mat: &[Vec<f64>]) -> Option<Matrix> {
+    let size = mat.len();
+    let mut root = mat.to_vec();
+    let mut square_roots = 0;
+    let minus_identity = |mat: &Matrix| combine(mat, &identity(size), |num1, num2| num1 - num2);
+    while norm_1(&minus_identity(&root)) > 0.25 {
+        if square_roots >= 50 {
+            return None;
+        }
+        root = sqrtm(&root)?;
+        square_roots += 1;
+    }
+
+    let e = minus_identity(&root);
+    let mut term = e.clone();
+    let mut output = e.clone();
+    for k in 2..100 {
+        term = multiply(&term, &e);
+        let sign = if k % 2 == 0 { -1.0 } else { 1.0 };
+        output = combine(&output, &term, |num, t| num + sign * t / k as f64);
+        if norm_1(&term) / (k as f64) <= f64::EPSILON * norm_1(&output) {
+            break;
+        }
+    }
+    Some(scale(&output, 2f64.powi(square_roots)))
+}
+
+pub fn is_symmetric(mat: &[Vec<f64>]) -> bool {
+    let tolerance = 1e-12 * norm_1(mat);
+    mat.iter()
+        .enumerate()
+        .all(|(i, row)| row.iter().enumerate().all(|(j, num)| (num - mat[j][i]).abs() <= tolerance))
+}
+
+// A = R'R with R upper triangular, None if A is not positive definite
+pub fn cholesky(mat: &[Vec<f64>]) -> Option<Matrix> {
+    let size = mat.len();
+    let mut r = vec![vec![0.0; size]; size];
+    for j in 0..size {
+        let diagonal = mat[j][j] - (0..j).map(|k| r[k][j] * r[k][j]).sum::<f64>();
+        if diagonal <= 0.0 || !diagonal.is_finite() {
+            return None;
+        }
+        r[j][j] = diagonal.sqrt();
+        for i in j + 1..size {
+            let sum: f64 = (0..j).map(|k| r[k][j] * r[k][i]).sum();
+            r[j][i] = (mat[j][i] - sum) / r[j][j];
+        }
+    }
+    Some(r)
+}
+
+// cyclic jacobi rotations for symmetric matrices, eigenvalues come out ascending
+// with the matching eigenvectors as columns
+pub fn symmetric_eigen(mat: &[Vec<f64>]) -> (Vec<f64>, Matrix) {
+    let size = mat.len();
+    let mut a = mat.to_vec();
+    let mut v = identity(size);
+
+    for _ in 0..100 {
+        let off_diagonal: f64 = (0..size)
+            .flat_map(|i| (0..size).filter(move |j| *j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i][j] * a[i][j])
+            .sum();
+        let total: f64 = a.iter().flatten().map(|num| num * num).sum();
+        if off_diagonal <= f64::EPSILON * f64::EPSILON * total {
+            break;
+        }
+        for p in 0..size {
+            for q in p + 1..size {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = match theta.abs() > 1e150 {
+                    true => 1.0 / (2.0 * theta),
+                    false => theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt()),
+                };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                rotate_columns(&mut a, p, q, c, s);
+                rotate_columns(&mut v, p, q, c, s);
+                let (row_p, row_q) = (a[p].clone(), a[q].clone());
+                for (k, (num_p, num_q)) in iter::zip(row_p, row_q).enumerate() {
+                    a[p][k] = c * num_p - s * num_q;
+                    a[q][k] = s * num_p + c * num_q;
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..size).collect();
+    order.sort_by(|i, j| a[*i][*i].total_cmp(&a[*j][*j]));
+    let values = order.iter().map(|&i| a[i][i]).collect();
+    let vectors = v.iter().map(|row| order.iter().map(|&j| row[j]).collect()).collect();
+    (values, normalize_columns(vectors))
+}
+
+fn rotate_columns(mat: &mut Matrix, p: usize, q: usize, c: f64, s: f64) {
+    for row in mat.iter_mut() {
+        let (num_p, num_q) = (row[p], row[q]);
+        row[p] = c * num_p - s * num_q;
+        row[q] = s * num_p + c * num_q;
+    }
+}
+
+// unit length columns with the largest entry positive so results don't flip sign between calls
+fn normalize_columns(mut mat: Matrix) -> Matrix {
+    let (_, cols) = dims(&mat);
+    for j in 0..cols {
+        let norm = mat.iter().map(|row| row[j] * row[j]).sum::<f64>().sqrt();
+        let largest = mat.iter().map(|row| row[j]).fold(0.0, |acc: f64, num| if num.abs() > acc.abs() { num } else { acc });
+        if norm == 0.0 {
+            continue;
+        }
+        for row in mat.iter_mut() {
+            row[j] = row[j] / norm * largest.signum();
+        }
+    }
+    mat
+}
+
+// eigenvalues of a general square matrix, reduced to hessenberg form then
+// shifted QR iteration in complex arithmetic so complex conjugate pairs fall out
+// naturally, sorted by real part then imaginary part
+pub fn eigenvalues(mat: &[Vec<f64>]) -> Option<Vec<Complex>> {
+    let size = mat.len();
+    let norm = norm_1(mat);
+    if !norm.is_finite() {
+        return None;
+    }
+    let mut h: Vec<Vec<Complex>> = hessenberg(mat).iter()
+        .map(|row| row.iter().map(|num| Complex::from(*num)).collect())
+        .collect();
+    let mut values = vec![Complex::default(); size];
+    let mut hi = size;
+    let mut iterations = 0;
+
+    while hi > 0 {
+        let end = hi - 1;
+        let mut lo = end;
+        while lo > 0 && h[lo][lo - 1].abs() > f64::EPSILON * (h[lo - 1][lo - 1].abs() + h[lo][lo].abs()) {
+            lo -= 1;
+        }
+        if lo == end {
+            values[end] = h[end][end];
+            hi -= 1;
+            iterations = 0;
+            continue;
+        }
+        if lo + 1 == end {
+            (values[lo], values[end]) = two_by_two_eigenvalues(h[lo][lo], h[lo][end], h[end][lo], h[end][end]);
+            hi -= 2;
+            iterations = 0;
+            continue;
+        }
+        if lo > 0 {
+            h[lo][lo - 1] = Complex::default();
+        }
+        iterations += 1;
+        if iterations > 100 * size {
+            return None;
+        }
+
+        // wilkinson shift from the trailing 2x2 block, with an occasional
+        // exceptional shift to break out of cycles
+        let (a, b, c, d) = (h[end - 1][end - 1], h[end - 1][end], h[end][end - 1], h[end][end]);
+        let (first, second) = two_by_two_eigenvalues(a, b, c, d);
+        let shift = match iterations % 11 == 10 {
+            true => d + Complex::from(c.abs()),
+            false if (first - d).abs() < (second - d).abs() => first,
+            false => second,
+        };
+
+        for (k, row) in h.iter_mut().enumerate().take(end + 1).skip(lo) {
+            row[k] = row[k] - shift;
+        }
+        let mut rotations = Vec::new();
+        for k in lo..end {
+            let (x, y) = (h[k][k], h[k + 1][k]);
+            let r = x.abs().hypot(y.abs());
+            if r == 0.0 {
+                rotations.push((Complex::from(1.0), Complex::default()));
+                continue;
+            }
+            let (c, s) = (x / Complex::from(r), y / Complex::from(r));
+            let (upper, lower) = h.split_at_mut(k + 1);
+            for (top, bottom) in iter::zip(upper[k].iter_mut(), lower[0].iter_mut()).take(end + 1).skip(k) {
+                (*top, *bottom) = (c.conj() * *top + s.conj() * *bottom, -s * *top + c * *bottom);
+            }
+            rotations.push((c, s));
+        }
+        for (k, (c, s)) in iter::zip(lo..end, rotations) {
+            for row in h.iter_mut().take(end + 1).skip(lo) {
+                let (left, right) = (row[k], row[k + 1]);
+                row[k] = left * c + right * s;
+                row[k + 1] = -left * s.conj() + right * c.conj();
+            }
+        }
+        for (k, row) in h.iter_mut().enumerate().take(end + 1).skip(lo) {
+            row[k] = row[k] + shift;
+        }
+    }
+
+    // clean up the rounding noise left on real eigenvalues
+    for value in values.iter_mut() {
+        if value.im.abs() <= 1e-12 * norm.max(value.abs()) {
+            value.im = 0.0;
+        }
+    }
+    values.sort_by(|z, w| z.re.total_cmp(&w.re).then(z.im.total_cmp(&w.im)));
+    Some(values)
+}
+
+// roots of the characteristic polynomial of [a, b; c, d], the second root comes from
+// the determinant to avoid cancellation
+fn two_by_two_eigenvalues(a: Complex, b: Complex, c: Complex, d: Complex) -> (Complex, Complex) {
+    let half_trace = (a + d) / Complex::from(2.0);
+    let det = a * d - b * c;
+    let root = (half_trace * half_trace - det).sqrt();
+    let first = match (half_trace + root).abs() >= (half_trace - root).abs() {
+        true => half_trace + root,
+        false => half_trace - root,
+    };
+    match first == Complex::default() {
+        true => (first, first),
+        false => (first, det / first),
+    }
+}
+
+// householder reduction to upper hessenberg form with the same eigenvalues
+fn hessenberg(mat: &[Vec<f64>]) -> Matrix {
+    let size = mat.len();
+    let mut h = mat.to_vec();
+    for col in 0..size.saturating_sub(2) {
+        let norm = (col + 1..size).map(|row| h[row][col].powi(2)).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            continue;
+        }
+        let alpha = match h[col + 1][col] > 0.0 {
+            true => -norm,
+            false => norm,
+        };
+        let mut v: Vec<f64> = (0..size).map(|row| if row <= col { 0.0 } else { h[row][col] }).collect();
+        v[col + 1] -= alpha;
+        let v_norm_sq: f64 = v.iter().map(|num| num * num).sum();
+        if v_norm_sq == 0.0 {
+            continue;
+        }
+        // H = P H P with P = I - 2vv'/v'v
+        for j in 0..size {
+            let dot: f64 = iter::zip(&h, &v).map(|(row, v_num)| v_num * row[j]).sum();
+            for (row, v_num) in iter::zip(h.iter_mut(), &v) {
+                row[j] -= 2.0 * dot / v_norm_sq * v_num;
+            }
+        }
+        for row in h.iter_mut() {
+            let dot: f64 = iter::zip(row.iter(), &v).map(|(num, v_num)| num * v_num).sum();
+            for (num, v_num) in iter::zip(row.iter_mut(), &v) {
+                *num -= 2.0 * dot / v_norm_sq * v_num;
+            }
+        }
+    }
+    h
+}
+
+// eigenvectors by inverse iteration, one column per eigenvalue. Repeated
+// eigenvalues take the rest of their vectors from the null space of A - λI so
+// diagonalizable matrices get a full independent set
+pub fn eigenvectors(mat: &[Vec<f64>], values: &[Complex]) -> Vec<Vec<Complex>> {
+    let size = mat.len();
+    let norm = norm_1(mat).max(f64::MIN_POSITIVE);
+    let mut vectors: Vec<Vec<Complex>> = Vec::new();
+
+    for value in values {
+        let shifted: Vec<Vec<Complex>> = mat.iter()
+            .enumerate()
+            .map(|(i, row)| row.iter().enumerate().map(|(j, num)| match i == j {
+                true => Complex::from(*num) - *value,
+                false => Complex::from(*num),
+            }).collect())
+            .collect();
+        let repeats: Vec<&Vec<Complex>> = iter::zip(values, &vectors)
+            .filter(|(other, _)| (**other - *value).abs() <= 1e-8 * norm)
+            .map(|(_, vector)| vector)
+            .collect();
+
+        let start: Vec<Complex> = (0..size).map(|i| Complex::from(1.0 + i as f64 / size as f64)).collect();
+        // defective matrices don't have enough independent eigenvectors, in which
+        // case the inverse iteration vector gets repeated
+        let mut x = null_space(&shifted, 1e-8 * norm).into_iter()
+            .filter(|_| !repeats.is_empty())
+            .find_map(|vector| orthogonalize(vector, &repeats))
+            .unwrap_or_else(|| inverse_iteration(&shifted, &start, norm));
+
+        // rotate so the largest entry is real and positive, then clear rounding noise
+        let largest = x.iter().fold(Complex::default(), |acc, num| if num.abs() > acc.abs() { *num } else { acc });
+        if largest.abs() > 0.0 {
+            let phase = largest.conj() / Complex::from(largest.abs());
+            x = x.iter().map(|num| *num * phase).collect();
+        }
+        for num in x.iter_mut() {
+            if num.re.abs() <= 1e-13 {
+                num.re = 0.0;
+            }
+            if num.im.abs() <= 1e-13 {
+                num.im = 0.0;
+            }
+        }
+        vectors.push(x);
+    }
+
+    // vectors are built one per eigenvalue, return them as columns
+    (0..size).map(|i| vectors.iter().map(|vector| vector[i]).collect()).collect()
+}
+
+// the shift is moved slightly off the eigenvalue so the system isn't exactly
+// singular, the eigenvectors of (A - μI)^-1 are the same as A's so nothing is lost
+fn inverse_iteration(shifted: &[Vec<Complex>], start: &[Complex], norm: f64) -> Vec<Complex> {
+    let offset = Complex::from(1e-14 * norm);
+    let perturbed: Vec<Vec<Complex>> = shifted.iter()
+        .enumerate()
+        .map(|(i, row)| row.iter().enumerate().map(|(j, num)| if i == j { *num - offset } else { *num }).collect())
+        .collect();
+    let mut x = start.to_vec();
+    for _ in 0..3 {
+        x = complex_solve(&perturbed, &x, f64::EPSILON * norm);
+        x = normalize_vector(x).unwrap_or(vec![Complex::default(); shifted.len()]);
+    }
+    x
+}
+
+// gram schmidt against already unit length vectors, None if nothing is left
+fn orthogonalize(mut x: Vec<Complex>, others: &[&Vec<Complex>]) -> Option<Vec<Complex>> {
+    x = normalize_vector(x)?;
+    for other in others {
+        let dot: Complex = iter::zip(other.iter(), &x).map(|(o, num)| o.conj() * *num).sum();
+        for (num, o) in iter::zip(x.iter_mut(), other.iter()) {
+            *num = *num - dot * *o;
+        }
+    }
+    match x.iter().map(|num| num.abs().powi(2)).sum::<f64>().sqrt() > 1e-8 {
+        true => normalize_vector(x),
+        false => None,
+    }
+}
+
+fn normalize_vector(x: Vec<Complex>) -> Option<Vec<Complex>> {
+    let length = x.iter().map(|num| num.abs().powi(2)).sum::<f64>().sqrt();
+    match length > 0.0 && length.is_finite() {
+        true => Some(x.iter().map(|num| *num / Complex::from(length)).collect()),
+        false => None,
+    }
+}
+
+// basis for the null space read off of the reduced row echelon form
+fn null_space(mat: &[Vec<Complex>], tolerance: f64) -> Vec<Vec<Complex>> {
+    let size = mat.len();
+    let mut r = mat.to_vec();
+    let mut pivots = Vec::new();
+    for col in 0..size {
+        let row = pivots.len();
+        if row == size {
+            break;
+        }
+        let pivot_row = (row..size)
+            .max_by(|i, j| r[*i][col].abs().total_cmp(&r[*j][col].abs()))
+            .unwrap_or(row);
+        if r[pivot_row][col].abs() <= tolerance {
+            continue;
+        }
+        r.swap(pivot_row, row);
+        let pivot = r[row][col];
+        let pivot_values: Vec<Complex> = r[row].iter().map(|num| *num / pivot).collect();
+        for (i, other) in r.iter_mut().enumerate() {
+            match i == row {
+                true => other.clone_from(&pivot_values),
+                false => {
+                    let factor = other[col];
+                    for (num, pivot_num) in iter::zip(other.iter_mut(), &pivot_values) {
+                        *num = *num - factor * *pivot_num;
+                    }
+                },
+            }
+        }
+        pivots.push(col);
+    }
+
+    (0..size)
+        .filter(|col| !pivots.contains(col))
+        .map(|free| {
+            let mut x = vec![Complex::default(); size];
+            x[free] = Complex::from(1.0);
+            for (row, &pivot_col) in pivots.iter().enumerate() {
+                x[pivot_col] = -r[row][free];
+            }
+            x
+        })
+        .collect()
+}
+
+// gaussian elimination with partial pivoting, near zero pivots are replaced with
+// the tolerance which is what inverse iteration wants from an exactly singular system
+fn complex_solve(a: &[Vec<Complex>], b: &[Complex], tolerance: f64) -> Vec<Complex> {
+    let size = a.len();
+    let mut a = a.to_vec();
+    let mut x = b.to_vec();
+    for col in 0..size {
+        let pivot_row = (col..size)
+            .max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))
+            .unwrap_or(col);
+        a.swap(pivot_row, col);
+        x.swap(pivot_row, col);
+        if a[col][col].abs() <= tolerance {
+            a[col][col] = Complex::from(tolerance.max(f64::MIN_POSITIVE));
+        }
+        for row in col + 1..size {
+            let factor = a[row][col] / a[col][col];
+            let pivot_values = a[col].clone();
+            for (num, pivot_num) in iter::zip(a[row].iter_mut(), pivot_values).skip(col) {
+                *num = *num - factor * pivot_num;
+            }
+            x[row] = x[row] - factor * x[col];
+        }
+    }
+    for i in (0..size).rev() {
+        let sum: Complex = (i + 1..size).map(|k| a[i][k] * x[k]).sum();
+        x[i] = (x[i] - sum) / a[i][i];
+    }
+    x
+}
+
+pub struct Svd {
+    pub u: Matrix,
+    pub s: Vec<f64>,
+    pub v: Matrix,
+}
+
+// one sided jacobi, the economy sized A = U diag(s) V' with singular values descending
+pub fn svd(mat: &[Vec<f64>]) -> Svd {
+    let (rows, cols) = dims(mat);
+    if rows < cols {
+        let Svd {u, s, v} = svd(&transpose(mat));
+        return Svd {u: v, s, v: u};
+    }
+
+    let mut u = mat.to_vec();
+    let mut v = identity(cols);
+    for _ in 0..100 {
+        let mut rotated = false;
+        for p in 0..cols {
+            for q in p + 1..cols {
+                let alpha: f64 = u.iter().map(|row| row[p] * row[p]).sum();
+                let beta: f64 = u.iter().map(|row| row[q] * row[q]).sum();
+                let gamma: f64 = u.iter().map(|row| row[p] * row[q]).sum();
+                if gamma.abs() <= f64::EPSILON * (alpha * beta).sqrt() || gamma == 0.0 {
+                    continue;
+                }
+                rotated = true;
+                let zeta = (beta - alpha) / (2.0 * gamma);
+                let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+                let c = 1.0 / (1.0 + t * t).sqrt();
+                let s = c * t;
+                rotate_columns(&mut u, p, q, c, s);
+                rotate_columns(&mut v, p, q, c, s);
+            }
+        }
+        if !rotated {
+            break;
+        }
+    }
+
+    let norms: Vec<f64> = (0..cols).map(|j| u.iter().map(|row| row[j] * row[j]).sum::<f64>().sqrt()).collect();
+    let mut order: Vec<usize> = (0..cols).collect();
+    order.sort_by(|i, j| norms[*j].total_cmp(&norms[*i]));
+    let u = u.iter()
+        .map(|row| order.iter().map(|&j| if norms[j] == 0.0 { 0.0 } else { row[j] / norms[j] }).collect())
+        .collect();
+    let v = v.iter().map(|row| order.iter().map(|&j| row[j]).collect()).collect();
+    let s = order.iter().map(|&j| norms[j]).collect();
+    Svd {u, s, v}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(mat1: &[Vec<f64>], mat2: &[Vec<f64>]) {
+        assert_eq!(dims(mat1), dims(mat2));
+        for (num1, num2) in iter::zip(mat1.iter().flatten(), mat2.iter().flatten()) {
+            assert!((num1 - num2).abs() < 1e-9, "{:?} != {:?}", mat1, mat2);
+        }
+    }
+
+    #[test]
+    fn lu_reconstructs() {
+        let a = vec![vec![2.0, 1.0, 1.0], vec![4.0, -6.0, 0.0], vec![-2.0, 7.0, 2.0]];
+        let lu = LuDecomposition::new(&a);
+        assert_close(&multiply(&lu.p(), &a), &multiply(&lu.l(), &lu.u()));
+        assert!((lu.det() - -16.0).abs() < 1e-9);
+    }
+    #[test]
+    fn singular_matrix() {
+        let lu = LuDecomposition::new(&[vec![1.0, 2.0], vec![2.0, 4.0]]);
+        assert!(lu.is_singular);
+        assert_eq!(lu.det(), 0.0);
+        assert!(lu.inverse().is_none());
+    }
+    #[test]
+    fn large_inverse() {
+        let size = 12;
+        let a: Matrix = (0..size)
+            .map(|i| (0..size).map(|j| 1.0 / (1 + i + j) as f64 + if i == j { 1.0 } else { 0.0 }).collect())
+            .collect();
+        let inverse = LuDecomposition::new(&a).inverse().unwrap();
+        assert_close(&multiply(&a, &inverse), &identity(size));
+    }
+    #[test]
+    fn least_squares() {
+        // fitting y = c0 + c1 x through (0, 1), (1, 3), (2, 5), (3, 7)
+        let a = vec![vec![1.0, 0.0], vec![1.0, 1.0], vec![1.0, 2.0], vec![1.0, 3.0]];
+        let b = vec![vec![1.0], vec![3.0], vec![5.0], vec![7.0]];
+        assert_close(&solve(&a, &b).ok().unwrap(), &[vec![1.0], vec![2.0]]);
+    }
+    #[test]
+    fn exponential_of_rotation() {
+        let a = vec![vec![0.0, 3.0], vec![-3.0, 0.0]];
+        let expected = vec![vec![3f64.cos(), 3f64.sin()], vec![-3f64.sin(), 3f64.cos()]];
+        assert_close(&expm(&a).unwrap(), &expected);
+    }
+    #[test]
+    fn square_root_and_log() {
+        let a = vec![vec![4.0, 1.0, 0.0], vec![1.0, 3.0, 1.0], vec![0.0, 1.0, 2.0]];
+        let root = sqrtm(&a).unwrap();
+        assert_close(&multiply(&root, &root), &a);
+        assert_close(&expm(&logm(&a).unwrap()).unwrap(), &a);
+        assert!(sqrtm(&[vec![-1.0, 0.0], vec![0.0, 1.0]]).is_none());
+    }
+    #[test]
+    fn symmetric_eigenvectors() {
+        let a = vec![vec![2.0, 1.0, 0.0], vec![1.0, 2.0, 1.0], vec![0.0, 1.0, 2.0]];
+        let (values, vectors) = symmetric_eigen(&a);
+        let expected = [2.0 - 2f64.sqrt(), 2.0, 2.0 + 2f64.sqrt()];
+        for (value, expected) in iter::zip(&values, expected) {
+            assert!((value - expected).abs() < 1e-12);
+        }
+        let diagonal: Matrix = (0..3).map(|i| (0..3).map(|j| if i == j { values[i] } else { 0.0 }).collect()).collect();
+        assert_close(&multiply(&a, &vectors), &multiply(&vectors, &diagonal));
+    }
+    #[test]
+    fn complex_eigenvalues() {
+        let rotation = vec![vec![0.0, -1.0, 0.0], vec![1.0, 0.0, 0.0], vec![0.0, 0.0, 3.0]];
+        let values = eigenvalues(&rotation).unwrap();
+        assert_eq!(values.len(), 3);
+        assert!((values[0] - Complex::new(0.0, -1.0)).abs() < 1e-12);
+        assert!((values[1] - Complex::new(0.0, 1.0)).abs() < 1e-12);
+        assert_eq!(values[2], Complex::from(3.0));
+
+        let vectors = eigenvectors(&rotation, &values);
+        for (k, value) in values.iter().enumerate() {
+            for (row, vector_num) in iter::zip(&rotation, &vectors) {
+                let product: Complex = iter::zip(row, &vectors).map(|(num, v_row)| Complex::from(*num) * v_row[k]).sum();
+                assert!((product - *value * vector_num[k]).abs() < 1e-9);
+            }
+        }
+    }
+    #[test]
+    fn svd_reconstructs() {
+        let a = vec![vec![3.0, 2.0, 2.0], vec![2.0, 3.0, -2.0]];
+        let Svd {u, s, v} = svd(&a);
+        assert!((s[0] - 5.0).abs() < 1e-12 && (s[1] - 3.0).abs() < 1e-12);
+        let sigma: Matrix = (0..2).map(|i| (0..2).map(|j| if i == j { s[i] } else { 0.0 }).collect()).collect();
+        assert_close(&multiply(&multiply(&u, &sigma), &transpose(&v)), &a);
+    }
+    #[test]
+    fn cholesky_factor() {
+        let a = vec![vec![4.0, 12.0, -16.0], vec![12.0, 37.0, -43.0], vec![-16.0, -43.0, 98.0]];
+        let r = cholesky(&a).unwrap();
+        assert_close(&r, &[vec![2.0, 6.0, -8.0], vec![0.0, 1.0, 5.0], vec![0.0, 0.0, 3.0]]);
+        assert!(cholesky(&[vec![1.0, 2.0], vec![2.0, 1.0]]).is_none());
+    }
+    #[test]
+    fn minimum_norm() {
+        let a = vec![vec![1.0, 1.0]];
+        let b = vec![vec![2.0]];
+        assert_close(&solve(&a, &b).ok().unwrap(), &[vec![1.0], vec![1.0]]);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{fs, path::PathBuf};
+
+// lines typed into the tui, kept in a file in the config dir between runs. browsing
+// with up and down walks back from the newest line and remembers what was being typed
+#[derive(Debug, Default)]
+pub struct LineHistory {
+    lines: Vec<String>,
+    path: Option<PathBuf>,
+    browse_index: Option<usize>,
+    draft: String,
+}
+
+// ctrl+r, found is the index of the line that currently matches
+#[derive(Debug, Default)]
+pub struct HistorySearch {
+    pub query: String,
+    pub found: Option<usize>,
+}
+
+impl LineHistory {
+    // a missing file just means nothing has been saved yet
+    pub fn load(path: Option<PathBuf>, max_len: usize) -> LineHistory {
+        let lines = path.as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        let mut history = LineHistory {lines, path, ..Default::default()};
+        history.trim(max_len);
+        history
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn push(&mut self, line: &str, max_len: usize) {
+        self.browse_index = None;
+        let line = line.replace(['\r', '\n'], " ");
+        if line.trim().is_empty() || self.lines.last() == Some(&line) {
+            return;
+        }
+        self.lines.push(line);
+        self.trim(max_len);
+        self.save();
+    }
+
+    // older line for up, current is what's in the input line when browsing starts
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let index = match self.browse_index {
+            Some(index) => index.checked_sub(1)?,
+            None => {
+                current.clone_into(&mut self.draft);
+                self.lines.len().checked_sub(1)?
+            },
+        };
+        self.browse_index = Some(index);
+        Some(&self.lines[index])
+    }
+
+    // newer line for down, past the newest gives back the draft
+    pub fn newer(&mut self) -> Option<&str> {
+        let index = self.browse_index? + 1;
+        match index < self.lines.len() {
+            true => {
+                self.browse_index = Some(index);
+                Some(&self.lines[index])
+            },
+            false => {
+                self.browse_index = None;
+                Some(&self.draft)
+            },
+        }
+    }
+
+    pub fn stop_browsing(&mut self) {
+        self.browse_index = None;
+    }
+
+    // newest line containing query that comes before the index
+    pub fn find(&self, query: &str, before: usize) -> Option<usize> {
+        self.lines[..before.min(self.lines.len())].iter().rposition(|line| line.contains(query))
+    }
+
+    fn trim(&mut self, max_len: usize) {
+        let extra = self.lines.len().saturating_sub(max_len);
+        self.lines.drain(..extra);
+    }
+
+    // history is a nice to have, so failing to write it isn't reported
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            let _ = fs::write(path, self.lines.join("\n") + "\n");
+        }
+    }
+}
+
+impl HistorySearch {
+    // searching again after a new query starts over from the newest line
+    pub fn update(&mut self, history: &LineHistory, from_start: bool) {
+        let before = match (from_start, self.found) {
+            (false, Some(found)) => found,
+            _ => history.lines().len(),
+        };
+        if let Some(found) = history.find(&self.query, before) {
+            self.found = Some(found);
+        } else if from_start {
+            self.found = None;
+        }
+    }
+
+    pub fn matched<'a>(&self, history: &'a LineHistory) -> Option<&'a str> {
+        self.found.map(|found| history.lines()[found].as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browsing() {
+        let mut history = LineHistory::default();
+        for line in ["1", "2", "2", "", "3"] {
+            history.push(line, 10);
+        }
+        assert_eq!(history.lines(), ["1", "2", "3"]);
+        assert_eq!(history.older("draft"), Some("3"));
+        assert_eq!(history.older(""), Some("2"));
+        assert_eq!(history.older(""), Some("1"));
+        assert_eq!(history.older(""), None);
+        assert_eq!(history.newer(), Some("2"));
+        assert_eq!(history.newer(), Some("3"));
+        assert_eq!(history.newer(), Some("draft"));
+        assert_eq!(history.newer(), None);
+    }
+    #[test]
+    fn search() {
+        let mut history = LineHistory::default();
+        for line in ["x = 1", "y = 2", "x + y", "z"] {
+            history.push(line, 10);
+        }
+        let mut search = HistorySearch {query: "x".to_string(), found: None};
+        search.update(&history, true);
+        assert_eq!(search.matched(&history), Some("x + y"));
+        search.update(&history, false);
+        assert_eq!(search.matched(&history), Some("x = 1"));
+        search.update(&history, false);
+        assert_eq!(search.matched(&history), Some("x = 1"));
+        search.query.push_str(" +");
+        search.update(&history, true);
+        assert_eq!(search.matched(&history), Some("x + y"));
+    }
+    #[test]
+    fn saved_to_file() {
+        let path = std::env::temp_dir().join(format!("lcalc_history_{}", std::process::id()));
+        let mut history = LineHistory::load(Some(path.clone()), 2);
+        for line in ["a", "b", "c"] {
+            history.push(line, 2);
+        }
+        assert_eq!(LineHistory::load(Some(path.clone()), 2).lines(), ["b", "c"]);
+        assert_eq!(LineHistory::load(Some(path.clone()), 1).lines(), ["c"]);
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,7 +1,20 @@
 mod ui;
 mod executor;
+mod complex;
+mod linalg;
+mod calculus;
+pub mod environment;
 pub mod state;
 pub mod config;
+pub mod theme;
 pub mod commands;
 pub mod user_scripts;
 pub mod builtin_functions;
+pub mod batch;
+pub mod session;
+pub mod input;
+pub mod line_history;
+pub mod workspace;
+pub mod plot;
+
+pub use executor::Value;
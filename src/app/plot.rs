@@ -0,0 +1,441 @@
+use std::iter;
+
+use super::{executor::Value, state::Context};
+
+// braille gives two dots per column, so this covers a full screen with room to spare
+const SAMPLES: usize = 400;
+pub const DEFAULT_X_BOUNDS: [f64; 2] = [-10.0, 10.0];
+const ZOOM_STEP: f64 = 0.8;
+const PAN_STEP: f64 = 0.1;
+const OUTLIER_RATIO: f64 = 10.0;
+const MAX_BINS: usize = 200;
+// space left on each side of a bar, as a fraction of its width
+const BAR_GAP: f64 = 0.1;
+
+// anything drawn in the plot popup, the labels come from how the plot was asked for
+#[derive(Debug)]
+pub struct Plot {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub x_bounds: [f64; 2],
+    pub y_bounds: [f64; 2],
+    pub contents: PlotContents,
+}
+
+#[derive(Debug)]
+pub enum PlotContents {
+    // sampled again after zooming or moving, which shows detail instead of stretching the old points
+    Functions(Vec<Series>),
+    // the columns of a matrix against its first column, as dots or joined up
+    Points {series: Vec<Series>, joined: bool},
+    Bars(Vec<Bar>),
+}
+
+// one set of points, split wherever there's no real value so no line is drawn across the gap
+#[derive(Debug)]
+pub struct Series {
+    pub name: String,
+    pub segments: Vec<Vec<(f64, f64)>>,
+}
+
+#[derive(Debug)]
+pub struct Bar {
+    pub left: f64,
+    pub right: f64,
+    pub height: f64,
+}
+
+impl Plot {
+    // a function with nothing to draw is an error, since it's usually the wrong function or range
+    pub fn functions(context: &Context, names: Vec<String>, x_bounds: [f64; 2]) -> Result<Plot, String> {
+        if x_bounds[0] >= x_bounds[1] {
+            return Err("plot range has to go from a smaller number to a bigger one".to_string());
+        }
+        let mut series = Vec::new();
+        for name in &names {
+            if context.get_function(name).is_none() && !matches!(context.get_var(name), Some(Value::Function(_))) {
+                return Err(format!("unknown function: {name}"));
+            }
+            let (sampled, first_error) = sample(context, name, x_bounds);
+            if sampled.segments.is_empty() {
+                return Err(match first_error {
+                    Some(error) => format!("{name} can't be plotted: {error}"),
+                    None => format!("{name} has no real values from {} to {}", x_bounds[0], x_bounds[1]),
+                });
+            }
+            series.push(sampled);
+        }
+        Ok(Plot {
+            title: format!("plot {}", names.join(", ")),
+            x_label: "x".to_string(),
+            y_label: String::new(),
+            x_bounds,
+            y_bounds: fit_function_bounds(series_values(&series, |point| point.1)),
+            contents: PlotContents::Functions(series),
+        })
+    }
+
+    // a vector is drawn against its indices, a matrix's other columns against its first
+    pub fn data(label: &str, value: &Value, joined: bool) -> Result<Plot, String> {
+        let mut columns = real_columns(label, value)?;
+        let (x_label, xs, first_y) = match columns.len() {
+            1 => ("index".to_string(), (1..=columns[0].len()).map(|i| i as f64).collect(), 1),
+            _ => (format!("{label}[:, 1]"), columns.remove(0), 2),
+        };
+        let series: Vec<_> = columns.iter().enumerate()
+            .map(|(i, ys)| Series {
+                name: match first_y {
+                    1 => label.to_string(),
+                    _ => format!("{label}[:, {}]", first_y + i),
+                },
+                segments: split_at_gaps(xs.iter().copied().zip(ys.iter().copied())),
+            })
+            .collect();
+        Ok(Plot {
+            title: format!("plot data {label}"),
+            y_label: match series.len() {
+                1 => series[0].name.clone(),
+                _ => String::new(),
+            },
+            x_label,
+            x_bounds: fit_bounds(series_values(&series, |point| point.0)),
+            y_bounds: fit_bounds(series_values(&series, |point| point.1)),
+            contents: PlotContents::Points {series, joined},
+        })
+    }
+
+    // bins default to sturges' rule, which suits the few hundred values this usually gets
+    pub fn histogram(label: &str, value: &Value, bins: Option<usize>) -> Result<Plot, String> {
+        let values: Vec<f64> = vector("hist", label, value)?.into_iter().filter(|value| value.is_finite()).collect();
+        if values.is_empty() {
+            return Err(format!("{label} has no real values to count"));
+        }
+        let bins = bins.unwrap_or((values.len() as f64).log2().ceil() as usize + 1);
+        if !(1..=MAX_BINS).contains(&bins) {
+            return Err(format!("bins must be from 1 to {MAX_BINS}"));
+        }
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let [low, high] = match max - min {
+            0.0 => [min - 0.5, max + 0.5],
+            _ => [min, max],
+        };
+        let width = (high - low) / bins as f64;
+        let mut counts = vec![0; bins];
+        for value in &values {
+            counts[(((value - low) / width) as usize).min(bins - 1)] += 1;
+        }
+        let bars: Vec<_> = counts.iter().enumerate()
+            .map(|(i, count)| Bar {left: low + width * i as f64, right: low + width * (i + 1) as f64, height: *count as f64})
+            .collect();
+        let most = counts.iter().copied().max().unwrap_or(0) as f64;
+        Ok(Plot {
+            title: format!("hist {label}, {bins} bins"),
+            x_label: label.to_string(),
+            y_label: "count".to_string(),
+            x_bounds: [low - width * BAR_GAP, high + width * BAR_GAP],
+            y_bounds: [0.0, most * 1.05],
+            contents: PlotContents::Bars(bars),
+        })
+    }
+
+    // one bar per element at its index
+    pub fn bars(label: &str, value: &Value) -> Result<Plot, String> {
+        let values = vector("bar", label, value)?;
+        let bars: Vec<_> = values.iter().enumerate()
+            .map(|(i, height)| Bar {left: i as f64 + 0.5, right: i as f64 + 1.5, height: *height})
+            .collect();
+        Ok(Plot {
+            title: format!("bar {label}"),
+            x_label: "index".to_string(),
+            y_label: label.to_string(),
+            x_bounds: [0.5, values.len() as f64 + 0.5],
+            y_bounds: fit_bounds(values.iter().copied().chain([0.0])),
+            contents: PlotContents::Bars(bars),
+        })
+    }
+
+    // zooms around the middle of the view, in when zooming_in is set and out otherwise
+    pub fn zoom(&mut self, context: &Context, zooming_in: bool) {
+        let factor = match zooming_in {
+            true => ZOOM_STEP,
+            false => 1.0 / ZOOM_STEP,
+        };
+        self.x_bounds = scaled(self.x_bounds, factor);
+        self.y_bounds = scaled(self.y_bounds, factor);
+        self.resample(context);
+    }
+
+    // moves the view by a fraction of its size in each direction
+    pub fn pan(&mut self, context: &Context, x_steps: f64, y_steps: f64) {
+        self.x_bounds = shifted(self.x_bounds, x_steps * PAN_STEP);
+        self.y_bounds = shifted(self.y_bounds, y_steps * PAN_STEP);
+        self.resample(context);
+    }
+
+    fn resample(&mut self, context: &Context) {
+        if let PlotContents::Functions(series) = &mut self.contents {
+            for function in series.iter_mut() {
+                *function = sample(context, &function.name, self.x_bounds).0;
+            }
+        }
+    }
+
+    // charts only draw lines and points, so each bar is filled with vertical lines close
+    // enough together to look solid at this many columns. only the part in view is filled
+    pub fn bar_fill(&self, columns: u16) -> Vec<Vec<(f64, f64)>> {
+        let PlotContents::Bars(bars) = &self.contents else {
+            return Vec::new();
+        };
+        let step = (self.x_bounds[1] - self.x_bounds[0]) / (columns.max(1) as f64 * 2.0);
+        bars.iter()
+            .filter(|bar| bar.height.is_finite())
+            .map(|bar| {
+                let gap = (bar.right - bar.left) * BAR_GAP;
+                let (left, right) = ((bar.left + gap).max(self.x_bounds[0]), (bar.right - gap).min(self.x_bounds[1]));
+                let middle = (bar.left + bar.right) / 2.0;
+                let steps = ((right - left) / step).max(0.0) as usize;
+                iter::once(middle)
+                    .chain((0..=steps).map(|i| left + step * i as f64))
+                    .map(|x| (x, bar.height))
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn x_labels(&self) -> Vec<String> {
+        axis_labels(self.x_bounds)
+    }
+
+    pub fn y_labels(&self) -> Vec<String> {
+        axis_labels(self.y_bounds)
+    }
+}
+
+fn series_values(series: &[Series], coordinate: fn(&(f64, f64)) -> f64) -> impl Iterator<Item = f64> + '_ {
+    series.iter().flat_map(move |series| series.segments.iter().flatten().map(coordinate))
+}
+
+// a single row is taken as a vector the same as a single column
+fn real_columns(label: &str, value: &Value) -> Result<Vec<Vec<f64>>, String> {
+    let rows = match value {
+        Value::Number(num) => vec![vec![*num]],
+        Value::Matrix(rows) => rows.clone(),
+        Value::Complex(_) | Value::ComplexMatrix(_) => return Err(format!("{label} is complex, only real values can be plotted")),
+        Value::Function(_) => return Err(format!("{label} is a function, use plot {label} to draw it")),
+    };
+    match (rows.len(), rows.first().map_or(0, |row| row.len())) {
+        (0, _) | (_, 0) => Err(format!("{label} is empty")),
+        (1, _) => Ok(rows),
+        (_, cols) => Ok((0..cols).map(|j| rows.iter().map(|row| row[j]).collect()).collect()),
+    }
+}
+
+fn vector(kind: &str, label: &str, value: &Value) -> Result<Vec<f64>, String> {
+    let mut columns = real_columns(label, value)?;
+    match columns.len() {
+        1 => Ok(columns.remove(0)),
+        cols => Err(format!("{kind} needs a vector, {label} is {} with {cols} columns", value.short_string())),
+    }
+}
+
+// errors, NaN, infinities and complex results all leave a gap. the first error is
+// returned so a function that fails everywhere can say why
+fn sample(context: &Context, name: &str, x_bounds: [f64; 2]) -> (Series, Option<String>) {
+    let mut first_error = None;
+    let points = (0..=SAMPLES).map(|i| {
+        let x = x_bounds[0] + (x_bounds[1] - x_bounds[0]) * i as f64 / SAMPLES as f64;
+        let y = match context.call(name, vec![Value::Number(x)]) {
+            Ok(Value::Number(y)) => y,
+            Ok(_) => f64::NAN,
+            Err(error) => {
+                first_error.get_or_insert(error.to_string());
+                f64::NAN
+            },
+        };
+        (x, y)
+    });
+    let segments = split_at_gaps(points);
+    (Series {name: name.to_string(), segments}, first_error)
+}
+
+// NaN and infinities end one segment and start the next
+fn split_at_gaps(points: impl Iterator<Item = (f64, f64)>) -> Vec<Vec<(f64, f64)>> {
+    let mut segments = vec![Vec::new()];
+    for (x, y) in points {
+        match (x.is_finite() && y.is_finite(), segments.last_mut()) {
+            (true, Some(segment)) => segment.push((x, y)),
+            (false, Some(segment)) if !segment.is_empty() => segments.push(Vec::new()),
+            _ => {},
+        }
+    }
+    segments.retain(|segment| !segment.is_empty());
+    segments
+}
+
+// the range of the values with a little space around it, flat lines get a unit of room
+fn fit_bounds(values: impl Iterator<Item = f64>) -> [f64; 2] {
+    let (min, max) = values
+        .filter(|value| value.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| (min.min(value), max.max(value)));
+    padded(min, max)
+}
+
+// near a pole like 1/x's a few huge values would squash everything else flat, so when the
+// extremes are far out past the rest they're left off the edges. data is always shown in full
+fn fit_function_bounds(values: impl Iterator<Item = f64>) -> [f64; 2] {
+    let mut values: Vec<f64> = values.filter(|value| value.is_finite()).collect();
+    if values.is_empty() {
+        return [-1.0, 1.0];
+    }
+    values.sort_by(f64::total_cmp);
+    let trim = values.len() / 50;
+    let (min, max) = (values[0], values[values.len() - 1]);
+    let (low, high) = (values[trim], values[values.len() - 1 - trim]);
+    match max - min > OUTLIER_RATIO * (high - low) && high > low {
+        true => padded(low, high),
+        false => padded(min, max),
+    }
+}
+
+fn padded(min: f64, max: f64) -> [f64; 2] {
+    match (min.is_finite() && max.is_finite(), max - min) {
+        (false, _) => [-1.0, 1.0],
+        (true, 0.0) => [min - 1.0, max + 1.0],
+        (true, span) => [min - span * 0.05, max + span * 0.05],
+    }
+}
+
+fn scaled(bounds: [f64; 2], factor: f64) -> [f64; 2] {
+    let middle = (bounds[0] + bounds[1]) / 2.0;
+    let half = (bounds[1] - bounds[0]) / 2.0 * factor;
+    [middle - half, middle + half]
+}
+
+fn shifted(bounds: [f64; 2], fraction: f64) -> [f64; 2] {
+    let offset = (bounds[1] - bounds[0]) * fraction;
+    [bounds[0] + offset, bounds[1] + offset]
+}
+
+// both ends and the middle, short enough to fit under a narrow chart
+fn axis_labels(bounds: [f64; 2]) -> Vec<String> {
+    [bounds[0], (bounds[0] + bounds[1]) / 2.0, bounds[1]].into_iter()
+        .map(|value| match value.abs() {
+            0.0 => "0".to_string(),
+            size if !(0.01..10000.0).contains(&size) => format!("{value:.2e}"),
+            _ => {
+                let text = format!("{value:.2}");
+                text.trim_end_matches('0').trim_end_matches('.').to_string()
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::session::{LineResult, PopupName, Session, StateChange};
+
+    fn first_segments(plot: &Plot) -> &[Vec<(f64, f64)>] {
+        match &plot.contents {
+            PlotContents::Functions(series) | PlotContents::Points {series, ..} => &series[0].segments,
+            PlotContents::Bars(_) => panic!("expected points"),
+        }
+    }
+
+    fn plot(session: &mut Session, line: &str) -> Result<Plot, String> {
+        match session.execute(line) {
+            LineResult::StateChange(StateChange::ShowPopup(PopupName::Plot(plot))) => Ok(plot),
+            LineResult::Error {message, ..} => Err(message),
+            result => panic!("expected a plot, got {result:?}"),
+        }
+    }
+
+    fn bar_heights(plot: &Plot) -> Vec<f64> {
+        match &plot.contents {
+            PlotContents::Bars(bars) => bars.iter().map(|bar| bar.height).collect(),
+            _ => panic!("expected bars"),
+        }
+    }
+
+    #[test]
+    fn gaps_and_bounds() {
+        let mut session = Session::new();
+        session.execute("def f(x) = std.sqrt(x)");
+        session.execute("def g(x) = 1 / (x - x)");
+        let plot = Plot::functions(&session.context, vec!["f".to_string()], [-1.0, 1.0]).unwrap();
+        // negative inputs give complex roots, which aren't drawn
+        assert_eq!(first_segments(&plot).len(), 1);
+        assert!(first_segments(&plot)[0].iter().all(|(x, _)| *x >= 0.0));
+        assert!(plot.y_bounds[0] < 0.0 && plot.y_bounds[1] > 1.0);
+
+        assert!(Plot::functions(&session.context, vec!["g".to_string()], [-1.0, 1.0]).is_err());
+        assert!(Plot::functions(&session.context, vec!["h".to_string()], [-1.0, 1.0]).is_err());
+        assert!(Plot::functions(&session.context, vec!["f".to_string()], [1.0, 1.0]).is_err());
+        assert_eq!(axis_labels([-0.5, 20000.0]), ["-0.5", "9999.75", "2.00e4"]);
+
+        assert_eq!(fit_bounds([1.0, 1.0].into_iter()), [0.0, 2.0]);
+        assert_eq!(fit_bounds((0..=100).map(|i| i as f64)), [-5.0, 105.0]);
+        let spiked = fit_function_bounds((0..100).map(|i| i as f64).chain([1e6, f64::NAN]));
+        assert!(spiked[1] < 200.0);
+    }
+
+    #[test]
+    fn zooming() {
+        let mut session = Session::new();
+        session.execute("def f(x) = x");
+        let mut plot = Plot::functions(&session.context, vec!["f".to_string()], [-10.0, 10.0]).unwrap();
+        plot.zoom(&session.context, true);
+        assert_eq!(plot.x_bounds, [-8.0, 8.0]);
+        assert_eq!(first_segments(&plot)[0].first(), Some(&(-8.0, -8.0)));
+        plot.pan(&session.context, 1.0, 0.0);
+        assert!((plot.x_bounds[0] + 6.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn command() {
+        let mut session = Session::new();
+        session.execute("def f(x) = x ^ 2");
+        session.execute("a = 3");
+        let both = plot(&mut session, "plot f, std.sin from -a to 2 * a").unwrap();
+        assert_eq!(both.title, "plot f, std.sin");
+        assert_eq!(both.x_bounds, [-3.0, 6.0]);
+        assert!(plot(&mut session, "plot f").is_ok());
+        for line in ["plot", "plot f g", "plot f,", "plot f from 1", "plot f from 1 to [1, 2]", "plot f from 2 to 1"] {
+            assert!(plot(&mut session, line).is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn data() {
+        let mut session = Session::new();
+        session.execute("M = [0, 1, 5; 1, 2, 6; 2, 0/0, 7]");
+        session.execute("v = [3, 1, 2, 3, 3]");
+
+        let points = plot(&mut session, "plot data M line").unwrap();
+        assert_eq!((points.x_label.as_str(), points.y_label.as_str()), ("M[:, 1]", ""));
+        let PlotContents::Points {series, joined: true} = &points.contents else {
+            panic!("expected joined points");
+        };
+        assert_eq!(series.iter().map(|series| series.name.as_str()).collect::<Vec<_>>(), ["M[:, 2]", "M[:, 3]"]);
+        assert_eq!(series[0].segments, [vec![(0.0, 1.0), (1.0, 2.0)]]);
+
+        let points = plot(&mut session, "plot data v").unwrap();
+        assert_eq!((points.x_label.as_str(), points.y_label.as_str()), ("index", "v"));
+        assert_eq!(first_segments(&points)[0][4], (5.0, 3.0));
+        assert_eq!(plot(&mut session, "plot data (-v)").unwrap().y_label, "-v");
+
+        let histogram = plot(&mut session, "plot hist v 2").unwrap();
+        assert_eq!(bar_heights(&histogram), [1.0, 4.0]);
+        assert_eq!((histogram.x_label.as_str(), histogram.y_label.as_str()), ("v", "count"));
+        assert_eq!(bar_heights(&plot(&mut session, "plot hist v * 2").unwrap()).len(), 4);
+        assert_eq!(bar_heights(&plot(&mut session, "plot bar v").unwrap()), [3.0, 1.0, 2.0, 3.0, 3.0]);
+
+        assert_eq!(plot(&mut session, "plot hist M").unwrap_err(), "hist needs a vector, M is 3x3 with 3 columns");
+        assert_eq!(plot(&mut session, "plot bar M").unwrap_err(), "bar needs a vector, M is 3x3 with 3 columns");
+        assert!(plot(&mut session, "plot data []").is_err());
+        assert!(plot(&mut session, "plot data [1i, 2]").is_err());
+        assert!(plot(&mut session, "plot hist v 0").is_err());
+    }
+}
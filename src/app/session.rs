@@ -0,0 +1,242 @@
+use crate::parser::{syntax_tree, tokens::{self, Span, SpannedToken, Token}};
+use super::{commands, config::{Config, Panel}, executor::Value, plot::Plot, state::Context, user_scripts::{self, ScriptError}};
+
+// what running a line did, frontends decide how to show it
+#[derive(Debug)]
+pub enum LineResult {
+    Value(Value),
+    Message(String),
+    Error {message: String, span: Option<Span>},
+    StateChange(StateChange),
+    // every line of a loaded script with what it did
+    Script(Vec<(String, LineResult)>),
+    Empty,
+}
+
+// commands that only mean something to the frontend
+#[derive(Debug)]
+pub enum StateChange {
+    ClearHistory,
+    Quit,
+    ShowPopup(PopupName),
+    // None flips it
+    TogglePanel(Panel, Option<bool>),
+    // a theme option and the color as 0xRRGGBB, only the frontend knows what the options are
+    SetThemeColor(String, u32),
+    // the session was started over, with the lines from the init script
+    Reload(Vec<(String, LineResult)>),
+}
+
+#[derive(Debug)]
+pub enum PopupName {
+    Vars,
+    Functions,
+    Help,
+    Plot(Plot),
+}
+
+// everything needed to evaluate lines and run commands, without any ui
+pub struct Session {
+    pub context: Context<'static>,
+    pub config: Config,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session {
+            context: Context::default(),
+            config: Config::default(),
+        }
+    }
+
+    // runs one line like it was typed in
+    pub fn execute(&mut self, line: &str) -> LineResult {
+        let spanned_tokens = tokens::tokenize_spanned(line);
+        let tokens: Vec<Token> = spanned_tokens.iter().map(|spanned| spanned.token.clone()).collect();
+
+        if let Some(result) = commands::handle_commands(self, &tokens) {
+            return result;
+        }
+
+        let result = evaluate(&mut self.context, spanned_tokens);
+        if let LineResult::Value(value) = &result {
+            self.context.set_var("ans".to_string(), value.clone());
+        }
+        result
+    }
+
+    // what a line would give if it ran now, without changing any vars, functions or settings.
+    // commands aren't run at all since most of them only make sense once
+    pub fn preview(&self, line: &str) -> LineResult {
+        let spanned_tokens = tokens::tokenize_spanned(line);
+        let tokens: Vec<Token> = spanned_tokens.iter().map(|spanned| spanned.token.clone()).collect();
+
+        if commands::is_command(&tokens) {
+            return LineResult::Message("command, runs on enter".to_string());
+        }
+
+        let mut context = Context::from_context(&self.context);
+        context.settings = self.context.settings.for_preview();
+        evaluate(&mut context, spanned_tokens)
+    }
+
+    pub fn run_script(&mut self, script_name: &str) -> Result<Vec<(String, LineResult)>, ScriptError> {
+        let script = user_scripts::read_script(script_name)?;
+        Ok(script.lines()
+            .map(|line| (line.to_string(), self.execute(line)))
+            .collect())
+    }
+}
+
+// a line starting with a binary operator continues from the last result
+fn evaluate(context: &mut Context, mut spanned_tokens: Vec<SpannedToken>) -> LineResult {
+    match spanned_tokens.first().map(|spanned| &spanned.token) {
+        Some(Token::Comment(_)) | None => return LineResult::Empty,
+        Some(token) if token.is_binary_op() => spanned_tokens.insert(0, Token::Identifier("ans".to_string()).into()),
+        _ => {},
+    }
+
+    match syntax_tree::generate_syntax_tree(spanned_tokens) {
+        Ok(tree) => match context.execute(tree) {
+            Ok(value) => LineResult::Value(value),
+            Err(e) => LineResult::Error {message: e.to_string(), span: e.span()},
+        },
+        Err(e) => LineResult::Error {message: e.to_string(), span: Some(e.span())},
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(result: LineResult) -> String {
+        match result {
+            LineResult::Value(value) => value.as_string(),
+            result => panic!("expected a value, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn line_results() {
+        let mut session = Session::new();
+        assert!(matches!(session.execute("x = 2 * 3"), LineResult::Value(Value::Number(6.0))));
+        assert!(matches!(session.execute("+ 1"), LineResult::Value(Value::Number(7.0))));
+        assert!(matches!(session.execute("-- nothing"), LineResult::Empty));
+        assert!(matches!(session.execute("config trig deg"), LineResult::Message(_)));
+        assert!(matches!(session.execute("show vars"), LineResult::StateChange(StateChange::ShowPopup(PopupName::Vars))));
+        assert!(matches!(session.execute("panel vars off"), LineResult::StateChange(StateChange::TogglePanel(Panel::Variables, Some(false)))));
+        assert!(matches!(session.execute("config theme text ff8800"), LineResult::StateChange(StateChange::SetThemeColor(_, 0xff8800))));
+        assert!(matches!(session.execute("config theme text orange"), LineResult::Error {..}));
+        match session.execute("x + y") {
+            LineResult::Error {message, span} => {
+                assert_eq!(message, "unknown identifier: y");
+                assert_eq!(span, Some(Span::new(4, 5)));
+            },
+            result => panic!("expected an error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn preview_changes_nothing() {
+        let mut session = Session::new();
+        session.execute("config seed 7");
+        session.execute("x = [1, 2]");
+        assert!(matches!(session.preview("x = 5"), LineResult::Value(Value::Number(5.0))));
+        assert!(matches!(session.preview("x[1] = 3"), LineResult::Value(Value::Matrix(_))));
+        assert!(matches!(session.preview("def f(a) = a"), LineResult::Message(_)));
+        assert!(matches!(session.preview("* 2"), LineResult::Value(Value::Matrix(_))));
+        assert!(matches!(session.preview("x + y"), LineResult::Error {..}));
+        assert!(matches!(session.preview("(x"), LineResult::Error {..}));
+        assert!(matches!(session.preview("std.rand()"), LineResult::Value(_)));
+
+        assert_eq!(session.context.get_var("x").map(Value::as_string), Some("[1, 2]".to_string()));
+        assert!(session.context.get_function("f").is_none());
+        let (LineResult::Value(previewed), LineResult::Value(drawn)) = (session.preview("std.rand()"), session.execute("std.rand()")) else {
+            panic!("rand should give a number");
+        };
+        assert_eq!(previewed.as_string(), drawn.as_string());
+    }
+
+    #[test]
+    fn preview_gives_up_on_heavy_lines() {
+        let mut session = Session::new();
+        session.execute("def deep(n) = if(n <= 0, 0, deep(n - 1))");
+        session.execute("def wide(n) = if(n <= 0, 0, wide(n - 1) + wide(n - 1))");
+        for line in ["deep(120)", "wide(30)", "std.map(wide, [30])"] {
+            match session.preview(line) {
+                LineResult::Error {message, ..} => assert_eq!(message, "too much work to preview, press enter to run it"),
+                _ => panic!("{line} should be too much to preview"),
+            }
+        }
+        assert!(matches!(session.preview("deep(50)"), LineResult::Value(Value::Number(0.0))));
+        assert!(matches!(session.execute("deep(120)"), LineResult::Value(Value::Number(0.0))));
+    }
+
+    #[test]
+    fn call_depth_limit() {
+        let mut session = Session::new();
+        let error = |result| match result {
+            LineResult::Error {message, ..} => message,
+            result => panic!("expected an error, got {result:?}"),
+        };
+        for line in ["config depth 0", "config depth 10001", "config depth 2.5"] {
+            assert_eq!(error(session.execute(line)), "depth must be a whole number from 1 to 10000", "{line}");
+        }
+        assert!(matches!(session.execute("config depth 10000"), LineResult::Message(_)));
+
+        session.execute("config depth 3");
+        session.execute("def up(n) = up(n + 1)");
+        assert_eq!(error(session.execute("up(0)")), "function calls nested more than 3 deep (see config depth): up(0) -> up(1) -> up(2) -> up(3)");
+        // only the outer and inner calls are listed once there are too many
+        session.execute("config depth 10");
+        assert_eq!(
+            error(session.execute("up(0)")),
+            "function calls nested more than 10 deep (see config depth): up(0) -> up(1) -> up(2) -> (5 more calls) -> up(8) -> up(9) -> up(10)",
+        );
+
+        session.execute("def down(n) = if(n <= 0, 0, down(n - 1))");
+        assert_eq!(shown(session.execute("down(9)")), "0");
+        assert!(error(session.execute("down(10)")).starts_with("function calls nested more than 10 deep"));
+    }
+    #[test]
+    fn lambdas() {
+        let mut session = Session::new();
+        session.execute("sq = x -> x^2");
+        session.execute("def apply(f, x) = f(x)");
+        assert_eq!(shown(session.execute("sq(3)")), "9");
+        assert_eq!(shown(session.execute("apply(sq, 4) + apply(y -> 2 * y, 1)")), "18");
+        assert_eq!(shown(session.execute("(a, b) -> a - b")), "(a, b) -> a - b");
+        assert_eq!(shown(session.execute("ans(5, 2)")), "3");
+        assert_eq!(shown(session.execute("fact = n -> if(n <= 1, 1, n * fact(n - 1))")), "n -> if(n <= 1, 1, n * fact(n - 1))");
+        assert_eq!(shown(session.execute("fact(5)")), "120");
+        match session.execute("sq(1, 2)") {
+            LineResult::Error {message, ..} => assert_eq!(message, "sq expected 1 arguments but got 2"),
+            result => panic!("expected an error, got {result:?}"),
+        }
+    }
+    #[test]
+    fn lambdas_keep_their_variables() {
+        let mut session = Session::new();
+        session.execute("def adder(n) = x -> x + n");
+        session.execute("add3 = adder(3)");
+        assert_eq!(shown(session.execute("add3(1)")), "4");
+        session.execute("n = 100");
+        assert_eq!(shown(session.execute("add3(1)")), "4");
+        assert_eq!(shown(session.execute("std.map(adder(10), [1, 2])")), "[11, 12]");
+
+        session.execute("curry = a -> b -> c -> a * b + c");
+        session.execute("double = curry(2)");
+        assert_eq!(shown(session.execute("add = double(5)")), "c -> a * b + c");
+        session.execute("a = 0");
+        assert_eq!(shown(session.execute("add(1)")), "11");
+        session.execute("def compose(f, g) = x -> f(g(x))");
+        session.execute("h = compose(x -> x + 1, x -> 2 * x)");
+        assert_eq!(shown(session.execute("h(3)")), "7");
+    }
+}
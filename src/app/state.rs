@@ -1,7 +1,7 @@
-use std::{io, rc::Rc};
+use std::{fmt, io};
 
-use crate::parser::{self, highlighting::{get_highlight_tokens, HighlightToken, HighlightTokenType}, syntax_tree::{self, Expression}, tokens::Token};
-use super::{builtin_functions, commands, config::Config, executor::{RuntimeError, Value}, user_scripts::{self, ScriptError}};
+use crate::parser::{highlighting::{get_highlight_tokens, HighlightToken, HighlightTokenType}, syntax_tree::Expression, tokens::Span};
+use super::{builtin_functions::{self, BuiltinFn}, environment::Settings, executor::Value, config::Panel, input::InputLine, line_history::{HistorySearch, LineHistory}, plot::Plot, session::{LineResult, PopupName, Session, StateChange}, theme::Theme, user_scripts::{self, ScriptError}};
 
 use crossterm::event::{KeyModifiers, MouseEvent, MouseEventKind};
 use ratatui::{
@@ -16,42 +16,65 @@ pub struct HistoryEntry {
     pub is_output: bool,
 }
 
-#[derive(Clone)]
+// what lambdas are called until they're assigned to a variable
+pub const LAMBDA_NAME: &str = "lambda";
+
+#[derive(Debug, Clone)]
 pub enum FunctionBody {
     User(Expression),
-    Builtin(Rc<dyn Fn(Vec<Value>) -> Result<Value, RuntimeError>>),
+    Builtin(BuiltinFn),
+    Lambda(Expression, Vec<(String, Value)>), // body, the variables it used where it was written
 }
 
+#[derive(Debug, Clone)]
 pub struct FunctionDef {
     pub name: String,
     pub params: Vec<String>,
     pub body: FunctionBody,
 }
 
-pub enum PopupName {
-    Vars,
-    Functions,
-    Help,
+impl FunctionDef {
+    // what follows the name, '(x, y) = x * y' for user functions and just the parameters for builtins.
+    // lambdas are written out in full since they're not known by their name
+    pub fn definition(&self) -> String {
+        match &self.body {
+            FunctionBody::User(body) => format!("({}) = {body}", self.params.join(", ")),
+            FunctionBody::Builtin(_) => format!("({})", self.params.join(", ")),
+            FunctionBody::Lambda(body, _) => match self.params.as_slice() {
+                [param] => format!("{param} -> {body}"),
+                params => format!("({}) -> {body}", params.join(", ")),
+            },
+        }
+    }
+}
+
+impl fmt::Display for FunctionDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.body {
+            FunctionBody::Lambda(..) => write!(f, "{}", self.definition()),
+            _ => write!(f, "{}{}", self.name, self.definition()),
+        }
+    }
 }
 
 pub struct Context<'a> {
-    pub history: Vec<HistoryEntry>,
-    pub modal_scroll: u16,
-    pub history_scroll: u16,
-    pub copy_scroll: usize,
-    pub should_scroll_to_fit: bool,
-    pub current_line: String,
     pub vars: Vec<(String, Value)>,
     pub functions: Vec<FunctionDef>,
-    pub current_popup: Option<PopupName>,
+    pub settings: Settings,
     pub parent_context: Option<&'a Context<'a>>, // for temporary function contexts
+    pub call_depth: usize,
 }
 
 impl Context<'_> {
+    // builtins and ans are found through the parent, so they aren't copied into every call
     pub fn from_context<'a>(context: &'a Context) -> Context<'a> {
-        let mut new_context = Context::default();
-        new_context.parent_context = Some(context);
-        new_context
+        Context {
+            vars: Vec::new(),
+            functions: Vec::new(),
+            settings: context.settings.clone(),
+            parent_context: Some(context),
+            call_depth: context.call_depth,
+        }
     }
 
     pub fn get_var(&self, name: &str) -> Option<&Value> {
@@ -63,7 +86,7 @@ impl Context<'_> {
 
     pub fn get_function(&self, name: &str) -> Option<&FunctionDef> {
         self.functions.iter()
-            .find(|func| &func.name == name)
+            .find(|func| func.name == name)
             .or_else(|| self.parent_context.and_then(|ctx| ctx.get_function(name)))
     }
 
@@ -82,48 +105,16 @@ impl Context<'_> {
             None => self.functions.push(definition),
         }
     }
-
-    // history_msg will highlight input, history_text will output as pure text
-    pub fn push_history_msg(&mut self, msg: &str) {
-        let tokens = get_highlight_tokens(msg);
-        self.history.push(HistoryEntry {tokens, is_output: true});
-    }
-
-    pub fn push_history_text(&mut self, msg: &str) {
-        self.history.push(HistoryEntry {
-            tokens: vec![HighlightToken {text: msg.to_string(), kind: HighlightTokenType::Identifier}],
-            is_output: true,
-        });
-    }
-
-    pub fn scroll_up(&mut self) {
-        match self.current_popup {
-            Some(_) => if self.modal_scroll > 0 { self.modal_scroll -= 1 },
-            None => if self.history_scroll > 0 { self.history_scroll -= 1 },
-        };
-    }
-
-    pub fn scroll_down(&mut self) {
-        match self.current_popup {
-            Some(_) => self.modal_scroll += 1,
-            None => self.history_scroll += 1,
-        };
-    }
 }
 
 impl Default for Context<'_> {
     fn default() -> Self {
         let mut ctx = Context {
-            history: Vec::new(),
-            modal_scroll: 0,
-            history_scroll: 0,
-            copy_scroll: 0,
-            should_scroll_to_fit: true,
-            current_line: String::new(),
             vars: Vec::new(),
             functions: Vec::new(),
-            current_popup: None,
+            settings: Settings::default(),
             parent_context: None,
+            call_depth: 0,
         };
 
         ctx.vars.push(("ans".to_string(), Value::Number(0.0)));
@@ -132,7 +123,7 @@ impl Default for Context<'_> {
             ctx.set_function(FunctionDef {
                 name: name.to_string(),
                 params: params.iter().map(|s| s.to_string()).collect(),
-                body: FunctionBody::Builtin(Rc::new(func)),
+                body: FunctionBody::Builtin(*func),
             });
         }
 
@@ -140,13 +131,46 @@ impl Default for Context<'_> {
     }
 }
 
-pub struct App<'a> {
-    pub context: Context<'a>,
-    pub config: Config,
+// the tui on top of a session, only holds what's needed to draw and edit the input line
+pub struct App {
+    pub session: Session,
+    pub theme: Theme,
+    pub panels: Vec<Panel>,
+    pub history: Vec<HistoryEntry>,
+    pub current_line: InputLine,
+    pub current_popup: Option<PopupName>,
+    pub modal_scroll: u16,
+    pub history_scroll: u16,
+    pub copy_scroll: usize,
+    pub should_scroll_to_fit: bool,
+    // history_scroll when the newest lines are in view, up and down only scroll above this
+    pub history_bottom: u16,
+    pub line_history: LineHistory,
+    pub search: Option<HistorySearch>,
     pub exit: bool,
 }
 
-impl App<'_> {
+// returns whether the key was used, anything else falls through to the normal popup keys
+fn handle_plot_key(plot: &mut Plot, context: &Context, code: KeyCode) -> bool {
+    match code {
+        KeyCode::Char('+' | '=') => plot.zoom(context, true),
+        KeyCode::Char('-') => plot.zoom(context, false),
+        KeyCode::Left => plot.pan(context, -1.0, 0.0),
+        KeyCode::Right => plot.pan(context, 1.0, 0.0),
+        KeyCode::Up => plot.pan(context, 0.0, 1.0),
+        KeyCode::Down => plot.pan(context, 0.0, -1.0),
+        _ => return false,
+    }
+    true
+}
+
+impl Default for App {
+    fn default() -> Self {
+        App::new()
+    }
+}
+
+impl App {
     /// runs the application's main loop until the user quits
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
         while !self.exit {
@@ -177,90 +201,184 @@ impl App<'_> {
     }
 }
 
-impl App<'_> {
-    pub fn new() -> App<'static> {
-        let mut app = App::new_raw();
+impl App {
+    pub fn new() -> App {
+        let mut session = Session::new();
+        let init = session.run_script("init");
+        // init can set 'config history', so the saved lines are only read once it has run
+        let mut app = App::with_session(session);
         // probably change this in the future to print where config is loaded
         // also might add a tip for if no config dir exists
-        if let Err(ScriptError::ScriptNotFound(_)) = app.run_script("init") {
-            app.context.push_history_msg("create init.txt inside your config dir to load a default script");
+        match init {
+            Ok(lines) => app.push_script_lines(lines),
+            Err(ScriptError::ScriptNotFound(_)) => {
+                app.push_history_msg("create init.txt inside your config dir to load a default script");
+            },
+            Err(_) => {},
         }
 
         app
     }
 
-    pub fn new_raw() -> App<'static> {
+    pub fn new_raw() -> App {
+        App::with_session(Session::new())
+    }
+
+    fn with_session(session: Session) -> App {
+        let line_history = LineHistory::load(user_scripts::history_path(), session.config.history_size);
         App {
-            context: Context::default(),
-            config: Config::default(),
+            session,
+            theme: Theme::default(),
+            panels: vec![Panel::Autocomplete, Panel::Variables],
+            history: Vec::new(),
+            current_line: InputLine::default(),
+            current_popup: None,
+            modal_scroll: 0,
+            history_scroll: 0,
+            copy_scroll: 0,
+            should_scroll_to_fit: true,
+            history_bottom: 0,
+            line_history,
+            search: None,
             exit: false,
         }
     }
 
     fn handle_key_down(&mut self, key_event: KeyEvent) {
+        if self.search.is_some() {
+            return self.handle_search_key(key_event);
+        }
+        if let Some(PopupName::Plot(plot)) = &mut self.current_popup {
+            if handle_plot_key(plot, &self.session.context, key_event.code) {
+                return;
+            }
+        }
         if key_event.code != KeyCode::Up {
-            self.context.copy_scroll = 0;
+            self.copy_scroll = 0;
+        }
+        if !matches!(key_event.code, KeyCode::Up | KeyCode::Down) {
+            self.line_history.stop_browsing();
         }
 
+        let is_ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+        let is_scrolled = self.current_popup.is_some() || self.history_scroll < self.history_bottom;
+        let input = &mut self.current_line;
         match key_event.code {
             KeyCode::Enter => self.execute_current_line(),
-            KeyCode::Backspace => {
-                match key_event.modifiers.contains(KeyModifiers::CONTROL) {
-                    true => self.context.current_line.clear(),
-                    false => {self.context.current_line.pop();},
-                };
+            KeyCode::Backspace => match is_ctrl {
+                true => input.clear(),
+                false => input.backspace(),
+            },
+            KeyCode::Delete => input.delete(),
+            KeyCode::Left => match is_ctrl {
+                true => input.word_left(),
+                false => input.left(),
+            },
+            KeyCode::Right => match is_ctrl {
+                true => input.word_right(),
+                false => input.right(),
+            },
+            KeyCode::Home => input.home(),
+            KeyCode::End => input.end(),
+            KeyCode::PageUp => self.scroll_up(),
+            KeyCode::PageDown => self.scroll_down(),
+            KeyCode::Down if is_scrolled => self.scroll_down(),
+            KeyCode::Down => {
+                if let Some(line) = self.line_history.newer() {
+                    self.current_line.set(line.to_string());
+                }
             },
-            KeyCode::Down => self.context.scroll_down(),
             KeyCode::Up => {
-                match key_event.modifiers.contains(KeyModifiers::CONTROL) {
+                match is_ctrl {
                     true => {
-                        let copy_line = self.context.history.iter()
+                        let copy_line = self.history.iter()
                             .rev()
                             .filter(|line| !line.is_output)
-                            .nth(self.context.copy_scroll);
+                            .nth(self.copy_scroll);
                         match copy_line {
                             Some(line) => {
-                                self.context.current_line = line.tokens.iter()
-                                    .map(|token| token.to_string())
-                                    .collect();
+                                let text = line.tokens.iter().map(|token| token.to_string()).collect();
+                                self.current_line.set(text);
                             },
-                            None => self.context.copy_scroll = 0,
+                            None => self.copy_scroll = 0,
                         };
-                        self.context.copy_scroll += 1;
+                        self.copy_scroll += 1;
+                    },
+                    false if is_scrolled => self.scroll_up(),
+                    false => {
+                        if let Some(line) = self.line_history.older(self.current_line.text()) {
+                            self.current_line.set(line.to_string());
+                        }
                     },
-                    false => self.context.scroll_up(),
                 };
             },
+            // completes the word before the cursor
             KeyCode::Tab => {
-                let mut tokens = get_highlight_tokens(&self.context.current_line);
-                if let Some(token) = tokens.pop() {
-                    let matching_var = self.context.vars.iter()
+                let before = input.before_cursor();
+                if let Some(token) = get_highlight_tokens(before).pop() {
+                    let matching_var = self.session.context.vars.iter()
                         .map(|(name, _)| name)
-                        .chain(self.context.functions.iter().map(|def| &def.name))
+                        .chain(self.session.context.functions.iter().map(|def| &def.name))
                         .find(|name| name.contains(&token.text));
                     if let Some(var_name) = matching_var {
-                        tokens.push(HighlightToken::text(var_name.clone()));
-                        self.context.current_line = tokens.iter().map(|token| token.to_string()).collect();
+                        input.replace_before_cursor(token.source_len(), var_name);
                     }
                 }
             },
             KeyCode::Char(char) => {
-                if self.context.current_popup.is_some() {
+                if self.current_popup.is_some() {
                     if char == 'q' {
-                        self.context.current_popup = None;
+                        self.current_popup = None;
+                    }
+                } else if is_ctrl {
+                    match char {
+                        'r' => self.search = Some(HistorySearch::default()),
+                        'w' => input.delete_word(),
+                        'k' => input.delete_to_end(),
+                        _ => {},
                     }
                 } else {
-                    self.context.current_line.push(char);
+                    input.insert(char);
                 }
             },
             _ => {},
         };
     }
 
+    // ctrl+r again looks further back, enter runs the match and other keys keep it to edit
+    fn handle_search_key(&mut self, key_event: KeyEvent) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        let is_ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+        match key_event.code {
+            KeyCode::Char('r') if is_ctrl => search.update(&self.line_history, false),
+            KeyCode::Char('g' | 'c') if is_ctrl => self.search = None,
+            KeyCode::Esc => self.search = None,
+            KeyCode::Char(char) if !is_ctrl => {
+                search.query.push(char);
+                search.update(&self.line_history, true);
+            },
+            KeyCode::Backspace => {
+                search.query.pop();
+                search.update(&self.line_history, true);
+            },
+            code => {
+                if let Some(line) = search.matched(&self.line_history) {
+                    self.current_line.set(line.to_string());
+                }
+                self.search = None;
+                if code == KeyCode::Enter {
+                    self.execute_current_line();
+                }
+            },
+        }
+    }
+
     fn handle_mouse_event(&mut self, mouse_event: MouseEvent) {
         match mouse_event.kind {
-            MouseEventKind::ScrollUp => self.context.scroll_up(),
-            event::MouseEventKind::ScrollDown => self.context.scroll_down(),
+            MouseEventKind::ScrollUp => self.scroll_up(),
+            event::MouseEventKind::ScrollDown => self.scroll_down(),
             _ => {},
         };
     }
@@ -269,13 +387,14 @@ impl App<'_> {
     // and updates state based on them here since a reference to frame is
     // required to make the update
     fn updates_with_frame(&mut self, frame: &mut Frame) {
-        if self.context.should_scroll_to_fit {
-            self.context.should_scroll_to_fit = false;
-            let height = frame.area().height as isize;
-            let lines = self.count_visible_lines(frame.area());
-            let required = lines as isize - height + 1;
-            let target = required.max(self.context.history_scroll as isize);
-            self.context.history_scroll = target.max(0) as u16;
+        let height = frame.area().height as isize;
+        let lines = self.count_visible_lines(frame.area());
+        let required = lines as isize - height + 1;
+        self.history_bottom = required.max(0) as u16;
+        if self.should_scroll_to_fit {
+            self.should_scroll_to_fit = false;
+            let target = required.max(self.history_scroll as isize);
+            self.history_scroll = target.max(0) as u16;
         }
     }
 
@@ -285,7 +404,7 @@ impl App<'_> {
 
         let mut total = 0;
 
-        for entry in &self.context.history {
+        for entry in &self.history {
             total += 1;
             let mut line_len = 0;
             for token in &entry.tokens {
@@ -295,61 +414,118 @@ impl App<'_> {
                     total += 1;
                     line_len = 0;
                 }
-                else if line_len > width && token.kind != HighlightTokenType::Space {
-                    if line_len - token_len > 0 {
-                        total += 1;
-                        line_len = token_len;
-                    }
+                else if line_len > width && token.kind != HighlightTokenType::Space && line_len - token_len > 0 {
+                    total += 1;
+                    line_len = token_len;
                 }
             }
         }
         total
     }
 
-    pub fn run_script(&mut self, script_name: &str) -> Result<(), ScriptError> {
-        let script = user_scripts::read_script(script_name)?;
-        for line in script.lines() {
-            line.clone_into(&mut self.context.current_line);
-            self.execute_current_line();
-        }
-        Ok(())
-    }
-
     fn execute_current_line(&mut self) {
-        let mut tokens = parser::tokens::tokenize(&self.context.current_line);
+        let line = self.current_line.take();
+        self.line_history.push(&line, self.session.config.history_size);
+        let result = self.session.execute(&line);
+        self.push_result(&line, result);
+    }
 
-        let highlight_tokens = get_highlight_tokens(&self.context.current_line);
-        self.context.history.push(HistoryEntry {tokens: highlight_tokens, is_output: false});
+    // the input line and whatever it produced go into the history
+    fn push_result(&mut self, line: &str, result: LineResult) {
+        self.history.push(HistoryEntry {tokens: get_highlight_tokens(line), is_output: false});
+        self.should_scroll_to_fit = true;
+
+        let tokens = match result {
+            LineResult::Value(value) => value.output_tokens(&self.session.context.settings),
+            LineResult::Message(msg) => return self.push_history_text(&msg),
+            LineResult::Error {message, span} => self.error_tokens(line, span, &message),
+            LineResult::Script(lines) => return self.push_script_lines(lines),
+            LineResult::StateChange(change) => return self.apply_state_change(change),
+            LineResult::Empty => return,
+        };
+        self.history.push(HistoryEntry {tokens, is_output: true});
+    }
 
-        self.context.current_line.clear();
-        self.context.should_scroll_to_fit = true;
+    fn push_script_lines(&mut self, lines: Vec<(String, LineResult)>) {
+        for (line, result) in lines {
+            self.push_result(&line, result);
+        }
+    }
 
-        let processed = commands::handle_commands(self, &tokens);
-        if processed {
-            return;
+    fn apply_state_change(&mut self, change: StateChange) {
+        match change {
+            StateChange::ClearHistory => {
+                self.history.clear();
+                self.history_scroll = 0;
+            },
+            StateChange::Quit => self.exit = true,
+            StateChange::ShowPopup(popup) => {
+                self.modal_scroll = 0;
+                self.current_popup = Some(popup);
+            },
+            StateChange::TogglePanel(panel, turn_on) => {
+                let index = self.panels.iter().position(|p| p == &panel);
+                match (turn_on.unwrap_or(index.is_none()), index) {
+                    (true, None) => self.panels.push(panel),
+                    (false, Some(index)) => {self.panels.remove(index);},
+                    _ => {},
+                };
+            },
+            StateChange::SetThemeColor(option, color) => match self.theme.set_color(&option, Color::from_u32(color)) {
+                Ok(msg) => self.push_history_text(&msg),
+                Err(msg) => {
+                    let tokens = self.error_tokens("", None, &msg);
+                    self.history.push(HistoryEntry {tokens, is_output: true});
+                },
+            },
+            StateChange::Reload(init_lines) => {
+                self.history.clear();
+                self.history_scroll = 0;
+                self.push_script_lines(init_lines);
+            },
         }
+    }
 
-        if tokens.get(0).is_some_and(|token| token.is_binary_op()) {
-            tokens.insert(0, Token::Identifier("ans".to_string()));
+    // carets under the part of the input line that caused the error, then the message
+    fn error_tokens(&self, line: &str, span: Option<Span>, msg: &str) -> Vec<HighlightToken> {
+        let mut tokens = Vec::new();
+        let underline = span.and_then(|span| Some((line.get(..span.start)?, line.get(span.start..span.end)?)));
+        if let Some((before, underlined)) = underline {
+            let column_width = |text: &str| text.chars()
+                .map(|ch| if ch == '\t' { self.session.config.tab_width } else { 1 })
+                .sum::<usize>();
+            tokens.push(HighlightToken {text: " ".repeat(column_width(before)), kind: HighlightTokenType::Space});
+            tokens.push(HighlightToken {text: "^".repeat(column_width(underlined).max(1)), kind: HighlightTokenType::Error});
+            tokens.push(HighlightToken::newline());
         }
+        tokens.extend(get_highlight_tokens(msg));
+        tokens
+    }
 
-        match tokens.get(0) {
-            Some(Token::Comment(_)) | None => return,
-            _ => {},
-        };
+    // history_msg will highlight input, history_text will output as pure text
+    pub fn push_history_msg(&mut self, msg: &str) {
+        let tokens = get_highlight_tokens(msg);
+        self.history.push(HistoryEntry {tokens, is_output: true});
+    }
 
-        let execution_response = match syntax_tree::generate_syntax_tree(tokens) {
-            Ok(tree) => match self.context.execute(tree) {
-                Ok(value) => {
-                    let output = value.output_tokens();
-                    self.context.set_var("ans".to_string(), value);
-                    output
-                },
-                Err(e) => get_highlight_tokens(&e.to_string()),
-            },
-            Err(e) => get_highlight_tokens(&e.to_string()),
+    pub fn push_history_text(&mut self, msg: &str) {
+        self.history.push(HistoryEntry {
+            tokens: vec![HighlightToken {text: msg.to_string(), kind: HighlightTokenType::Identifier}],
+            is_output: true,
+        });
+    }
+
+    pub fn scroll_up(&mut self) {
+        match self.current_popup {
+            Some(_) => if self.modal_scroll > 0 { self.modal_scroll -= 1 },
+            None => if self.history_scroll > 0 { self.history_scroll -= 1 },
         };
+    }
 
-        self.context.history.push(HistoryEntry {tokens: execution_response, is_output: true});
+    pub fn scroll_down(&mut self) {
+        match self.current_popup {
+            Some(_) => self.modal_scroll += 1,
+            None => self.history_scroll += 1,
+        };
     }
 }
@@ -0,0 +1,57 @@
+use ratatui::style::Color;
+
+// colors only the tui uses, set with 'config theme <option> <hex color>'
+#[derive(Debug)]
+pub struct Theme {
+    pub number: Color,
+    pub identifier: Color,
+    pub unknown_identifier: Color,
+    pub command: Color,
+    pub operator: Color,
+    pub error: Color,
+    pub input_line_bg: Color,
+    pub result_line_bg: Color,
+    pub current_line_bg: Color,
+    pub divider: Color,
+    pub text: Color,
+    pub cursor: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            number: Color::Rgb(230, 134, 57),
+            identifier: Color::Rgb(240, 240, 240),
+            unknown_identifier: Color::Rgb(180, 180, 180),
+            command: Color::Rgb(255, 87, 87),
+            operator: Color::Rgb(232, 208, 151),
+            error: Color::Rgb(255, 87, 87),
+            input_line_bg: Color::Rgb(60, 60, 60),
+            result_line_bg: Color::Rgb(40, 40, 40),
+            current_line_bg: Color::Rgb(60, 60, 60),
+            divider: Color::White,
+            text: Color::Rgb(240, 240, 240),
+            cursor: Color::White,
+        }
+    }
+}
+
+impl Theme {
+    pub fn set_color(&mut self, option: &str, color: Color) -> Result<String, String> {
+        match option {
+            "number" => self.number = color,
+            "identifier" => self.identifier = color,
+            "unknownIdentifier" => self.unknown_identifier = color,
+            "command" => self.command = color,
+            "operator" => self.operator = color,
+            "error" => self.error = color,
+            "inputBg" => self.input_line_bg = color,
+            "resultBg" => self.result_line_bg = color,
+            "currentBg" => self.current_line_bg = color,
+            "text" => self.text = color,
+            "cursor" => self.cursor = color,
+            _ => return Err("unknown theme option".to_string()),
+        };
+        Ok("theme option updated".to_string())
+    }
+}
@@ -3,20 +3,25 @@ use std::iter;
 use layout::Flex;
 use ratatui::{
     prelude::*,
-    widgets::{Block, Clear, Paragraph},
+    widgets::{Axis, Block, Chart, Clear, Dataset, GraphType, Paragraph},
 };
 use symbols::border;
 
 use crate::parser::highlighting::{get_highlight_tokens, HighlightToken, HighlightTokenType};
 
-use super::{config::Panel, state::{App, PopupName}};
+use super::{config::Panel, executor::Value, line_history::HistorySearch, plot::{Plot, PlotContents, Series}, session::{LineResult, PopupName}, state::App};
 
-impl App<'_> {
+// formatting every element of a huge matrix on each keystroke would make typing lag
+const PREVIEW_MAX_ELEMENTS: usize = 2000;
+
+const PLOT_COLORS: [Color; 6] = [Color::Cyan, Color::Yellow, Color::Magenta, Color::Green, Color::Red, Color::Blue];
+
+impl App {
     fn map_token_colors(&self, token: &HighlightToken) -> Span<'_> {
-        let theme = &self.config.theme;
+        let theme = &self.theme;
         match token.kind {
             HighlightTokenType::Identifier => {
-                match self.context.get_var(&token.text).is_some() || self.context.get_function(&token.text).is_some() {
+                match self.session.context.get_var(&token.text).is_some() || self.session.context.get_function(&token.text).is_some() {
                     true => token.text.clone().fg(theme.identifier),
                     false => token.text.clone().fg(theme.unknown_identifier),
                 }
@@ -24,14 +29,16 @@ impl App<'_> {
             HighlightTokenType::Number => token.text.clone().fg(theme.number),
             HighlightTokenType::Operator => token.text.clone().fg(theme.operator),
             HighlightTokenType::Command => token.text.clone().fg(theme.command),
+            HighlightTokenType::Error => token.text.clone().fg(theme.error),
             HighlightTokenType::Space => token.text.clone().fg(Color::Black),
-            HighlightTokenType::Tab => " ".repeat(self.config.tab_width).fg(Color::Black),
-            HighlightTokenType::Newline => panic!("newlines are delimiters"),
+            HighlightTokenType::Tab => " ".repeat(self.session.config.tab_width).fg(Color::Black),
+            // history lines are split on newlines before this, but a pasted input line can still have one
+            HighlightTokenType::Newline => " ".fg(Color::Black),
         }
     }
 
     pub fn get_horizontal_layout(&self, area: Rect) -> (Rect, Rect) {
-        let [text_area, panel_area] = match self.config.panels.len() {
+        let [text_area, panel_area] = match self.panels.len() {
             0 => Layout::horizontal([
                 Constraint::Percentage(100),
                 Constraint::Percentage(0),
@@ -47,14 +54,14 @@ impl App<'_> {
     fn render_text_area(&self, area: Rect, buf: &mut Buffer) {
         let mut lines = Vec::new();
 
-        for entry in &self.context.history {
+        for entry in &self.history {
             lines.push(Vec::new());
 
             let mut line_length = 0;
 
             let bg_color = match entry.is_output {
-                true => self.config.theme.result_line_bg,
-                false => self.config.theme.input_line_bg,
+                true => self.theme.result_line_bg,
+                false => self.theme.input_line_bg,
             };
 
             for token in &entry.tokens {
@@ -65,11 +72,9 @@ impl App<'_> {
                     line_length = 0;
                     continue;
                 }
-                if line_length > area.width && token.kind != HighlightTokenType::Space {
-                    if line_length - token_len > 0 {
-                        lines.push(Vec::new());
-                        line_length = token_len;
-                    }
+                if line_length > area.width && token.kind != HighlightTokenType::Space && line_length - token_len > 0 {
+                    lines.push(Vec::new());
+                    line_length = token_len;
                 }
                 let span = self.map_token_colors(token).bg(bg_color);
                 lines.last_mut().unwrap().push(span);
@@ -78,24 +83,81 @@ impl App<'_> {
 
         let mut lines: Vec<_> = lines.into_iter().map(Line::from).collect();
 
-        let mut current_line = get_highlight_tokens(&self.context.current_line)
-            .iter()
-            .map(|token| self.map_token_colors(token))
-            .collect::<Vec<_>>();
+        let current_line = self.current_line_spans();
+        lines.push(Line::from(current_line).bg(self.theme.current_line_bg));
 
-        current_line.push(Span::from(&self.config.cursor).fg(self.config.theme.cursor));
+        Paragraph::new(lines).scroll((self.history_scroll, 0)).render(area, buf);
+    }
+
+    // the cursor glyph goes between characters, a token it lands in keeps its color on both sides
+    fn current_line_spans(&self) -> Vec<Span<'_>> {
+        let cursor_span = Span::from(&self.session.config.cursor).fg(self.theme.cursor);
+        if let Some(search) = &self.search {
+            return self.search_spans(search, cursor_span);
+        }
+        let cursor = self.current_line.cursor();
+        let mut spans = Vec::new();
+        let mut offset = 0;
+        let mut cursor_span = Some(cursor_span);
+
+        for token in get_highlight_tokens(self.current_line.text()) {
+            let token_len = token.source_len();
+            let span = self.map_token_colors(&token);
+            match cursor_span.take_if(|_| cursor < offset + token_len) {
+                Some(cursor_span) if cursor > offset => {
+                    let (before, after) = token.text.split_at(cursor - offset);
+                    spans.push(Span::styled(before.to_string(), span.style));
+                    spans.push(cursor_span);
+                    spans.push(Span::styled(after.to_string(), span.style));
+                },
+                Some(cursor_span) => {
+                    spans.push(cursor_span);
+                    spans.push(span);
+                },
+                None => spans.push(span),
+            }
+            offset += token_len;
+        }
+        spans.extend(cursor_span);
+        spans
+    }
 
-        lines.push(Line::from(current_line).bg(self.config.theme.current_line_bg));
+    // the query being typed and the line it matched so far
+    fn search_spans<'a>(&'a self, search: &'a HistorySearch, cursor_span: Span<'a>) -> Vec<Span<'a>> {
+        let theme = &self.theme;
+        let mut spans = vec![
+            "search '".fg(theme.command),
+            search.query.as_str().fg(theme.text),
+            cursor_span,
+            "': ".fg(theme.command),
+        ];
+        match search.matched(&self.line_history) {
+            Some(line) => spans.extend(get_highlight_tokens(line).iter().map(|token| self.map_token_colors(token))),
+            None if !search.query.is_empty() => spans.push("no match".fg(theme.unknown_identifier)),
+            None => {},
+        }
+        spans
+    }
 
-        Paragraph::new(lines).scroll((self.context.history_scroll, 0)).render(area, buf);
+    // small indicator in the top right of the text area for settings that change results
+    fn render_status(&self, area: Rect, buf: &mut Buffer) {
+        let settings = &self.session.context.settings;
+        let mut status = settings.angle_mode_name().to_uppercase();
+        if let Some(digits) = settings.precision {
+            status.push_str(&format!(" {digits}sig"));
+        }
+        let status = format!(" {status} ");
+        let width = (status.len() as u16).min(area.width);
+        let status_area = Rect::new(area.right() - width, area.y, width, 1.min(area.height));
+        Paragraph::new(status.fg(self.theme.command).bg(self.theme.current_line_bg)).render(status_area, buf);
     }
 
     fn render_panels(&self, area: Rect, buf: &mut Buffer) {
-        let panel_count = self.config.panels.len();
-        let constraints = iter::repeat(Constraint::Percentage((100 / panel_count) as u16)).take(panel_count);
+        let panel_count = self.panels.len();
+        let constraints = iter::repeat_n(Constraint::Percentage((100 / panel_count) as u16), panel_count);
         let panel_layout = Layout::vertical(constraints).split(area);
 
-        let panels: Vec<_> = self.config.panels.iter()
+        let panels: Vec<_> = self.panels.iter()
             .map(|panel| match panel {
                 Panel::Variables => self.get_vars_panel(),
                 Panel::Autocomplete => self.get_autocomplete_panel(),
@@ -110,7 +172,7 @@ impl App<'_> {
 
     fn get_vars_panel(&self) -> Paragraph<'_> {
         let block = Block::bordered().title(Line::from("Vars".bold())).border_set(border::THICK);
-        let vars: Vec<_> = self.context.vars.iter()
+        let vars: Vec<_> = self.session.context.vars.iter()
             .map(|(name, value)| Line::from(format!("{} = {}", name, value.short_string())))
             .rev()
             .collect();
@@ -118,22 +180,25 @@ impl App<'_> {
     }
 
     fn get_autocomplete_panel(&self) -> Paragraph<'_> {
-        let lines = match get_highlight_tokens(&self.context.current_line).last() {
+        let lines = match get_highlight_tokens(self.current_line.before_cursor()).last() {
             Some(token) => match token.kind {
-                HighlightTokenType::Identifier => self.context.vars.iter()
-                    .map(|(name, value)| (name, Some(value)))
-                    .chain(self.context.functions.iter().map(|function_def| (&function_def.name, None)))
-                    .filter(|(name, _)| name.contains(&token.text))
-                    .map(|(name, value)| {
+                HighlightTokenType::Identifier => self.session.context.vars.iter()
+                    .map(|(name, value)| (name, Some(value), None))
+                    .chain(self.session.context.functions.iter().map(|function_def| (&function_def.name, None, Some(function_def))))
+                    .filter(|(name, _, _)| name.contains(&token.text))
+                    .map(|(name, value, function_def)| {
                         let (left_side, right_side) = name.split_once(&token.text).unwrap();
                         let mut line_tokens = vec![
-                            left_side.fg(self.config.theme.unknown_identifier),
-                            token.text.clone().fg(self.config.theme.identifier),
-                            right_side.fg(self.config.theme.unknown_identifier),
+                            left_side.fg(self.theme.unknown_identifier),
+                            token.text.clone().fg(self.theme.identifier),
+                            right_side.fg(self.theme.unknown_identifier),
                         ];
                         if let Some(value) = value {
-                            line_tokens.push(" = ".fg(self.config.theme.operator));
-                            line_tokens.push(value.short_string().fg(self.config.theme.number));
+                            line_tokens.push(" = ".fg(self.theme.operator));
+                            line_tokens.push(value.short_string().fg(self.theme.number));
+                        }
+                        if let Some(function_def) = function_def {
+                            line_tokens.extend(get_highlight_tokens(&function_def.definition()).iter().map(|token| self.map_token_colors(token)));
                         }
                         Line::from(line_tokens)
                     })
@@ -146,28 +211,57 @@ impl App<'_> {
         Paragraph::new(Text::from(lines)).block(block)
     }
 
+    // what the line being typed would give, shown as it's typed. big results get their shape in
+    // the title since the values themselves won't all fit
     fn get_preview_panel(&self) -> Paragraph<'_> {
-        // TODO
-        let block = Block::bordered().title(Line::from("Preview".bold())).border_set(border::THICK);
-        Paragraph::new(Text::from("Hello")).block(block)
+        let theme = &self.theme;
+        let mut title = vec!["Preview".bold()];
+        let lines = match self.session.preview(self.current_line.text()) {
+            LineResult::Value(value) => {
+                if let Value::Matrix(_) | Value::ComplexMatrix(_) = value {
+                    title.push(format!(" {}", value.short_string()).fg(theme.number));
+                }
+                let (rows, cols) = value.dims();
+                if rows * cols > PREVIEW_MAX_ELEMENTS {
+                    return self.preview_block(title, vec![Line::from("too big to show".fg(theme.unknown_identifier))]);
+                }
+                let mut lines = vec![Vec::new()];
+                for token in value.output_tokens(&self.session.context.settings) {
+                    match token.kind {
+                        HighlightTokenType::Newline => lines.push(Vec::new()),
+                        _ => lines.last_mut().unwrap().push(self.map_token_colors(&token)),
+                    }
+                }
+                lines.into_iter().map(Line::from).collect()
+            },
+            LineResult::Error {message, ..} => vec![Line::from(message.fg(theme.error))],
+            LineResult::Message(message) => vec![Line::from(message.fg(theme.command))],
+            _ => Vec::new(),
+        };
+        self.preview_block(title, lines)
+    }
+
+    fn preview_block<'a>(&self, title: Vec<Span<'a>>, lines: Vec<Line<'a>>) -> Paragraph<'a> {
+        let block = Block::bordered().title(Line::from(title)).border_set(border::THICK);
+        Paragraph::new(Text::from(lines)).block(block)
     }
 
     fn get_vars_popup(&self) -> Paragraph<'_> {
-        let lines: Vec<_> = self.context.vars.iter()
+        let lines: Vec<_> = self.session.context.vars.iter()
             .map(|(name, value)| Line::from(format!("{} = {:?}", name, value)))
             .rev()
             .collect();
         let block = Block::bordered().title("Vars");
-        Paragraph::new(lines).scroll((self.context.modal_scroll, 0)).block(block)
+        Paragraph::new(lines).scroll((self.modal_scroll, 0)).block(block)
     }
 
     fn get_functions_popup(&self) -> Paragraph<'_> {
-        let lines: Vec<_> = self.context.functions.iter()
-            .map(|function_def| Line::from(format!("{}({})", function_def.name, function_def.params.join(", "))))
+        let lines: Vec<_> = self.session.context.functions.iter()
+            .map(|function_def| Line::from(function_def.to_string()))
             .rev()
             .collect();
         let block = Block::bordered().title("Functions");
-        Paragraph::new(lines).scroll((self.context.modal_scroll, 0)).block(block)
+        Paragraph::new(lines).scroll((self.modal_scroll, 0)).block(block)
     }
 
     fn get_help_popup(&self) -> Paragraph<'_> {
@@ -180,8 +274,22 @@ impl App<'_> {
             "",
             "      Vectors are just matrices with a single column",
             "",
+            "      Complex numbers use an i or j suffix, ex: 3+4i",
+            "",
+            "      Solve Ax = b with A \\ b (least squares if A is not square)",
+            "      Element-wise operators: .* ./ .^, numbers and vectors broadcast",
+            "      Ranges and indexing: 0:0.5:2, A[2, :], A[1, end] = 5",
+            "      Comparisons and logic: < <= > >= == != and or not, if(cond, a, b)",
+            "",
             "Controls:",
-            "    - arrowkeys: scroll view up and down",
+            "    - up and down: previous and next input lines, or scroll once scrolled up",
+            "    - page up and page down: scroll view up and down",
+            "    - ctrl + r: search input history, ctrl + r again for older matches",
+            "    - left and right, home and end: move the cursor",
+            "    - ctrl + left and right: move by words",
+            "    - ctrl + w: delete the word before the cursor",
+            "    - ctrl + k: delete everything after the cursor",
+            "    - delete: delete the character after the cursor",
             "    - ctrl + up: copy line",
             "    - ctrl + backspace: delete line",
             "    - q: close active modal",
@@ -197,6 +305,10 @@ impl App<'_> {
             "    - config <option>: used to edit config values",
             "    - show <vars/functions/help>: used to show modals like this",
             "    - panel <vars/autocomplete>: toggles a panel",
+            "    - save <name>: saves variables and functions to the config folder",
+            "    - restore <name>: brings back a saved workspace",
+            "    - plot <f, g> from <start> to <end>: plots functions, +/- to zoom and arrows to move",
+            "    - plot data <M> <optional: line>, plot hist <v> <optional: bins>, plot bar <v>: plots values",
             "",
             "Config Options:",
             "      Really there isn't a ton to configure and I never bothered to",
@@ -207,12 +319,20 @@ impl App<'_> {
             "    - script",
             "       - show (shows the current config directory if it exists)",
             "       - create (creates a config directory at the default location for your system)",
+            "    - trig <deg/rad> (angle units used by trig functions, toggles if left out)",
+            "    - complex <rect/polar> (how complex numbers are displayed)",
+            "    - precision <digits> (significant digits shown, leave out for full precision)",
+            "    - seed <number> (seeds std.rand)",
+            "    - depth <calls> (how deep function calls can nest)",
+            "    - history <lines> (how many typed lines are kept between runs)",
+            "    - autosave <on/off> (saves a workspace called autosave on quit)",
             "    - theme (change your colors for current session)",
             "       - number <color>",
             "       - identifier <color>",
             "       - unknownIdentifier <color>",
             "       - command <color>",
             "       - operator <color>",
+            "       - error <color>",
             "       - inputBg <color>",
             "       - resultBg <color>",
             "       - currentBg <color>",
@@ -222,7 +342,7 @@ impl App<'_> {
             .map(|line| Line::from(line.to_string()))
             .collect();
         let block = Block::bordered().title("Help");
-        Paragraph::new(lines).scroll((self.context.modal_scroll, 0)).block(block)
+        Paragraph::new(lines).scroll((self.modal_scroll, 0)).block(block)
     }
 
     fn render_popup(&self, popup: &PopupName, area: Rect, buf: &mut Buffer) {
@@ -239,23 +359,71 @@ impl App<'_> {
             PopupName::Vars => self.get_vars_popup(),
             PopupName::Functions => self.get_functions_popup(),
             PopupName::Help => self.get_help_popup(),
+            PopupName::Plot(plot) => return self.render_plot(plot, popup_area, buf),
         };
 
         popup_context.render(popup_area, buf);
     }
+
+    fn render_plot(&self, plot: &Plot, area: Rect, buf: &mut Buffer) {
+        let bar_fill = plot.bar_fill(area.width);
+        let datasets = match &plot.contents {
+            PlotContents::Functions(series) => series_datasets(series, GraphType::Line, symbols::Marker::Braille),
+            PlotContents::Points {series, joined: true} => series_datasets(series, GraphType::Line, symbols::Marker::Braille),
+            PlotContents::Points {series, joined: false} => series_datasets(series, GraphType::Scatter, symbols::Marker::Dot),
+            PlotContents::Bars(_) => bar_fill.iter()
+                .map(|points| Dataset::default()
+                    .marker(symbols::Marker::HalfBlock)
+                    .graph_type(GraphType::Bar)
+                    .style(PLOT_COLORS[0])
+                    .data(points))
+                .collect(),
+        };
+        let axis = |title: &str, bounds, labels: Vec<String>| Axis::default()
+            .title(title.to_string())
+            .style(Style::default().fg(self.theme.unknown_identifier))
+            .bounds(bounds)
+            .labels(labels);
+        let block = Block::bordered().title(plot.title.as_str()).title_bottom(" +/- zoom, arrows move, q close ");
+        Chart::new(datasets)
+            .block(block)
+            .x_axis(axis(&plot.x_label, plot.x_bounds, plot.x_labels()))
+            .y_axis(axis(&plot.y_label, plot.y_bounds, plot.y_labels()))
+            .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)))
+            .render(area, buf);
+    }
+}
+
+// each series gets an empty dataset for its legend entry, then one per unbroken stretch so gaps aren't joined up
+fn series_datasets(series: &[Series], graph_type: GraphType, marker: symbols::Marker) -> Vec<Dataset<'_>> {
+    series.iter()
+        .zip(PLOT_COLORS.iter().cycle())
+        .flat_map(|(series, color)| {
+            let legend = Dataset::default().name(series.name.as_str()).style(*color);
+            let segments = series.segments.iter().map(move |segment| {
+                Dataset::default()
+                    .marker(marker)
+                    .graph_type(graph_type)
+                    .style(*color)
+                    .data(segment)
+            });
+            iter::once(legend).chain(segments)
+        })
+        .collect()
 }
 
-impl Widget for &App<'_> {
+impl Widget for &App {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let (text_area, panel_area) = self.get_horizontal_layout(area);
 
         self.render_text_area(text_area, buf);
+        self.render_status(text_area, buf);
 
-        if self.config.panels.len() > 0 {
+        if !self.panels.is_empty() {
             self.render_panels(panel_area, buf);
         }
 
-        if let Some(popup) = &self.context.current_popup {
+        if let Some(popup) = &self.current_popup {
             self.render_popup(popup, area, buf);
         }
     }
@@ -11,6 +11,9 @@ pub enum ScriptError {
     NoConfigPath,
     ScriptNotFound(String),
     OsDoesNotSupportConfigDir,
+    WriteFailed(String),
+    // a workspace line that isn't a variable or def, with its line number
+    NotADefinition(usize, String),
 }
 
 pub fn read_script(name: &str) -> Result<String, ScriptError> {
@@ -31,10 +34,20 @@ pub fn read_script(name: &str) -> Result<String, ScriptError> {
     Err(ScriptError::ScriptNotFound(name.to_string()))
 }
 
+// typed lines are saved here, only when a config folder exists
+pub fn history_path() -> Option<PathBuf> {
+    get_config_dir().ok().map(|config_path| config_path.join(".history"))
+}
+
+// saved workspaces get their own folder, 'load workspaces.<name>' also works but doesn't report anything
+pub fn workspace_path(name: &str) -> Result<PathBuf, ScriptError> {
+    Ok(get_config_dir()?.join("workspaces").join(name.replace(".", "/") + ".txt"))
+}
+
 // this version will return a path if supported by os
 pub fn guessed_config_path() -> Result<PathBuf, ScriptError> {
     dirs::config_dir()
-        .map(|path| path.join("lcalc".to_string()))
+        .map(|path| path.join("lcalc"))
         .ok_or(ScriptError::OsDoesNotSupportConfigDir)
 }
 
@@ -61,6 +74,10 @@ impl fmt::Display for ScriptError {
             ScriptError::NoConfigPath => write!(f, "no config path found"),
             ScriptError::ScriptNotFound(name) => write!(f, "script '{}' not found", name),
             ScriptError::OsDoesNotSupportConfigDir => write!(f, "external config only supported on windows/mac/linux"),
+            ScriptError::WriteFailed(e) => write!(f, "couldn't save: {e}"),
+            ScriptError::NotADefinition(line_number, line) => {
+                write!(f, "line {line_number} only restores variables and functions, got '{line}'")
+            },
         }
     }
 }
@@ -0,0 +1,256 @@
+use std::fs;
+
+use crate::parser::{commands, syntax_tree::{generate_syntax_tree, Expression, ExpressionKind}, tokens::{tokenize, tokenize_spanned, Token}};
+use super::{complex::Complex, executor::Value, session::{LineResult, Session}, state::{Context, FunctionBody, FunctionDef}, user_scripts::{self, ScriptError}};
+
+pub const AUTOSAVE_NAME: &str = "autosave";
+
+// workspaces are saved as lines that could be typed in, so they can be read and edited by hand
+pub fn save(context: &Context, name: &str) -> Result<String, ScriptError> {
+    let mut lines = vec!["-- saved by lcalc, bring it back with 'restore <name>'".to_string()];
+    // functions go first so variables holding them can refer to them by name
+    let functions: Vec<_> = context.functions.iter()
+        .filter(|function_def| matches!(function_def.body, FunctionBody::User(_)))
+        .collect();
+    for function_def in &functions {
+        lines.push(format!("def {function_def}"));
+    }
+    let mut vars = 0;
+    let mut skipped = Vec::new();
+    for (var_name, value) in context.vars.iter().filter(|(var_name, _)| var_name != "ans") {
+        match value_source(value) {
+            Some(source) => {
+                lines.push(format!("{var_name} = {source}"));
+                vars += 1;
+            },
+            None => skipped.push(var_name.as_str()),
+        }
+    }
+
+    let path = user_scripts::workspace_path(name)?;
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, lines.join("\n") + "\n"))
+        .map_err(|e| ScriptError::WriteFailed(e.to_string()))?;
+    let mut msg = format!(
+        "saved {} and {} to {}",
+        count(vars, "variable"),
+        count(functions.len(), "function"),
+        path.to_string_lossy(),
+    );
+    if !skipped.is_empty() {
+        msg.push_str(&format!(", left out {} since they call lambdas they captured", skipped.join(", ")));
+    }
+    Ok(msg)
+}
+
+// runs each saved line and lists anything that already had a value or definition
+pub fn restore(session: &mut Session, name: &str) -> Result<String, ScriptError> {
+    let path = user_scripts::workspace_path(name)?;
+    let text = fs::read_to_string(path).map_err(|_| ScriptError::ScriptNotFound(name.to_string()))?;
+    restore_lines(session, &text)
+}
+
+// every line is checked before any run, so a file with commands in it changes nothing
+fn restore_lines(session: &mut Session, text: &str) -> Result<String, ScriptError> {
+    let mut definitions = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let tokens = tokenize(line);
+        let defined = match tokens.as_slice() {
+            [] | [Token::Comment(_)] => continue,
+            [Token::Identifier(def), Token::Identifier(fname), Token::OpenParen, ..] if def == "def" => (fname.clone(), true),
+            [Token::Identifier(var_name), Token::Assign, ..] if !commands::is_command(var_name) => (var_name.clone(), false),
+            _ => return Err(ScriptError::NotADefinition(i + 1, line.to_string())),
+        };
+        definitions.push((line, defined));
+    }
+
+    let ans = session.context.get_var("ans").cloned();
+    let (mut vars, mut functions, mut failed) = (0, 0, 0);
+    let mut overwritten = Vec::new();
+    for (line, (name, is_function)) in definitions {
+        let existed = match is_function {
+            true => session.context.get_function(&name).is_some(),
+            false => session.context.get_var(&name).is_some(),
+        };
+        if let LineResult::Error {..} = session.execute(line) {
+            failed += 1;
+            continue;
+        }
+        match is_function {
+            true => functions += 1,
+            false => vars += 1,
+        };
+        if existed {
+            overwritten.push(name);
+        }
+    }
+    if let Some(ans) = ans {
+        session.context.set_var("ans".to_string(), ans);
+    }
+
+    let mut msg = format!("restored {} and {}", count(vars, "variable"), count(functions, "function"));
+    if !overwritten.is_empty() {
+        msg.push_str(&format!(", overwrote {}", overwritten.join(", ")));
+    }
+    if failed > 0 {
+        msg.push_str(&format!(", {} failed", count(failed, "line")));
+    }
+    Ok(msg)
+}
+
+fn count(n: usize, word: &str) -> String {
+    match n {
+        1 => format!("1 {word}"),
+        _ => format!("{n} {word}s"),
+    }
+}
+
+// None for a lambda that can't be written out, see lambda_source
+fn value_source(value: &Value) -> Option<String> {
+    let matrix = |rows: Vec<Vec<String>>| {
+        let rows: Vec<_> = rows.iter().map(|row| row.join(", ")).collect();
+        format!("[{}]", rows.join("; "))
+    };
+    let source = match value {
+        Value::Number(num) => number_source(*num),
+        Value::Complex(num) => complex_source(*num),
+        Value::Matrix(rows) => matrix(rows.iter().map(|row| row.iter().map(|num| number_source(*num)).collect()).collect()),
+        Value::ComplexMatrix(rows) => matrix(rows.iter().map(|row| row.iter().map(|num| complex_source(*num)).collect()).collect()),
+        Value::Function(function_def) => match &function_def.body {
+            FunctionBody::Lambda(body, captured) => lambda_source(function_def, body, captured)?,
+            _ => function_def.name.clone(),
+        },
+    };
+    Some(source)
+}
+
+// the values a lambda captured are written into its body, since the names could mean something
+// else by the time it's restored. A captured lambda that gets called has no name to call it by
+fn lambda_source(function_def: &FunctionDef, body: &Expression, captured: &[(String, Value)]) -> Option<String> {
+    let body = inline_captures(body, captured, &function_def.params)?;
+    Some(ExpressionKind::Lambda(function_def.params.clone(), Box::new(body)).to_string())
+}
+
+fn inline_captures(expression: &Expression, captured: &[(String, Value)], bound: &[String]) -> Option<Expression> {
+    let captured_value = |name: &String| match bound.contains(name) {
+        true => None,
+        false => captured.iter().find(|(captured_name, _)| captured_name == name).map(|(_, value)| value),
+    };
+    let all = |expressions: &[Expression]| expressions.iter().map(|e| inline_captures(e, captured, bound)).collect::<Option<Vec<_>>>();
+    let boxed = |e: &Expression| inline_captures(e, captured, bound).map(Box::new);
+    let kind = match &expression.kind {
+        ExpressionKind::Identifier(name) => match captured_value(name) {
+            Some(value) => ExpressionKind::Group(Box::new(generate_syntax_tree(tokenize_spanned(&value_source(value)?)).ok()?)),
+            None => return Some(expression.clone()),
+        },
+        ExpressionKind::FuncCall(name, args) => {
+            let name = match captured_value(name) {
+                Some(Value::Function(function_def)) if !matches!(function_def.body, FunctionBody::Lambda(..)) => function_def.name.clone(),
+                Some(_) => return None,
+                None => name.clone(),
+            };
+            ExpressionKind::FuncCall(name, all(args)?)
+        },
+        ExpressionKind::Group(e) => ExpressionKind::Group(boxed(e)?),
+        ExpressionKind::Matrix(rows) => ExpressionKind::Matrix(rows.iter().map(|row| all(row)).collect::<Option<_>>()?),
+        ExpressionKind::Binary(lhs, op, rhs) => ExpressionKind::Binary(boxed(lhs)?, op.clone(), boxed(rhs)?),
+        ExpressionKind::Unary(op, e) => ExpressionKind::Unary(op.clone(), boxed(e)?),
+        ExpressionKind::Range(start, step, end) => {
+            let step = match step {
+                Some(step) => Some(boxed(step)?),
+                None => None,
+            };
+            ExpressionKind::Range(boxed(start)?, step, boxed(end)?)
+        },
+        ExpressionKind::Index(e, args) => ExpressionKind::Index(boxed(e)?, all(args)?),
+        ExpressionKind::Lambda(params, body) => {
+            ExpressionKind::Lambda(params.clone(), Box::new(inline_captures(body, captured, &[bound, params].concat())?))
+        },
+        ExpressionKind::Empty | ExpressionKind::Number(_) | ExpressionKind::Colon => expression.kind.clone(),
+    };
+    Some(Expression {kind, span: expression.span})
+}
+
+// f64 display is exact, only the special values need spelling out
+fn number_source(num: f64) -> String {
+    match num {
+        _ if num.is_nan() => "(0/0)".to_string(),
+        f64::INFINITY => "(1/0)".to_string(),
+        f64::NEG_INFINITY => "(-1/0)".to_string(),
+        _ => num.to_string(),
+    }
+}
+
+fn complex_source(num: Complex) -> String {
+    match num.re.is_finite() && num.im.is_finite() {
+        true => num.to_string(),
+        false => format!("({} + {} * 1i)", number_source(num.re), number_source(num.im)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_read_back() {
+        let mut session = Session::new();
+        let values = [
+            Value::Number(0.1 + 0.2),
+            Value::Number(f64::NEG_INFINITY),
+            Value::Complex(Complex::new(-1.5, 2e-30)),
+            Value::Matrix(vec![vec![1.0, -2.0], vec![f64::INFINITY, 1e300]]),
+            Value::Matrix(vec![]),
+            Value::ComplexMatrix(vec![vec![Complex::new(1.0, -1.0), Complex::new(f64::INFINITY, 1.0)]]),
+        ];
+        for value in values {
+            let source = value_source(&value).unwrap();
+            match session.execute(&format!("x = {source}")) {
+                LineResult::Value(read) => assert_eq!(format!("{read:?}"), format!("{value:?}")),
+                result => panic!("{source} gave {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn only_definitions_restore() {
+        let mut session = Session::new();
+        session.execute("a = 1");
+        let msg = restore_lines(&mut session, "-- saved\n\na = 2\nb = [1, 2]\ndef f(x) = x + a\n").unwrap();
+        assert_eq!(msg, "restored 2 variables and 1 function, overwrote a");
+        assert!(matches!(session.execute("f(1)"), LineResult::Value(Value::Number(3.0))));
+
+        for text in ["a = 5\nquit", "restore same", "a = 5\n1 + 1", "quit = 1", "A[1] = 2"] {
+            assert!(matches!(restore_lines(&mut session, text), Err(ScriptError::NotADefinition(..))), "{text}");
+        }
+        assert!(matches!(session.execute("a"), LineResult::Value(Value::Number(2.0))));
+    }
+
+    #[test]
+    fn lambdas_keep_captures() {
+        let mut session = Session::new();
+        session.execute("def adder(n) = x -> x + n");
+        session.execute("add3 = adder(3)");
+        session.execute("x = 5");
+        session.execute("f = y -> y + x");
+        session.execute("x = -100");
+        session.execute("def compose(f, g) = x -> f(g(x))");
+        session.execute("h = compose(std.sin, std.cos)");
+        session.execute("k = compose(f, std.sin)");
+        session.execute("m = v -> std.map(f, v)");
+        let source = |name: &str| value_source(session.context.get_var(name).unwrap());
+        assert_eq!(source("add3").as_deref(), Some("x -> x + 3"));
+        assert_eq!(source("f").as_deref(), Some("y -> y + 5"));
+        assert_eq!(source("h").as_deref(), Some("x -> std.sin(std.cos(x))"));
+        assert_eq!(source("k"), None);
+        assert_eq!(source("m").as_deref(), Some("v -> std.map(y -> y + 5, v)"));
+
+        let text = ["add3", "f", "h", "m", "x"].map(|name| format!("{name} = {}", source(name).unwrap())).join("\n");
+        let mut restored = Session::new();
+        restore_lines(&mut restored, &text).unwrap();
+        assert!(matches!(restored.execute("add3(1)"), LineResult::Value(Value::Number(4.0))));
+        assert!(matches!(restored.execute("f(1)"), LineResult::Value(Value::Number(6.0))));
+        assert!(matches!(restored.execute("m(1)"), LineResult::Value(Value::Number(6.0))));
+    }
+}
@@ -1,11 +1,111 @@
-use std::io;
+use std::{env, fs, io::{self, BufRead, IsTerminal, Write}, iter, process::ExitCode, thread};
 
-use full_calc::app::state::App;
+use full_calc::app::{batch::{self, Flow, Source}, session::Session, state::App, user_scripts::ScriptError};
 
-fn main() -> io::Result<()> {
+// deep recursion in user functions needs more than the default main thread stack
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+const USAGE: &str = "usage: lcalc [-r] [-e <expression>]... [file]
+
+with no expressions or file the calculator opens, unless input is piped in
+  -e, --eval <expression>  evaluate an expression, can be given more than once
+  -r, --raw                don't run the init script
+  -h, --help               show this message
+  file                     run each line of a file, '-' reads from stdin";
+
+fn main() -> io::Result<ExitCode> {
+    let app_thread = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)?;
+    app_thread.join().unwrap_or_else(|_| Err(io::Error::other("app thread panicked")))
+}
+
+fn run() -> io::Result<ExitCode> {
+    let mut args = env::args().skip(1);
+    let mut expressions = Vec::new();
+    let mut file = None;
+    let mut is_raw = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" | "--eval" => match args.next() {
+                Some(expression) => expressions.push(expression),
+                None => return usage_error("-e needs an expression"),
+            },
+            "-r" | "--raw" => is_raw = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(ExitCode::SUCCESS);
+            },
+            _ if arg.starts_with('-') && arg != "-" => return usage_error(&format!("unknown option '{arg}'")),
+            _ if file.is_none() => file = Some(arg),
+            _ => return usage_error("only one file can be run at a time"),
+        }
+    }
+
+    let is_piped = !io::stdin().is_terminal();
+    let script_lines: Box<dyn Iterator<Item = String>> = match file.as_deref() {
+        Some("-") => Box::new(stdin_lines()),
+        Some(path) => match fs::read_to_string(path) {
+            Ok(script) => Box::new(skip_shebang(script.lines().map(String::from)).collect::<Vec<_>>().into_iter()),
+            Err(e) => {
+                eprintln!("lcalc: can't read '{path}': {e}");
+                return Ok(ExitCode::FAILURE);
+            },
+        },
+        None if !expressions.is_empty() => Box::new(iter::empty()),
+        None if is_piped => Box::new(stdin_lines()),
+        None => return run_tui(is_raw),
+    };
+    let script_source = match file.as_deref() {
+        Some("-") | None => Source::Stdin,
+        Some(path) => Source::File(path),
+    };
+
+    let mut session = Session::new();
+    let (mut out, mut err) = (io::stdout().lock(), io::stderr().lock());
+    if !is_raw {
+        match session.run_script("init") {
+            Ok(lines) => batch::report_init(lines, &mut err)?,
+            Err(ScriptError::ScriptNotFound(_) | ScriptError::NoConfigPath) => {},
+            Err(e) => writeln!(err, "lcalc: couldn't run the init script: {e}")?,
+        }
+    }
+    // -e expressions run first and are numbered apart from the file's lines
+    let mut flow = batch::run_lines(&mut session, Source::Expressions, expressions, &mut out, &mut err)?;
+    if flow == Flow::Continue {
+        flow = batch::run_lines(&mut session, script_source, script_lines, &mut out, &mut err)?;
+    }
+    match flow {
+        Flow::Failed => Ok(ExitCode::FAILURE),
+        Flow::Continue | Flow::Quit => Ok(ExitCode::SUCCESS),
+    }
+}
+
+fn run_tui(is_raw: bool) -> io::Result<ExitCode> {
+    let mut app = match is_raw {
+        true => App::new_raw(),
+        false => App::new(),
+    };
     let mut terminal = ratatui::init();
-    // terminal.clear()?;
-    let app_result = App::new().run(&mut terminal);
+    let app_result = app.run(&mut terminal);
     ratatui::restore();
-    app_result
+    app_result.map(|_| ExitCode::SUCCESS)
+}
+
+fn stdin_lines() -> impl Iterator<Item = String> {
+    skip_shebang(io::stdin().lock().lines().map_while(Result::ok))
+}
+
+// so scripts can start with #!/usr/bin/env lcalc, it's blanked rather than removed to keep line numbers right
+fn skip_shebang(lines: impl Iterator<Item = String>) -> impl Iterator<Item = String> {
+    lines.enumerate().map(|(i, line)| match i == 0 && line.starts_with("#!") {
+        true => String::new(),
+        false => line,
+    })
+}
+
+fn usage_error(msg: &str) -> io::Result<ExitCode> {
+    eprintln!("lcalc: {msg}\n\n{USAGE}");
+    Ok(ExitCode::from(2))
 }
@@ -0,0 +1,28 @@
+// every command word with the options that can follow it. app::commands runs them and the
+// highlighter colors them, options only count after the command that takes them
+pub static COMMAND_WORDS: &[(&str, &[&str])] = &[
+    ("clear", &[]),
+    ("quit", &[]),
+    ("exit", &[]),
+    ("reload", &["raw"]),
+    ("use", &[]),
+    ("load", &[]),
+    ("def", &[]),
+    ("config", &[
+        "cursor", "script", "theme", "history", "autosave", "on", "off", "trig", "deg", "rad",
+        "complex", "rect", "polar", "precision", "seed", "depth",
+    ]),
+    ("show", &["vars", "functions", "help"]),
+    ("panel", &["vars", "autocomplete", "preview", "on", "off"]),
+    ("save", &[]),
+    ("restore", &[]),
+    ("plot", &["from", "to", "data", "hist", "bar", "line", "scatter"]),
+];
+
+pub fn is_command(word: &str) -> bool {
+    COMMAND_WORDS.iter().any(|(name, _)| *name == word)
+}
+
+pub fn is_command_param(command: &str, word: &str) -> bool {
+    COMMAND_WORDS.iter().any(|(name, params)| *name == command && params.contains(&word))
+}
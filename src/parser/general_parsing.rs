@@ -1,7 +1,7 @@
 use core::fmt;
 
 use crate::app::state::{FunctionBody, FunctionDef};
-use super::{syntax_tree::{generate_syntax_tree, SyntaxError}, tokens::Token};
+use super::{syntax_tree::{generate_syntax_tree, SyntaxError}, tokens::{SpannedToken, Token}};
 
 #[derive(Debug)]
 pub enum FunctionDefinitionError {
@@ -13,7 +13,7 @@ pub enum FunctionDefinitionError {
     Default,
 }
 
-pub fn parse_function_definition(tokens: &Vec<Token>) -> Result<FunctionDef, FunctionDefinitionError> {
+pub fn parse_function_definition(tokens: &[Token]) -> Result<FunctionDef, FunctionDefinitionError> {
     let function_name = match tokens.get(1) {
         Some(token) => match token {
             Token::Identifier(ident) => ident.clone(),
@@ -41,8 +41,8 @@ pub fn parse_function_definition(tokens: &Vec<Token>) -> Result<FunctionDef, Fun
         return Err(FunctionDefinitionError::MissingAssignOp);
     }
 
-    let body_tokens = tokens[params_end + 2..].to_vec();
-    let function_body = generate_syntax_tree(body_tokens).map_err(|e| FunctionDefinitionError::SyntaxError(e))?;
+    let body_tokens = tokens[params_end + 2..].iter().cloned().map(SpannedToken::from).collect();
+    let function_body = generate_syntax_tree(body_tokens).map_err(FunctionDefinitionError::SyntaxError)?;
 
     Ok(FunctionDef {
         name: function_name,
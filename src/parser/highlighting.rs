@@ -1,3 +1,5 @@
+use super::{commands, tokens::{tokenize_spanned, SpannedToken, Token}};
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum HighlightTokenType {
     Identifier,
@@ -7,6 +9,7 @@ pub enum HighlightTokenType {
     Space,
     Newline,
     Tab,
+    Error,
 }
 
 #[derive(PartialEq, Debug)]
@@ -15,130 +18,71 @@ pub struct HighlightToken {
     pub kind: HighlightTokenType,
 }
 
+// built from the same tokens the parser sees so the colors always match how a line is read
 pub fn get_highlight_tokens(line: &str) -> Vec<HighlightToken> {
-    Tokenizer::new(line).consume()
-}
-
-struct Tokenizer<'a> {
-    line: &'a str,
-    tokens: Vec<HighlightToken>,
-    current_kind: HighlightTokenType,
-    current_buf: String,
-}
-
-impl Tokenizer<'_> {
-    fn new(line: &str) -> Tokenizer {
-        Tokenizer {
-            line,
-            tokens: Vec::new(),
-            current_kind: HighlightTokenType::Space,
-            current_buf: String::new(),
-        }
-    }
-
-    fn consume(mut self) -> Vec<HighlightToken> {
-        for ch in self.line.chars() {
-            if ch == '\r' || ch == '\n' {
-                self.flush_token();
-                self.tokens.push(HighlightToken {
-                    text: String::new(),
-                    kind: HighlightTokenType::Newline,
-                });
-                continue;
-            }
-
-            if ch == '\t' {
-                self.flush_token();
-                self.tokens.push(HighlightToken {
-                    text: String::new(),
-                    kind: HighlightTokenType::Tab,
-                });
-                continue;
-            }
-
-            match self.current_kind.clone() {
-                HighlightTokenType::Space => {
-                    if ch == ' ' {
-                        self.current_buf.push(ch);
-                    } else {
-                        self.start_token(ch);
-                    }
+    let mut tokens = Vec::new();
+    let mut rest = line;
+    // command params are only colored after the last command word that takes them
+    let mut command: Option<String> = None;
+    loop {
+        let mut end = 0;
+        let mut comment_start = None;
+        for SpannedToken {token, span} in tokenize_spanned(rest) {
+            push_whitespace(&mut tokens, &rest[end..span.start]);
+            end = span.end;
+            let kind = match token {
+                // the text after '--' is colored like any other line
+                Token::Comment(_) => {
+                    comment_start = Some(span.start + 2);
+                    tokens.push(HighlightToken::op("--"));
+                    break;
                 },
-                HighlightTokenType::Identifier | HighlightTokenType::Number => {
-                    if ch.is_alphanumeric() || ch == '.' || ch == '_' {
-                        self.current_buf.push(ch);
-                    } else {
-                        self.start_token(ch);
-                    }
+                Token::Identifier(ref word) if commands::is_command(word) => {
+                    command = Some(word.clone());
+                    HighlightTokenType::Command
                 },
-                HighlightTokenType::Operator => {
-                    if ch.is_alphanumeric() || ch == '.' || ch == '_' {
-                        self.start_token(ch);
-                    } else {
-                        self.current_buf.push(ch)
-                    }
+                Token::Identifier(ref word) if command.as_ref().is_some_and(|command| commands::is_command_param(command, word)) => {
+                    HighlightTokenType::Command
                 },
-                HighlightTokenType::Command |
-                HighlightTokenType::Newline |
-                HighlightTokenType::Tab => panic!("token type set weird"),
-            }
+                Token::Identifier(_) => HighlightTokenType::Identifier,
+                Token::Number(_) => HighlightTokenType::Number,
+                _ => HighlightTokenType::Operator,
+            };
+            tokens.push(HighlightToken {text: rest[span.start..span.end].to_string(), kind});
         }
-
-        self.flush_token();
-        self.tokens
-    }
-
-    // true for all primary commands, true for command params if prior token is command
-    fn current_is_command(&self) -> bool {
-        match self.current_buf.as_str() {
-            "clear" | "quit" | "exit" | "reload" | "use" | "load" | "def" | "config" | "show" | "panel" => true,
-            "raw" | "theme" | "trig" | "script" | "deg" | "rad" | "vars" | "autocomplete" | "preview" | "functions" | "help" => {
-                match self.tokens.iter().rev().find(|token| token.kind != HighlightTokenType::Space) {
-                    Some(token) => match token.kind {
-                        HighlightTokenType::Command => true,
-                        _ => false,
-                    },
-                    None => false,
-                }
+        match comment_start {
+            Some(start) => rest = &rest[start..],
+            None => {
+                push_whitespace(&mut tokens, &rest[end..]);
+                return tokens;
             },
-            _ => false,
         }
     }
+}
 
-    fn flush_token(&mut self) {
-        if self.current_buf.is_empty() {
-            return;
-        }
-
-        if self.current_is_command() {
-            self.current_kind = HighlightTokenType::Command;
+// the lexer skips whitespace, so the gaps between its tokens are filled back in here
+fn push_whitespace(tokens: &mut Vec<HighlightToken>, gap: &str) {
+    for ch in gap.chars() {
+        match ch {
+            '\r' | '\n' => tokens.push(HighlightToken::newline()),
+            '\t' => tokens.push(HighlightToken::tab()),
+            _ => match tokens.last_mut() {
+                Some(last) if last.kind == HighlightTokenType::Space => last.text.push(ch),
+                _ => tokens.push(HighlightToken {text: ch.to_string(), kind: HighlightTokenType::Space}),
+            },
         }
-
-        self.tokens.push(HighlightToken {
-            text: self.current_buf.clone(),
-            kind: self.current_kind.clone(),
-        });
-
-        self.current_buf.clear();
     }
+}
 
-    fn start_token(&mut self, ch: char) {
-        self.flush_token();
-        self.current_buf.push(ch);
-
-        if ch.is_whitespace() {
-            self.current_kind = HighlightTokenType::Space;
-        } else if ch.is_alphabetic() {
-            self.current_kind = HighlightTokenType::Identifier;
-        } else if ch.is_numeric() || ch == '.' {
-            self.current_kind = HighlightTokenType::Number;
-        } else {
-            self.current_kind = HighlightTokenType::Operator;
+impl HighlightToken {
+    // how many bytes of the line this came from, tabs and newlines are kept without their text
+    pub fn source_len(&self) -> usize {
+        match self.kind {
+            HighlightTokenType::Tab | HighlightTokenType::Newline => 1,
+            _ => self.text.len(),
         }
     }
-}
 
-impl HighlightToken {
     pub fn text(text: String) -> HighlightToken {
         HighlightToken {
             text,
@@ -232,6 +176,36 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn command_params() {
+        let kinds = |line: &str| get_highlight_tokens(line).into_iter()
+            .filter(|token| token.kind != HighlightTokenType::Space)
+            .map(|token| (token.text, token.kind == HighlightTokenType::Command))
+            .collect::<Vec<_>>();
+        let words = |pairs: &[(&str, bool)]| pairs.iter().map(|(text, is_command)| (text.to_string(), *is_command)).collect::<Vec<_>>();
+        assert_eq!(kinds("panel preview off"), words(&[("panel", true), ("preview", true), ("off", true)]));
+        assert_eq!(kinds("plot data M line"), words(&[("plot", true), ("data", true), ("M", false), ("line", true)]));
+        assert_eq!(kinds("plot f from 0 to 1"), words(&[("plot", true), ("f", false), ("from", true), ("0", false), ("to", true), ("1", false)]));
+        assert_eq!(kinds("show line"), words(&[("show", true), ("line", false)]));
+    }
+
+    #[test]
+    fn matches_parser() {
+        let t = get_highlight_tokens("my_var>=-2 -- not x_1");
+        assert_eq!(t, vec![
+            HighlightToken {text: "my_var".to_string(), kind: HighlightTokenType::Identifier},
+            HighlightToken {text: ">=".to_string(), kind: HighlightTokenType::Operator},
+            HighlightToken {text: "-".to_string(), kind: HighlightTokenType::Operator},
+            HighlightToken {text: "2".to_string(), kind: HighlightTokenType::Number},
+            HighlightToken {text: " ".to_string(), kind: HighlightTokenType::Space},
+            HighlightToken {text: "--".to_string(), kind: HighlightTokenType::Operator},
+            HighlightToken {text: " ".to_string(), kind: HighlightTokenType::Space},
+            HighlightToken {text: "not".to_string(), kind: HighlightTokenType::Operator},
+            HighlightToken {text: " ".to_string(), kind: HighlightTokenType::Space},
+            HighlightToken {text: "x_1".to_string(), kind: HighlightTokenType::Identifier},
+        ]);
+    }
+
     #[test]
     fn whitespace() {
         let t = get_highlight_tokens("1+.5\n -2.0");
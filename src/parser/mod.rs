@@ -2,3 +2,4 @@ pub mod tokens;
 pub mod syntax_tree;
 pub mod highlighting;
 pub mod general_parsing;
+pub mod commands;
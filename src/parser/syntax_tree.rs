@@ -1,32 +1,71 @@
 use std::fmt;
 
-use super::tokens::Token;
-
-const MAX_BINARY_PRECEDENCE: i8 = 3;
+use super::tokens::{Span, SpannedToken, Token};
+
+// a lambda body takes everything after the arrow, so one needs parentheses anywhere but on its own
+const LAMBDA_PRECEDENCE: i8 = -1;
+const MAX_BINARY_PRECEDENCE: i8 = 8;
+const NOT_PRECEDENCE: i8 = 3;
+const RANGE_PRECEDENCE: i8 = 5;
+// unary minus binds tighter than '^', and indexing or calls tighter still
+const UNARY_PRECEDENCE: i8 = MAX_BINARY_PRECEDENCE + 1;
+const ATOM_PRECEDENCE: i8 = MAX_BINARY_PRECEDENCE + 2;
+// deeper input would risk overflowing the stack while parsing or evaluating
+const MAX_NESTING: usize = 100;
 
 #[derive(Debug)]
 pub enum SyntaxError {
-    NoClosingParen(Vec<Expression>),
-    CallNonIdentifier(Token),
-    UnexpectedToken(Token),
-    ExpectedButGot(Token, Token),
+    Unclosed(Token, Span), // the opening token
+    UnexpectedToken(Token, Span),
+    ExpectedButGot(Token, Token, Span),
+    TooDeep(Span),
 }
 
 impl fmt::Display for SyntaxError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            SyntaxError::NoClosingParen(expressions) => write!(f, "no closing parentheses around {:?}", expressions),
-            SyntaxError::CallNonIdentifier(token) => write!(f, "attempting to call {:?} as a function", token),
-            SyntaxError::UnexpectedToken(token) => write!(f, "unexpected {:?}", token),
-            SyntaxError::ExpectedButGot(t1, t2) => write!(f, "expected '{:?}' but got '{:?}'", t1, t2),
+            SyntaxError::Unclosed(token, _) => write!(f, "unclosed {} opened here", token.describe()),
+            SyntaxError::UnexpectedToken(token, _) => write!(f, "unexpected {}", token.describe()),
+            SyntaxError::ExpectedButGot(t1, t2, _) => write!(f, "expected {} but got {}", t1.describe(), t2.describe()),
+            SyntaxError::TooDeep(_) => write!(f, "expression is nested more than {MAX_NESTING} levels deep"),
         }
     }
 }
 
 impl std::error::Error for SyntaxError {}
 
+impl SyntaxError {
+    pub fn span(&self) -> Span {
+        match self {
+            SyntaxError::Unclosed(_, span) |
+            SyntaxError::UnexpectedToken(_, span) |
+            SyntaxError::ExpectedButGot(_, _, span) |
+            SyntaxError::TooDeep(span) => *span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+// spans only say where an expression was typed, so they don't count towards equality
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl From<ExpressionKind> for Expression {
+    fn from(kind: ExpressionKind) -> Self {
+        Expression {kind, span: Span::default()}
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
-pub enum Expression {
+pub enum ExpressionKind {
     Empty,
     Identifier(String),
     Number(String),
@@ -35,43 +74,166 @@ pub enum Expression {
     Binary(Box<Expression>, Token, Box<Expression>),
     Unary(Token, Box<Expression>),
     FuncCall(String, Vec<Expression>),
+    Range(Box<Expression>, Option<Box<Expression>>, Box<Expression>), // start, step, end
+    Index(Box<Expression>, Vec<Expression>),
+    Colon, // a bare ':' selecting everything inside an index
+    Lambda(Vec<String>, Box<Expression>), // params, body
+}
+
+// source text that parses back into the same tree. the user's own parentheses are dropped
+// and only put back where the parser would group things differently without them
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl fmt::Display for ExpressionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |expressions: &[Expression]| expressions.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ");
+        match self {
+            ExpressionKind::Empty => Ok(()),
+            ExpressionKind::Identifier(s) | ExpressionKind::Number(s) => write!(f, "{s}"),
+            ExpressionKind::Group(e) => write!(f, "{e}"),
+            ExpressionKind::Matrix(rows) => {
+                let rows: Vec<_> = rows.iter().map(|row| join(row)).collect();
+                write!(f, "[{}]", rows.join("; "))
+            },
+            // all binary operators are left associative, so only the rhs needs parentheses at the same level
+            ExpressionKind::Binary(lhs, op, rhs) => {
+                let precedence = op.binary_precedence();
+                write!(f, "{} {op} {}", Operand(lhs, precedence), Operand(rhs, precedence + 1))
+            },
+            ExpressionKind::Unary(Token::Not, e) => write!(f, "not {}", Operand(e, NOT_PRECEDENCE)),
+            // '--' would start a comment
+            ExpressionKind::Unary(op, e) => match e.ungrouped().kind {
+                ExpressionKind::Unary(Token::Minus, _) => write!(f, "{op}({e})"),
+                _ => write!(f, "{op}{}", Operand(e, UNARY_PRECEDENCE)),
+            },
+            ExpressionKind::FuncCall(fname, args) => write!(f, "{fname}({})", join(args)),
+            ExpressionKind::Range(start, step, end) => {
+                write!(f, "{}:", Operand(start, RANGE_PRECEDENCE + 1))?;
+                if let Some(step) = step {
+                    write!(f, "{}:", Operand(step, RANGE_PRECEDENCE + 1))?;
+                }
+                write!(f, "{}", Operand(end, RANGE_PRECEDENCE + 1))
+            },
+            ExpressionKind::Index(e, args) => write!(f, "{}[{}]", Operand(e, ATOM_PRECEDENCE), join(args)),
+            ExpressionKind::Colon => write!(f, ":"),
+            ExpressionKind::Lambda(params, body) => match params.as_slice() {
+                [param] => write!(f, "{param} -> {body}"),
+                params => write!(f, "({}) -> {body}", params.join(", ")),
+            },
+        }
+    }
+}
+
+// an expression printed where anything binding looser than the precedence needs parentheses
+struct Operand<'a>(&'a Expression, i8);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Operand(e, precedence) = *self;
+        match e.precedence() < precedence {
+            true => write!(f, "({e})"),
+            false => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Expression {
+    // names the expression uses that a lambda inside it doesn't bind, called names included
+    // since a variable can hold a function
+    pub fn free_names(&self, bound: &[String], names: &mut Vec<String>) {
+        match &self.kind {
+            ExpressionKind::Identifier(name) | ExpressionKind::FuncCall(name, _) if !bound.contains(name) && !names.contains(name) => {
+                names.push(name.clone());
+            },
+            _ => (),
+        }
+        match &self.kind {
+            ExpressionKind::Empty | ExpressionKind::Identifier(_) | ExpressionKind::Number(_) | ExpressionKind::Colon => (),
+            ExpressionKind::Group(e) | ExpressionKind::Unary(_, e) => e.free_names(bound, names),
+            ExpressionKind::Binary(lhs, _, rhs) => {
+                lhs.free_names(bound, names);
+                rhs.free_names(bound, names);
+            },
+            ExpressionKind::Matrix(rows) => rows.iter().flatten().for_each(|e| e.free_names(bound, names)),
+            ExpressionKind::FuncCall(_, args) => args.iter().for_each(|e| e.free_names(bound, names)),
+            ExpressionKind::Range(start, step, end) => {
+                start.free_names(bound, names);
+                if let Some(step) = step {
+                    step.free_names(bound, names);
+                }
+                end.free_names(bound, names);
+            },
+            ExpressionKind::Index(e, args) => {
+                e.free_names(bound, names);
+                args.iter().for_each(|e| e.free_names(bound, names));
+            },
+            ExpressionKind::Lambda(params, body) => body.free_names(&[bound, params].concat(), names),
+        }
+    }
+
+    fn ungrouped(&self) -> &Expression {
+        match &self.kind {
+            ExpressionKind::Group(e) => e.ungrouped(),
+            _ => self,
+        }
+    }
+
+    // how tightly the expression holds together, matching the levels the parser goes through
+    fn precedence(&self) -> i8 {
+        match &self.ungrouped().kind {
+            ExpressionKind::Lambda(..) => LAMBDA_PRECEDENCE,
+            ExpressionKind::Binary(_, op, _) => op.binary_precedence(),
+            ExpressionKind::Unary(Token::Not, _) => NOT_PRECEDENCE,
+            ExpressionKind::Range(..) => RANGE_PRECEDENCE,
+            ExpressionKind::Unary(..) => UNARY_PRECEDENCE,
+            _ => ATOM_PRECEDENCE,
+        }
+    }
 }
 
 struct TreeBuilder {
-    tokens: Vec<Token>,
-    i: usize
+    tokens: Vec<SpannedToken>,
+    i: usize,
+    depth: usize,
 }
 
 
 impl Token {
     fn matches_binary_precedence(&self, precedence: i8) -> bool {
-        match (precedence, self) {
-            (0, Token::Assign | Token::AltAssign) => true,
-            (1, Token::Plus | Token::Minus) => true,
-            (2, Token::Mult | Token::Div) => true,
-            (3, Token::Pow) => true,
-            _ => false,
-        }
+        matches!(
+            (precedence, self),
+            (0, Token::Assign | Token::AltAssign) |
+            (1, Token::Or) |
+            (2, Token::And) |
+            (4, Token::Equal | Token::NotEqual | Token::Less | Token::LessEqual | Token::Greater | Token::GreaterEqual) |
+            (6, Token::Plus | Token::Minus) |
+            (7, Token::Mult | Token::Div | Token::LeftDiv | Token::ElemMult | Token::ElemDiv) |
+            (8, Token::Pow | Token::ElemPow)
+        )
+    }
+
+    fn binary_precedence(&self) -> i8 {
+        (0..=MAX_BINARY_PRECEDENCE)
+            .find(|precedence| self.matches_binary_precedence(*precedence))
+            .unwrap_or(MAX_BINARY_PRECEDENCE)
     }
 
     fn matches_unary(&self) -> bool {
-        match self {
-            Token::Minus => true,
-            _ => false,
-        }
+        matches!(self, Token::Minus)
     }
 }
 
 impl TreeBuilder {
-    fn new(tokens: Vec<Token>) -> TreeBuilder {
-        TreeBuilder {tokens, i: 0}
+    fn new(tokens: Vec<SpannedToken>) -> TreeBuilder {
+        TreeBuilder {tokens, i: 0, depth: 0}
     }
 
     fn current(&self) -> &Token {
-        match self.tokens.get(self.i) {
-            Some(token) => token,
-            None => &Token::None,
-        }
+        self.peek(0)
     }
 
     fn advance(&mut self, n: usize) {
@@ -81,58 +243,160 @@ impl TreeBuilder {
     fn take(&mut self) -> &Token {
         self.i += 1;
         match self.tokens.get(self.i - 1) {
-            Some(token) => token,
+            Some(spanned) => &spanned.token,
             None => &Token::None,
         }
     }
 
     fn peek(&self, offset: usize) -> &Token {
         match self.tokens.get(self.i + offset) {
-            Some(token) => token,
+            Some(spanned) => &spanned.token,
             None => &Token::None,
         }
     }
 
-    fn peek_back(&self, offset: usize) -> &Token {
-        if offset > self.i {
-            return &Token::None;
-        }
-        match self.tokens.get(self.i - offset) {
-            Some(token) => token,
-            None => &Token::None,
+    // past the last token this is an empty span at the end of the line
+    fn span_at(&self, i: usize) -> Span {
+        match self.tokens.get(i) {
+            Some(spanned) => spanned.span,
+            None => {
+                let end = self.tokens.last().map(|spanned| spanned.span.end).unwrap_or(0);
+                Span::new(end, end)
+            },
         }
     }
 
+    fn current_span(&self) -> Span {
+        self.span_at(self.i)
+    }
+
+    // from the token at start up to the last one taken
+    fn span_from(&self, start: usize) -> Span {
+        self.span_at(start).to(self.span_at(self.i.saturating_sub(1).max(start)))
+    }
+
     fn parse(&mut self) -> Result<Expression, SyntaxError> {
-        self.parse_binary(0)
+        self.nested(|builder| builder.parse_binary(0))
+    }
+
+    // wraps anything that recurses back into the parser
+    fn nested(&mut self, parse_fn: impl FnOnce(&mut Self) -> Result<Expression, SyntaxError>) -> Result<Expression, SyntaxError> {
+        if self.depth >= MAX_NESTING {
+            return Err(SyntaxError::TooDeep(self.current_span()));
+        }
+        self.depth += 1;
+        let expression = parse_fn(self);
+        self.depth -= 1;
+        expression
     }
 
     fn parse_binary(&mut self, precedence: i8) -> Result<Expression, SyntaxError> {
+        if precedence == NOT_PRECEDENCE {
+            return self.parse_not();
+        }
+        if precedence == RANGE_PRECEDENCE {
+            return self.parse_range();
+        }
         let mut lhs = match precedence >= MAX_BINARY_PRECEDENCE {
-            false => self.parse_binary(precedence + 1),
-            true => self.parse_unary(),
+            false => self.parse_binary(precedence + 1)?,
+            true => self.parse_unary()?,
         };
 
         while self.current().matches_binary_precedence(precedence) {
             let op = self.take().clone();
             let rhs = match precedence >= MAX_BINARY_PRECEDENCE {
-                false => self.parse_binary(precedence + 1),
-                true => self.parse_unary(),
+                false => self.parse_binary(precedence + 1)?,
+                true => self.parse_unary()?,
             };
-            lhs = Ok(Expression::Binary(Box::new(lhs?), op, Box::new(rhs?)));
+            let span = lhs.span.to(rhs.span);
+            lhs = Expression {kind: ExpressionKind::Binary(Box::new(lhs), op, Box::new(rhs)), span};
+        }
+
+        Ok(lhs)
+    }
+
+    // binds looser than comparisons so 'not a == b' negates the comparison
+    fn parse_not(&mut self) -> Result<Expression, SyntaxError> {
+        match self.current() {
+            Token::Not => {
+                let start = self.i;
+                self.advance(1);
+                let rhs = self.nested(|builder| builder.parse_not())?;
+                let span = self.span_at(start).to(rhs.span);
+                Ok(Expression {kind: ExpressionKind::Unary(Token::Not, Box::new(rhs)), span})
+            },
+            _ => self.parse_binary(NOT_PRECEDENCE + 1),
         }
+    }
 
-        lhs
+    // start:end or start:step:end
+    fn parse_range(&mut self) -> Result<Expression, SyntaxError> {
+        let start = self.parse_binary(RANGE_PRECEDENCE + 1)?;
+        if *self.current() != Token::Colon {
+            return Ok(start);
+        }
+        self.advance(1);
+        let second = self.parse_binary(RANGE_PRECEDENCE + 1)?;
+        if *self.current() != Token::Colon {
+            let span = start.span.to(second.span);
+            return Ok(Expression {kind: ExpressionKind::Range(Box::new(start), None, Box::new(second)), span});
+        }
+        self.advance(1);
+        let end = self.parse_binary(RANGE_PRECEDENCE + 1)?;
+        let span = start.span.to(end.span);
+        Ok(Expression {kind: ExpressionKind::Range(Box::new(start), Some(Box::new(second)), Box::new(end)), span})
     }
 
     fn parse_unary(&mut self) -> Result<Expression, SyntaxError> {
         match self.current().matches_unary() {
             true => {
+                let start = self.i;
                 let op = self.take().clone();
-                let rhs = self.parse_unary()?;
-                Ok(Expression::Unary(op, Box::new(rhs)))
+                let rhs = self.nested(|builder| builder.parse_unary())?;
+                let span = self.span_at(start).to(rhs.span);
+                Ok(Expression {kind: ExpressionKind::Unary(op, Box::new(rhs)), span})
             },
-            false => self.parse_function_call(),
+            false => self.parse_index(),
+        }
+    }
+
+    fn parse_index(&mut self) -> Result<Expression, SyntaxError> {
+        let mut expression = self.parse_function_call()?;
+        while let Token::OpenBracket = self.current() {
+            let open = self.i;
+            self.advance(1);
+            let args = self.parse_index_args(open)?;
+            let span = expression.span.to(self.span_from(open));
+            expression = Expression {kind: ExpressionKind::Index(Box::new(expression), args), span};
+        }
+        Ok(expression)
+    }
+
+    fn parse_index_args(&mut self, open: usize) -> Result<Vec<Expression>, SyntaxError> {
+        let mut args = Vec::new();
+        loop {
+            let arg = match (self.current(), self.peek(1)) {
+                (Token::Colon, Token::Comma | Token::CloseBracket) => {
+                    let span = self.current_span();
+                    self.advance(1);
+                    Expression {kind: ExpressionKind::Colon, span}
+                },
+                _ => self.parse()?,
+            };
+            args.push(arg);
+            match self.take().clone() {
+                Token::Comma => continue,
+                Token::CloseBracket => return Ok(args),
+                token => return Err(self.closing_error(Token::CloseBracket, token, open)),
+            }
+        }
+    }
+
+    // running out of tokens points at the opening bracket, anything else at what was found
+    fn closing_error(&self, expected: Token, got: Token, open: usize) -> SyntaxError {
+        match got {
+            Token::None => SyntaxError::Unclosed(self.tokens[open].token.clone(), self.span_at(open)),
+            got => SyntaxError::ExpectedButGot(expected, got, self.span_at(self.i - 1)),
         }
     }
 
@@ -140,21 +404,25 @@ impl TreeBuilder {
         match (self.current(), self.peek(1)) {
             (Token::Identifier(fname), Token::OpenParen) => {
                 let fname = fname.clone();
+                let start = self.i;
                 self.advance(2);
-                let fargs = self.parse_function_args()?;
-                Ok(Expression::FuncCall(fname, fargs))
+                let fargs = self.parse_function_args(start + 1)?;
+                Ok(Expression {kind: ExpressionKind::FuncCall(fname, fargs), span: self.span_from(start)})
             },
             _ => self.parse_base(),
         }
     }
 
-    fn parse_function_args(&mut self) -> Result<Vec<Expression>, SyntaxError> {
+    fn parse_function_args(&mut self, open: usize) -> Result<Vec<Expression>, SyntaxError> {
         let mut args = Vec::new();
         let mut next_arg = self.parse()?;
 
         // empty means the closing ')' is already consumed
-        if let Expression::Empty = next_arg {
-            return Ok(args)
+        if let ExpressionKind::Empty = next_arg.kind {
+            return match self.peek_back(1) {
+                Token::CloseParen => Ok(args),
+                _ => Err(SyntaxError::Unclosed(Token::OpenParen, self.span_at(open))),
+            };
         }
 
         while let Token::Comma = self.current() {
@@ -163,65 +431,111 @@ impl TreeBuilder {
             next_arg = self.parse()?;
         }
 
-        match self.take() {
+        match self.take().clone() {
             Token::CloseParen => {
                 args.push(next_arg);
                 Ok(args)
             },
-            _ => Err(SyntaxError::NoClosingParen(args)),
+            token => Err(self.closing_error(Token::CloseParen, token, open)),
+        }
+    }
+
+    fn peek_back(&self, offset: usize) -> &Token {
+        if offset > self.i {
+            return &Token::None;
+        }
+        match self.tokens.get(self.i - offset) {
+            Some(spanned) => &spanned.token,
+            None => &Token::None,
         }
     }
 
-    fn parse_matrix(&mut self) -> Result<Expression, SyntaxError> {
+    fn parse_matrix(&mut self, open: usize) -> Result<Expression, SyntaxError> {
+        if let Token::CloseBracket = self.current() {
+            self.advance(1);
+            return Ok(Expression {kind: ExpressionKind::Matrix(Vec::new()), span: self.span_from(open)});
+        }
         let mut rows: Vec<Vec<Expression>> = Vec::new();
         let mut current_row: Vec<Expression> = Vec::new();
         let mut next_value = self.parse()?;
 
         while let Token::Comma | Token::Semicolon = self.current() {
-            self.advance(1);
             current_row.push(next_value);
-
-            match *self.peek_back(1) {
-                Token::Comma => next_value = self.parse()?,
-                Token::Semicolon => {
-                    rows.push(current_row);
-                    current_row = Vec::new();
-                    next_value = self.parse()?;
-                },
-                _ => panic!("messed up parsing"),
-            };
+            if let Token::Semicolon = self.take() {
+                rows.push(current_row);
+                current_row = Vec::new();
+            }
+            next_value = self.parse()?;
         }
 
         current_row.push(next_value);
         rows.push(current_row);
 
-        match self.take() {
-            Token::CloseBracket => Ok(Expression::Matrix(rows)),
-            t => Err(SyntaxError::ExpectedButGot(Token::CloseBracket, t.clone())),
+        match self.take().clone() {
+            Token::CloseBracket => Ok(Expression {kind: ExpressionKind::Matrix(rows), span: self.span_from(open)}),
+            token => Err(self.closing_error(Token::CloseBracket, token, open)),
+        }
+    }
+
+    // 'x ->' or '(a, b) ->', giving the parameters and how many tokens they take up
+    fn lambda_params(&self) -> Option<(Vec<String>, usize)> {
+        if let (Token::Identifier(param), Token::Arrow) = (self.current(), self.peek(1)) {
+            return Some((vec![param.clone()], 2));
+        }
+        if *self.current() != Token::OpenParen {
+            return None;
+        }
+        let mut params = Vec::new();
+        let mut offset = 1;
+        loop {
+            match (self.peek(offset), self.peek(offset + 1)) {
+                (Token::CloseParen, Token::Arrow) if params.is_empty() => return Some((params, offset + 2)),
+                (Token::Identifier(param), Token::Comma) => params.push(param.clone()),
+                (Token::Identifier(param), Token::CloseParen) if *self.peek(offset + 2) == Token::Arrow => {
+                    params.push(param.clone());
+                    return Some((params, offset + 3));
+                },
+                _ => return None,
+            }
+            offset += 2;
         }
     }
 
     fn parse_base(&mut self) -> Result<Expression, SyntaxError> {
-        match self.take() {
-            Token::Identifier(identifier) => Ok(Expression::Identifier(identifier.clone())),
-            Token::Number(num) => Ok(Expression::Number(num.clone())),
-            Token::Comment(_) => self.parse_base(),
-            Token::CloseParen | Token::None => Ok(Expression::Empty),
+        let start = self.i;
+        let span = self.current_span();
+        if let Some((params, len)) = self.lambda_params() {
+            self.advance(len);
+            let body = self.parse()?;
+            let span = span.to(body.span);
+            return Ok(Expression {kind: ExpressionKind::Lambda(params, Box::new(body)), span});
+        }
+        let kind = match self.take().clone() {
+            Token::Identifier(identifier) => ExpressionKind::Identifier(identifier),
+            Token::Number(num) => ExpressionKind::Number(num),
+            Token::Comment(_) => return self.parse_base(),
+            Token::CloseParen | Token::None => ExpressionKind::Empty,
             Token::OpenParen => {
                 let expression = self.parse()?;
-                match self.take() {
-                    Token::CloseParen => Ok(Expression::Group(Box::new(expression))),
-                    _ => Err(SyntaxError::NoClosingParen(vec![expression])),
+                match self.take().clone() {
+                    Token::CloseParen => ExpressionKind::Group(Box::new(expression)),
+                    token => return Err(self.closing_error(Token::CloseParen, token, start)),
                 }
             },
-            Token::OpenBracket => self.parse_matrix(),
-            _ => Err(SyntaxError::UnexpectedToken(self.peek_back(1).clone())),
-        }
+            Token::OpenBracket => return self.parse_matrix(start),
+            token => return Err(SyntaxError::UnexpectedToken(token, span)),
+        };
+        Ok(Expression {kind, span: self.span_from(start)})
     }
 }
 
-pub fn generate_syntax_tree(tokens: Vec<Token>) -> Result<Expression, SyntaxError> {
-    TreeBuilder::new(tokens).parse()
+pub fn generate_syntax_tree(tokens: Vec<SpannedToken>) -> Result<Expression, SyntaxError> {
+    let mut builder = TreeBuilder::new(tokens);
+    let expression = builder.parse()?;
+    match builder.current() {
+        Token::None | Token::Comment(_) => Ok(expression),
+        token => Err(SyntaxError::UnexpectedToken(token.clone(), builder.current_span())),
+    }
 }
 
 #[cfg(test)]
@@ -229,30 +543,89 @@ mod tests {
     use super::*;
     use crate::parser::tokens::*;
 
-    fn e(s: &str) -> Expression {
-        let tokens = tokenize(s);
-        generate_syntax_tree(tokens).unwrap()
+    fn e(s: &str) -> ExpressionKind {
+        let tokens = tokenize_spanned(s);
+        generate_syntax_tree(tokens).unwrap().kind
     }
 
     fn num(s: &str) -> Box<Expression> {
-        Box::new(Expression::Number(s.to_string()))
+        Box::new(ExpressionKind::Number(s.to_string()).into())
+    }
+    fn ident(s: &str) -> Box<Expression> {
+        Box::new(ExpressionKind::Identifier(s.to_string()).into())
     }
     fn bin(e1: Box<Expression>, op: Token, e2: Box<Expression>) -> Box<Expression> {
-        Box::new(Expression::Binary(e1, op, e2))
+        Box::new(ExpressionKind::Binary(e1, op, e2).into())
     }
     fn unary(op: Token, e1: Box<Expression>) -> Box<Expression> {
-        Box::new(Expression::Unary(op, e1))
+        Box::new(ExpressionKind::Unary(op, e1).into())
     }
     fn group(e1: Box<Expression>) -> Box<Expression> {
-        Box::new(Expression::Group(e1))
+        Box::new(ExpressionKind::Group(e1).into())
     }
     fn func(fname: &str, args: Vec<Expression>) -> Box<Expression> {
-        Box::new(Expression::FuncCall(fname.to_string(), args))
+        Box::new(ExpressionKind::FuncCall(fname.to_string(), args).into())
+    }
+
+    fn err_span(s: &str) -> (String, &str) {
+        let err = generate_syntax_tree(tokenize_spanned(s)).unwrap_err();
+        let span = err.span();
+        (err.to_string(), &s[span.start..span.end])
+    }
+
+    #[test]
+    fn error_spans() {
+        assert_eq!(err_span("1 + (2 * 3"), ("unclosed '(' opened here".to_string(), "("));
+        assert_eq!(err_span("[1, 2) + 3"), ("expected ']' but got ')'".to_string(), ")"));
+        assert_eq!(err_span("1 2"), ("unexpected '2'".to_string(), "2"));
+    }
+    #[test]
+    fn expression_spans() {
+        let tree = generate_syntax_tree(tokenize_spanned("x = 2 * std.abs(y)")).unwrap();
+        let ExpressionKind::Binary(_, _, rhs) = tree.kind else { panic!() };
+        assert_eq!(rhs.span, Span::new(4, 18));
+    }
+    // the tree without the user's parentheses, to compare structure only
+    fn ungrouped(kind: ExpressionKind) -> ExpressionKind {
+        let all = |expressions: Vec<Expression>| expressions.into_iter().map(|e| ungrouped(e.kind).into()).collect();
+        let boxed = |e: Box<Expression>| Box::new(ungrouped(e.kind).into());
+        match kind {
+            ExpressionKind::Group(e) => ungrouped(e.kind),
+            ExpressionKind::Matrix(rows) => ExpressionKind::Matrix(rows.into_iter().map(all).collect()),
+            ExpressionKind::Binary(lhs, op, rhs) => ExpressionKind::Binary(boxed(lhs), op, boxed(rhs)),
+            ExpressionKind::Unary(op, e) => ExpressionKind::Unary(op, boxed(e)),
+            ExpressionKind::FuncCall(name, args) => ExpressionKind::FuncCall(name, all(args)),
+            ExpressionKind::Range(start, step, end) => ExpressionKind::Range(boxed(start), step.map(boxed), boxed(end)),
+            ExpressionKind::Index(e, args) => ExpressionKind::Index(boxed(e), all(args)),
+            ExpressionKind::Lambda(params, body) => ExpressionKind::Lambda(params, boxed(body)),
+            kind => kind,
+        }
     }
 
+    #[test]
+    fn source_round_trip() {
+        let source = |line: &str| generate_syntax_tree(tokenize_spanned(line)).unwrap().to_string();
+        let lines = [
+            "x = 2 * -y + (1 - -1)", "[1, 2; 3, std.f(a, b)][end, 1:2:end]", "not (a and b) <= 3", "if(a, (1:3)[2], 0)",
+            "a - (b - c) / (d * e) ^ (f ^ g)", "-(-x) .^ (-(2))", "(x = 1) = (y = 2)", "1:(n + 1):(not m)",
+            "f = (x -> x ^ 2)", "(x -> x)[1] + 1", "std.map((a, b) -> c -> a + b + c, () -> 1)",
+        ];
+        for line in lines {
+            let printed = source(line);
+            assert_eq!(source(&printed), printed);
+            assert_eq!(ungrouped(e(&printed)), ungrouped(e(line)), "{line} printed as {printed}");
+        }
+        assert_eq!(source("((a + b)) * (c * d) * (e ^ f)"), "(a + b) * (c * d) * e ^ f");
+        assert_eq!(source("a - (b - c) - (d + e)"), "a - (b - c) - (d + e)");
+        assert_eq!(source("1 - -x ^ 2"), "1 - -x ^ 2");
+        assert_eq!(source("not (a == b) or (not c)"), "not a == b or not c");
+        assert_eq!(source("(-a)[1] + (1:3)[2]"), "(-a)[1] + (1:3)[2]");
+        assert_eq!(source("A[1:(end-1), :] .* 2"), "A[1:end - 1, :] .* 2");
+        assert_eq!(source("g = (x) -> (x + 1)"), "g = (x -> x + 1)");
+    }
     #[test]
     fn binary_ops() {
-        assert_eq!(e("1 + 2"), Expression::Binary(
+        assert_eq!(e("1 + 2"), ExpressionKind::Binary(
             num("1"),
             Token::Plus,
             num("2"),
@@ -260,7 +633,7 @@ mod tests {
     }
     #[test]
     fn operator_chaining() {
-        assert_eq!(e("1 - 2 * 3 - 4"), Expression::Binary(
+        assert_eq!(e("1 - 2 * 3 - 4"), ExpressionKind::Binary(
             bin(
                 num("1"),
                 Token::Minus,
@@ -275,10 +648,69 @@ mod tests {
         ))
     }
     #[test]
+    fn left_division() {
+        assert_eq!(e("1 + 2 \\ 3 * 4"), ExpressionKind::Binary(
+            num("1"),
+            Token::Plus,
+            bin(
+                bin(
+                    num("2"),
+                    Token::LeftDiv,
+                    num("3"),
+                ),
+                Token::Mult,
+                num("4"),
+            ),
+        ))
+    }
+    #[test]
+    fn ranges() {
+        assert_eq!(e("1:n+1"), ExpressionKind::Range(
+            num("1"),
+            None,
+            bin(
+                ident("n"),
+                Token::Plus,
+                num("1"),
+            ),
+        ));
+        assert_eq!(e("0:0.25:1"), ExpressionKind::Range(num("0"), Some(num("0.25")), num("1")));
+    }
+    #[test]
+    fn indexing() {
+        assert_eq!(e("a[:, 2:end]"), ExpressionKind::Index(
+            ident("a"),
+            vec![
+                ExpressionKind::Colon.into(),
+                ExpressionKind::Range(num("2"), None, ident("end")).into(),
+            ],
+        ));
+    }
+    #[test]
+    fn logic_precedence() {
+        assert_eq!(e("not a < 1 or b and 1:2 == c"), ExpressionKind::Binary(
+            unary(Token::Not, bin(
+                ident("a"),
+                Token::Less,
+                num("1"),
+            )),
+            Token::Or,
+            bin(
+                ident("b"),
+                Token::And,
+                bin(
+                    Box::new(ExpressionKind::Range(num("1"), None, num("2")).into()),
+                    Token::Equal,
+                    ident("c"),
+                ),
+            ),
+        ))
+    }
+    #[test]
     fn unary_ops() {
         assert_eq!(
             e("2 * -2 + 3"),
-            Expression::Binary(
+            ExpressionKind::Binary(
                 bin(
                     num("2"),
                     Token::Mult,
@@ -293,7 +725,7 @@ mod tests {
     fn using_parentheses() {
         assert_eq!(
             e("2^(-1 * (24))"),
-            Expression::Binary(
+            ExpressionKind::Binary(
                 num("2"),
                 Token::Pow,
                 group(bin(
@@ -308,7 +740,7 @@ mod tests {
     fn calling_functions() {
         assert_eq!(
             e("max(1, 2+4, 3) + 1"),
-            Expression::Binary(
+            ExpressionKind::Binary(
                 func("max", vec![
                     *num("1"),
                     *bin(
@@ -324,10 +756,23 @@ mod tests {
         )
     }
     #[test]
+    fn lambdas() {
+        let lambda = |params: &[&str], body| ExpressionKind::Lambda(params.iter().map(|p| p.to_string()).collect(), body);
+        assert_eq!(e("f = x -> x + 1"), ExpressionKind::Binary(
+            ident("f"),
+            Token::Assign,
+            Box::new(lambda(&["x"], bin(ident("x"), Token::Plus, num("1"))).into()),
+        ));
+        assert_eq!(e("(a, b) -> a"), lambda(&["a", "b"], ident("a")));
+        assert_eq!(e("() -> 1"), lambda(&[], num("1")));
+        assert_eq!(e("(a) * 2"), ExpressionKind::Binary(group(ident("a")), Token::Mult, num("2")));
+        assert_eq!(err_span("(a + 1) -> a"), ("unexpected '->'".to_string(), "->"));
+    }
+    #[test]
     fn matrices() {
         assert_eq!(
             e("[1, 2, 3; 4, 5, 6; 7, 8, 9]"),
-            Expression::Matrix(vec![
+            ExpressionKind::Matrix(vec![
                 vec![*num("1"), *num("2"), *num("3")],
                 vec![*num("4"), *num("5"), *num("6")],
                 vec![*num("7"), *num("8"), *num("9")],
@@ -15,15 +15,33 @@ static OPERATORS: &[(&str, Token)] = &[
     ("^", Token::Pow),
     ("*", Token::Mult),
     ("/", Token::Div),
+    ("\\", Token::LeftDiv),
+    (".*", Token::ElemMult),
+    ("./", Token::ElemDiv),
+    (".^", Token::ElemPow),
     ("+", Token::Plus),
     ("-", Token::Minus),
     ("=", Token::Assign),
     ("=>", Token::AltAssign),
+    ("->", Token::Arrow),
+    ("==", Token::Equal),
+    ("!=", Token::NotEqual),
+    ("<", Token::Less),
+    ("<=", Token::LessEqual),
+    (">", Token::Greater),
+    (">=", Token::GreaterEqual),
     (",", Token::Comma),
     (":", Token::Colon),
     (";", Token::Semicolon),
 ];
 
+// operators spelled as words, these can't be used as variable names
+static KEYWORDS: &[(&str, Token)] = &[
+    ("and", Token::And),
+    ("or", Token::Or),
+    ("not", Token::Not),
+];
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum Token {
     Identifier(String),
@@ -38,10 +56,24 @@ pub enum Token {
     Pow,
     Mult,
     Div,
+    LeftDiv,
+    ElemMult,
+    ElemDiv,
+    ElemPow,
     Plus,
     Minus,
     Assign,
     AltAssign,
+    Arrow,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Not,
     Comma,
     Colon,
     Semicolon,
@@ -62,10 +94,24 @@ impl fmt::Display for Token {
             Token::Pow => write!(f, "^"),
             Token::Mult => write!(f, "*"),
             Token::Div => write!(f, "/"),
+            Token::LeftDiv => write!(f, "\\"),
+            Token::ElemMult => write!(f, ".*"),
+            Token::ElemDiv => write!(f, "./"),
+            Token::ElemPow => write!(f, ".^"),
             Token::Plus => write!(f, "+"),
             Token::Minus => write!(f, "-"),
             Token::Assign => write!(f, "="),
             Token::AltAssign => write!(f, "=>"),
+            Token::Arrow => write!(f, "->"),
+            Token::Equal => write!(f, "=="),
+            Token::NotEqual => write!(f, "!="),
+            Token::Less => write!(f, "<"),
+            Token::LessEqual => write!(f, "<="),
+            Token::Greater => write!(f, ">"),
+            Token::GreaterEqual => write!(f, ">="),
+            Token::And => write!(f, "and"),
+            Token::Or => write!(f, "or"),
+            Token::Not => write!(f, "not"),
             Token::Comma => write!(f, ","),
             Token::Colon => write!(f, ":"),
             Token::Semicolon => write!(f, ";"),
@@ -73,22 +119,65 @@ impl fmt::Display for Token {
     }
 }
 
+// byte offsets into the line a token or expression came from
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span {start, end}
+    }
+
+    // covers both spans and everything between them
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+// for tokens that weren't typed by the user like an inserted 'ans'
+impl From<Token> for SpannedToken {
+    fn from(token: Token) -> Self {
+        SpannedToken {token, span: Span::default()}
+    }
+}
+
 impl Token {
+    // how the token is named in error messages
+    pub fn describe(&self) -> String {
+        match self {
+            Token::None => "end of line".to_string(),
+            token => format!("'{token}'"),
+        }
+    }
+
     pub fn is_binary_op(&self) -> bool {
-        match *self {
+        matches!(
+            *self,
             Token::Plus | Token::Minus |
-            Token::Mult | Token::Div |
-            Token::Pow |
-            Token::Assign | Token::AltAssign => true,
-            _ => false,
-        }
+            Token::Mult | Token::Div | Token::LeftDiv |
+            Token::ElemMult | Token::ElemDiv |
+            Token::Pow | Token::ElemPow |
+            Token::Equal | Token::NotEqual |
+            Token::Less | Token::LessEqual | Token::Greater | Token::GreaterEqual |
+            Token::And | Token::Or |
+            Token::Assign | Token::AltAssign
+        )
     }
 
     pub fn is_from_str(&self, st: &str) -> bool {
         match *self {
             Token::Identifier(ref ident) => ident == st,
             Token::Number(_) => false,
-            ref token => OPERATORS.iter().any(|(op_str, op_token)| *op_str == st && op_token == token),
+            ref token => OPERATORS.iter().chain(KEYWORDS).any(|(op_str, op_token)| *op_str == st && op_token == token),
         }
     }
 }
@@ -96,11 +185,23 @@ impl Token {
 // might want to add type as a struct field so not constantly recomputing
 #[derive(Default)]
 struct TokenState {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     current_buffer: String,
+    buffer_start: usize,
 }
 
 impl TokenState {
+    fn push_char(&mut self, i: usize, ch: char) {
+        if self.current_buffer.is_empty() {
+            self.buffer_start = i;
+        }
+        self.current_buffer.push(ch);
+    }
+
+    fn push_token(&mut self, token: Token, start: usize, len: usize) {
+        self.tokens.push(SpannedToken {token, span: Span::new(start, start + len)});
+    }
+
     fn continues_op(&self, ch: char) -> bool {
         let mut joined = self.current_buffer.clone();
         joined.push(ch);
@@ -109,23 +210,25 @@ impl TokenState {
 
     fn get_type(&self) -> TokenType {
         if self.current_buffer.is_empty() {
-            return TokenType::Unknown;
+            TokenType::Unknown
         } else if is_ident(self.current_buffer.chars().next_back().unwrap()) {
-            return TokenType::Identifier;
+            TokenType::Identifier
         } else {
-            return TokenType::Operator;
+            TokenType::Operator
         }
     }
 
     fn flush_token(&mut self) {
-        match self.get_type() {
+        let token = match self.get_type() {
             TokenType::Identifier => {
-                if self.current_buffer.chars().next().unwrap().is_alphabetic() {
-                    self.tokens.push(Token::Identifier(self.current_buffer.clone()));
+                let keyword = KEYWORDS.iter().find(|(st, _)| *st == self.current_buffer);
+                if let Some((_, keyword)) = keyword {
+                    keyword.clone()
+                } else if self.current_buffer.chars().next().is_some_and(|ch| ch.is_alphabetic() || ch == '_') {
+                    Token::Identifier(self.current_buffer.clone())
                 } else {
-                    self.tokens.push(Token::Number(self.current_buffer.clone()));
+                    Token::Number(self.current_buffer.clone())
                 }
-                self.current_buffer.clear();
             },
             TokenType::Operator => {
                 let op_type = OPERATORS.iter().find_map(|(st, enum_type)| {
@@ -135,18 +238,18 @@ impl TokenState {
                     }
                 });
 
-                if let Some(op_type) = op_type {
-                    self.tokens.push(op_type.clone());
-                    self.current_buffer.clear();
-                } else {
-                    self.tokens.push(Token::Unknown(std::mem::take(&mut self.current_buffer)));
+                match op_type {
+                    Some(op_type) => op_type.clone(),
+                    None => Token::Unknown(self.current_buffer.clone()),
                 }
             },
-            TokenType::Unknown => {},
+            TokenType::Unknown => return,
         };
+        self.push_token(token, self.buffer_start, self.current_buffer.len());
+        self.current_buffer.clear();
     }
 
-    fn consume(mut self) -> Vec<Token> {
+    fn consume(mut self) -> Vec<SpannedToken> {
         if !self.current_buffer.is_empty() {
             self.flush_token();
         }
@@ -155,29 +258,49 @@ impl TokenState {
 }
 
 fn is_ident(ch: char) -> bool {
-    ch.is_alphanumeric() || ch == '.'
+    ch.is_alphanumeric() || ch == '.' || ch == '_'
 }
 
 pub fn tokenize(line: &str) -> Vec<Token> {
+    tokenize_spanned(line).into_iter().map(|spanned| spanned.token).collect()
+}
+
+pub fn tokenize_spanned(line: &str) -> Vec<SpannedToken> {
     let mut token_state = TokenState::default();
-    let chars: Vec<_> = line.chars().collect();
+    let mut skip_next = false;
 
     for (i, ch) in line.char_indices() {
-        if let ('-', Some(&'-')) = (ch, chars.get(i+1)) {
+        if skip_next {
+            skip_next = false;
+            continue;
+        }
+        let next = line[i + ch.len_utf8()..].chars().next();
+        if let ('-', Some('-')) = (ch, next) {
             token_state.flush_token();
-            token_state.tokens.push(Token::Comment(String::from(&line[i..])));
+            token_state.push_token(Token::Comment(String::from(&line[i..])), i, line.len() - i);
             return token_state.consume();
         }
 
+        // '.' is also part of numbers and namespaces, so element-wise operators are picked out up front
+        if let ('.', Some(next @ ('*' | '/' | '^'))) = (ch, next) {
+            let op = OPERATORS.iter().find(|(st, _)| *st == format!(".{next}"));
+            if let Some((_, op_type)) = op {
+                token_state.flush_token();
+                token_state.push_token(op_type.clone(), i, 2);
+                skip_next = true;
+                continue;
+            }
+        }
+
         match token_state.get_type() {
             TokenType::Identifier => {
                 if ch.is_whitespace() {
                     token_state.flush_token();
                 } else if is_ident(ch) {
-                    token_state.current_buffer.push(ch);
+                    token_state.push_char(i, ch);
                 } else {
                     token_state.flush_token();
-                    token_state.current_buffer.push(ch);
+                    token_state.push_char(i, ch);
                 }
             },
             TokenType::Operator => {
@@ -185,18 +308,18 @@ pub fn tokenize(line: &str) -> Vec<Token> {
                     token_state.flush_token();
                 } else if is_ident(ch) {
                     token_state.flush_token();
-                    token_state.current_buffer.push(ch);
+                    token_state.push_char(i, ch);
                 } else if token_state.continues_op(ch) {
-                    token_state.current_buffer.push(ch);
+                    token_state.push_char(i, ch);
 
                 } else {
                     token_state.flush_token();
-                    token_state.current_buffer.push(ch);
+                    token_state.push_char(i, ch);
                 }
             },
             TokenType::Unknown => {
                 if !ch.is_whitespace() {
-                    token_state.current_buffer.push(ch);
+                    token_state.push_char(i, ch);
                 }
             },
         };
@@ -229,6 +352,69 @@ mod tests {
         ]);
     }
     #[test]
+    fn underscores() {
+        let t = tokenize("_a1 + my_var.x_2 - 1_000");
+        assert_eq!(t, vec![
+            Token::Identifier("_a1".to_string()),
+            Token::Plus,
+            Token::Identifier("my_var.x_2".to_string()),
+            Token::Minus,
+            Token::Number("1_000".to_string()),
+        ]);
+    }
+    #[test]
+    fn elementwise_operators() {
+        let t = tokenize("a.*b./2.^c");
+        assert_eq!(t, vec![
+            Token::Identifier("a".to_string()),
+            Token::ElemMult,
+            Token::Identifier("b".to_string()),
+            Token::ElemDiv,
+            Token::Number("2".to_string()),
+            Token::ElemPow,
+            Token::Identifier("c".to_string()),
+        ]);
+    }
+    #[test]
+    fn comparisons() {
+        let t = tokenize("a>=-1 and b==2 or not c!=d");
+        assert_eq!(t, vec![
+            Token::Identifier("a".to_string()),
+            Token::GreaterEqual,
+            Token::Minus,
+            Token::Number("1".to_string()),
+            Token::And,
+            Token::Identifier("b".to_string()),
+            Token::Equal,
+            Token::Number("2".to_string()),
+            Token::Or,
+            Token::Not,
+            Token::Identifier("c".to_string()),
+            Token::NotEqual,
+            Token::Identifier("d".to_string()),
+        ]);
+    }
+    #[test]
+    fn lambda_arrows() {
+        let t = tokenize("(a,b)->-a --c");
+        assert_eq!(t, vec![
+            Token::OpenParen,
+            Token::Identifier("a".to_string()),
+            Token::Comma,
+            Token::Identifier("b".to_string()),
+            Token::CloseParen,
+            Token::Arrow,
+            Token::Minus,
+            Token::Identifier("a".to_string()),
+            Token::Comment("--c".to_string()),
+        ]);
+    }
+    #[test]
+    fn spans() {
+        let spans: Vec<_> = tokenize_spanned("ab + 1.5.*x --c").into_iter().map(|t| (t.span.start, t.span.end)).collect();
+        assert_eq!(spans, vec![(0, 2), (3, 4), (5, 8), (8, 10), (10, 11), (12, 15)]);
+    }
+    #[test]
     fn operator_mashing() {
         let t = tokenize("3^-1=>-a");
         assert_eq!(t, vec![
@@ -0,0 +1,38 @@
+// runs the lcalc binary the way scripts would and checks what it exits with
+
+use std::{env, fs, process::{Command, Output}};
+
+fn lcalc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lcalc"))
+        .arg("-r")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn failed_commands_exit_with_failure() {
+    for line in ["load nope", "config trig blah", "show bogus", "restore lcalc_cli_test_missing", "save"] {
+        let output = lcalc(&["-e", line]);
+        assert!(!output.status.success(), "{line} exited with {}", output.status);
+        assert!(stderr(&output).starts_with("error in -e 1: "), "{line} gave {}", stderr(&output));
+    }
+    let output = lcalc(&["-e", "config trig deg", "-e", "std.sin(90)"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+}
+
+#[test]
+fn file_lines_are_numbered_apart_from_expressions() {
+    let path = env::temp_dir().join(format!("lcalc_cli_test_{}.txt", std::process::id()));
+    fs::write(&path, "a = 2\nb = a + y\n").unwrap();
+    let output = lcalc(&["-e", "1", "-e", "2", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).starts_with(&format!("error on line 2 of {}: unknown identifier: y", path.display())), "{}", stderr(&output));
+}
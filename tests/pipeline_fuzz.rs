@@ -0,0 +1,165 @@
+// feeds random lines through tokenize -> generate_syntax_tree -> Context::execute and
+// fails on the first one that panics, run longer with LCALC_FUZZ_ITERS=1000000
+
+use std::{panic, thread};
+
+use full_calc::{
+    app::{builtin_functions::FUNCTIONS, state::Context},
+    parser::{highlighting::get_highlight_tokens, syntax_tree::generate_syntax_tree, tokens::tokenize_spanned},
+};
+
+const DEFAULT_ITERS: usize = 20_000;
+// same as a normal main thread so stack use is checked too
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+const NUMBERS: &[&str] = &[
+    "0", "1", "2", "3", "0.5", ".5", "2.", "1e3", "1e308", "1e400", "-1", "1i", "2.5j", "0i", "1.2.3", "1e", "9999999999",
+];
+const IDENTIFIERS: &[&str] = &["x", "y", "A", "B", "ans", "end", "i", "j", "inf", "nan", "std", "std.", "if", "def", "clear", "config"];
+const BINARY_OPS: &[&str] = &["==", "!=", "<", "<=", ">", ">=", "and", "or", "+", "-", "*", "/", "\\", ".*", "./", ".^", "^"];
+const SYMBOLS: &[&str] = &[
+    "(", ")", "[", "]", ",", ";", ":", "=", "=>", "==", "!=", "<", "<=", ">", ">=", "and", "or", "not",
+    "+", "-", "*", "/", "\\", ".*", "./", ".^", "^", "--", "!", "'", "\"", "#", "@", "~", "_", "é", "\t", "\n", "\r", " ",
+];
+
+// xorshift so runs are repeatable without pulling in a crate
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, options: &[&'a str]) -> &'a str {
+        options[self.below(options.len())]
+    }
+
+    fn function_name(&mut self) -> &'static str {
+        FUNCTIONS[self.below(FUNCTIONS.len())].0
+    }
+}
+
+// mostly well formed expressions so evaluation gets exercised and not just the parser
+fn expression(rng: &mut Rng, depth: usize) -> String {
+    let choice = match depth {
+        0 => rng.below(3),
+        _ => rng.below(13),
+    };
+    let sub = |rng: &mut Rng| expression(rng, depth - 1);
+    match choice {
+        0 => rng.pick(NUMBERS).to_string(),
+        1 => rng.pick(IDENTIFIERS).to_string(),
+        2 => "[]".to_string(),
+        3 => {
+            let (rows, cols) = (rng.below(4), 1 + rng.below(3));
+            let rows: Vec<String> = (0..rows)
+                .map(|_| (0..cols).map(|_| sub(rng)).collect::<Vec<_>>().join(", "))
+                .collect();
+            format!("[{}]", rows.join("; "))
+        },
+        4 | 5 => {
+            let op = rng.pick(BINARY_OPS);
+            format!("{} {op} {}", sub(rng), sub(rng))
+        },
+        6 => format!("{}{}", rng.pick(&["-", "not ", "--"]), sub(rng)),
+        7 => {
+            let fname = rng.function_name();
+            let args: Vec<String> = (0..rng.below(4)).map(|_| sub(rng)).collect();
+            format!("{fname}({})", args.join(", "))
+        },
+        8 => match rng.below(2) {
+            0 => format!("{}:{}", sub(rng), sub(rng)),
+            _ => format!("{}:{}:{}", sub(rng), sub(rng), sub(rng)),
+        },
+        9 => {
+            let args: Vec<String> = (0..rng.below(4))
+                .map(|_| match rng.below(3) {
+                    0 => ":".to_string(),
+                    1 => "end".to_string(),
+                    _ => sub(rng),
+                })
+                .collect();
+            format!("{}[{}]", sub(rng), args.join(", "))
+        },
+        10 => format!("if({}, {}, {})", sub(rng), sub(rng), sub(rng)),
+        11 => format!("({})", sub(rng)),
+        _ => format!("{} = {}", rng.pick(&["x", "y", "A", "A[1]", "A[end, :]", "B[2, 2]"]), sub(rng)),
+    }
+}
+
+// anything goes, including unbalanced brackets and characters the tokenizer doesn't know
+fn garbage(rng: &mut Rng) -> String {
+    let mut line = String::new();
+    for _ in 0..1 + rng.below(20) {
+        let fragment = match rng.below(4) {
+            0 => rng.pick(NUMBERS),
+            1 => rng.pick(IDENTIFIERS),
+            2 => rng.function_name(),
+            _ => rng.pick(SYMBOLS),
+        };
+        line.push_str(fragment);
+        if rng.below(3) == 0 {
+            line.push(' ');
+        }
+    }
+    line
+}
+
+// error spans also have to land on the line so the caret underline can be drawn
+fn run_line(context: &mut Context, line: &str) {
+    get_highlight_tokens(line);
+    let span = match generate_syntax_tree(tokenize_spanned(line)) {
+        Ok(tree) => match context.execute(tree) {
+            Ok(_) => None,
+            Err(e) => e.span(),
+        },
+        Err(e) => Some(e.span()),
+    };
+    if let Some(span) = span {
+        assert!(line.get(span.start..span.end).is_some(), "bad span {span:?} for {line:?}");
+    }
+}
+
+#[test]
+fn random_lines_never_panic() {
+    let iters = std::env::var("LCALC_FUZZ_ITERS")
+        .ok()
+        .and_then(|iters| iters.parse().ok())
+        .unwrap_or(DEFAULT_ITERS);
+
+    let fuzz_thread = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        let mut rng = Rng(0x2545F4914F6CDD1D);
+        let mut context = Context::default();
+        for _ in 0..iters {
+            let depth = 1 + rng.below(4);
+            let line = match rng.below(2) {
+                0 => expression(&mut rng, depth),
+                _ => garbage(&mut rng),
+            };
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| run_line(&mut context, &line)));
+            assert!(result.is_ok(), "panicked on input: {line:?}");
+        }
+    });
+    fuzz_thread.unwrap().join().unwrap();
+}
+
+#[test]
+fn deep_nesting_never_overflows() {
+    let fuzz_thread = thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+        let mut context = Context::default();
+        for depth in [10, 100, 1000, 100_000] {
+            for (open, close) in [("(", ")"), ("[", "]"), ("-", ""), ("not ", ""), ("std.abs(", ")"), ("x[", "]")] {
+                let line = format!("{}1{}", open.repeat(depth), close.repeat(depth));
+                run_line(&mut context, &line);
+            }
+        }
+    });
+    fuzz_thread.unwrap().join().unwrap();
+}